@@ -1,6 +1,6 @@
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum CatppuccinFlavor {
     Latte,
     Frappe,
@@ -8,7 +8,7 @@ pub enum CatppuccinFlavor {
     Mocha,
 }
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum AccentColor {
     Rosewater,
     Flamingo,
@@ -59,6 +59,22 @@ impl Theme {
 
 // Helper to get hex codes for Catppuccin Mocha (as an example)
 impl CatppuccinFlavor {
+    pub const ALL: [Self; 4] = [Self::Latte, Self::Frappe, Self::Macchiato, Self::Mocha];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Latte => "Latte",
+            Self::Frappe => "Frappé",
+            Self::Macchiato => "Macchiato",
+            Self::Mocha => "Mocha",
+        }
+    }
+
+    /// Latte is the only light flavor.
+    pub fn is_light(&self) -> bool {
+        matches!(self, Self::Latte)
+    }
+
     pub fn base(&self) -> u32 {
         match self {
             Self::Mocha => 0x1e1e2e,
@@ -85,4 +101,61 @@ impl CatppuccinFlavor {
             Self::Latte => 0x4c4f69,
         }
     }
+
+    pub fn accent(&self, accent: AccentColor) -> u32 {
+        let palette: [u32; 14] = match self {
+            Self::Mocha => [
+                0xf5e0dc, 0xf2cdcd, 0xf5c2e7, 0xcba6f7, 0xf38ba8, 0xeba0ac, 0xfab387, 0xf9e2af, 0xa6e3a1, 0x94e2d5, 0x89dceb, 0x74c7ec, 0x89b4fa, 0xb4befe,
+            ],
+            Self::Macchiato => [
+                0xf4dbd6, 0xf0c6c6, 0xf5bde6, 0xc6a0f6, 0xed8796, 0xee99a0, 0xf5a97f, 0xeed49f, 0xa6da95, 0x8bd5ca, 0x91d7e3, 0x7dc4e4, 0x8aadf4, 0xb7bdf8,
+            ],
+            Self::Frappe => [
+                0xf2d5cf, 0xeebebe, 0xf4b8e4, 0xca9ee6, 0xe78284, 0xea999c, 0xef9f76, 0xe5c890, 0xa6d189, 0x81c8be, 0x99d1db, 0x85c1dc, 0x8caaee, 0xbabbf1,
+            ],
+            Self::Latte => [
+                0xdc8a78, 0xdd7878, 0xea76cb, 0x8839ef, 0xd20f39, 0xe64553, 0xfe640b, 0xdf8e1d, 0x40a02b, 0x179299, 0x04a5e5, 0x209fb5, 0x1e66f5, 0x7287fd,
+            ],
+        };
+        // In `AccentColor` order
+        palette[accent as usize]
+    }
+}
+
+impl AccentColor {
+    pub const ALL: [Self; 14] = [
+        Self::Rosewater,
+        Self::Flamingo,
+        Self::Pink,
+        Self::Mauve,
+        Self::Red,
+        Self::Maroon,
+        Self::Peach,
+        Self::Yellow,
+        Self::Green,
+        Self::Teal,
+        Self::Sky,
+        Self::Sapphire,
+        Self::Blue,
+        Self::Lavender,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Rosewater => "Rosewater",
+            Self::Flamingo => "Flamingo",
+            Self::Pink => "Pink",
+            Self::Mauve => "Mauve",
+            Self::Red => "Red",
+            Self::Maroon => "Maroon",
+            Self::Peach => "Peach",
+            Self::Yellow => "Yellow",
+            Self::Green => "Green",
+            Self::Teal => "Teal",
+            Self::Sky => "Sky",
+            Self::Sapphire => "Sapphire",
+            Self::Blue => "Blue",
+            Self::Lavender => "Lavender",
+        }
+    }
 }
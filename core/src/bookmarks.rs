@@ -0,0 +1,541 @@
+//! Bookmarks: a tree of folders and bookmarks, with tags and keywords.
+//!
+//! A keyword is a shortcut typed into the omnibox: `w rust` opens the bookmark with keyword
+//! `w`, its `%s` replaced by the rest of the input. Keywords are unique; tags aren't.
+//!
+//! Everything lives in one `bookmarks.json` in the profile directory, saved the same way as the
+//! spaces file. Bookmarks come in from Netscape HTML files (what every browser exports) and
+//! from Firefox and Chromium JSON, and go out as Netscape HTML.
+
+pub mod browser_json;
+pub mod netscape;
+
+use crate::json_store::{JsonStore, JsonStoreError};
+use crate::spaces::unix_now;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Version written by this build.
+pub const SCHEMA_VERSION: u64 = 1;
+
+const FILE_NAME: &str = "bookmarks.json";
+
+/// ID of the folder everything else sits in.
+pub const ROOT_ID: &str = "root";
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Bookmark {
+    pub id: String,
+    pub title: String,
+    pub url: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub keyword: Option<String>,
+    /// URL of the page's icon
+    #[serde(default)]
+    pub favicon: Option<String>,
+    /// Unix time (seconds)
+    pub added: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Folder {
+    pub id: String,
+    pub title: String,
+    pub children: Vec<BookmarkNode>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum BookmarkNode {
+    Bookmark(Bookmark),
+    Folder(Folder),
+}
+
+impl BookmarkNode {
+    pub fn id(&self) -> &str {
+        match self {
+            BookmarkNode::Bookmark(bookmark) => &bookmark.id,
+            BookmarkNode::Folder(folder) => &folder.id,
+        }
+    }
+
+    pub fn title(&self) -> &str {
+        match self {
+            BookmarkNode::Bookmark(bookmark) => &bookmark.title,
+            BookmarkNode::Folder(folder) => &folder.title,
+        }
+    }
+}
+
+impl Bookmark {
+    pub fn new(title: &str, url: &str) -> Self {
+        Self {
+            id: new_id(),
+            title: title.to_string(),
+            url: url.to_string(),
+            tags: Vec::new(),
+            keyword: None,
+            favicon: None,
+            added: unix_now(),
+        }
+    }
+}
+
+impl Folder {
+    pub fn new(title: &str) -> Self {
+        Self {
+            id: new_id(),
+            title: title.to_string(),
+            children: Vec::new(),
+        }
+    }
+
+    /// Bookmarks in this folder and all folders below it, in order.
+    pub fn bookmarks(&self) -> Vec<&Bookmark> {
+        let mut bookmarks = Vec::new();
+        collect_bookmarks(self, &mut bookmarks);
+        bookmarks
+    }
+
+    fn find(&self, id: &str) -> Option<&BookmarkNode> {
+        self.children.iter().find_map(|child| match child {
+            _ if child.id() == id => Some(child),
+            BookmarkNode::Folder(folder) => folder.find(id),
+            BookmarkNode::Bookmark(_) => None,
+        })
+    }
+
+    fn find_mut(&mut self, id: &str) -> Option<&mut BookmarkNode> {
+        let index = self.children.iter().position(|child| child.id() == id);
+        if let Some(index) = index {
+            return self.children.get_mut(index);
+        }
+        self.children.iter_mut().find_map(|child| match child {
+            BookmarkNode::Folder(folder) => folder.find_mut(id),
+            BookmarkNode::Bookmark(_) => None,
+        })
+    }
+
+    fn folder_mut(&mut self, id: &str) -> Option<&mut Folder> {
+        if self.id == id {
+            return Some(self);
+        }
+        match self.find_mut(id)? {
+            BookmarkNode::Folder(folder) => Some(folder),
+            BookmarkNode::Bookmark(_) => None,
+        }
+    }
+
+    fn remove(&mut self, id: &str) -> Option<BookmarkNode> {
+        if let Some(index) = self.children.iter().position(|child| child.id() == id) {
+            return Some(self.children.remove(index));
+        }
+        self.children.iter_mut().find_map(|child| match child {
+            BookmarkNode::Folder(folder) => folder.remove(id),
+            BookmarkNode::Bookmark(_) => None,
+        })
+    }
+
+    fn bookmarks_mut(&mut self) -> Vec<&mut Bookmark> {
+        self.children
+            .iter_mut()
+            .flat_map(|child| match child {
+                BookmarkNode::Bookmark(bookmark) => vec![bookmark],
+                BookmarkNode::Folder(folder) => folder.bookmarks_mut(),
+            })
+            .collect()
+    }
+}
+
+fn collect_bookmarks<'a>(folder: &'a Folder, bookmarks: &mut Vec<&'a Bookmark>) {
+    for child in &folder.children {
+        match child {
+            BookmarkNode::Bookmark(bookmark) => bookmarks.push(bookmark),
+            BookmarkNode::Folder(folder) => collect_bookmarks(folder, bookmarks),
+        }
+    }
+}
+
+fn new_id() -> String {
+    uuid::Uuid::new_v4().to_string()
+}
+
+#[derive(Debug)]
+pub enum BookmarkError {
+    Io(io::Error),
+    Parse(serde_json::Error),
+    /// The file's schema version, newer than [`SCHEMA_VERSION`]
+    UnsupportedVersion(u64),
+    /// No folder has this ID
+    UnknownFolder(String),
+    /// Nothing has this ID
+    UnknownBookmark(String),
+    /// Another bookmark already answers to this keyword
+    KeywordTaken(String),
+    /// A folder can't go inside itself
+    FolderCycle,
+    /// The file isn't a bookmarks export this build can read
+    UnrecognizedFormat,
+}
+
+impl fmt::Display for BookmarkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BookmarkError::Io(e) => write!(f, "I/O error: {}", e),
+            BookmarkError::Parse(e) => write!(f, "malformed bookmarks file: {}", e),
+            BookmarkError::UnsupportedVersion(version) => {
+                write!(f, "bookmarks file has schema version {}, newer than the supported {}", version, SCHEMA_VERSION)
+            }
+            BookmarkError::UnknownFolder(id) => write!(f, "no folder with ID {}", id),
+            BookmarkError::UnknownBookmark(id) => write!(f, "no bookmark with ID {}", id),
+            BookmarkError::KeywordTaken(keyword) => write!(f, "keyword {} is already in use", keyword),
+            BookmarkError::FolderCycle => write!(f, "a folder can't be moved into itself"),
+            BookmarkError::UnrecognizedFormat => write!(f, "not a bookmarks file"),
+        }
+    }
+}
+
+impl std::error::Error for BookmarkError {}
+
+impl From<io::Error> for BookmarkError {
+    fn from(e: io::Error) -> Self {
+        BookmarkError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for BookmarkError {
+    fn from(e: serde_json::Error) -> Self {
+        BookmarkError::Parse(e)
+    }
+}
+
+impl From<JsonStoreError> for BookmarkError {
+    fn from(e: JsonStoreError) -> Self {
+        match e {
+            JsonStoreError::Io(e) => BookmarkError::Io(e),
+            JsonStoreError::Parse(e) => BookmarkError::Parse(e),
+            JsonStoreError::UnsupportedVersion(version) => BookmarkError::UnsupportedVersion(version),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Bookmarks {
+    root: Folder,
+}
+
+impl Default for Bookmarks {
+    fn default() -> Self {
+        Self {
+            root: Folder {
+                id: ROOT_ID.to_string(),
+                title: String::new(),
+                children: Vec::new(),
+            },
+        }
+    }
+}
+
+impl Bookmarks {
+    pub fn root(&self) -> &Folder {
+        &self.root
+    }
+
+    pub fn get(&self, id: &str) -> Option<&BookmarkNode> {
+        self.root.find(id)
+    }
+
+    pub fn bookmark(&self, id: &str) -> Option<&Bookmark> {
+        match self.get(id)? {
+            BookmarkNode::Bookmark(bookmark) => Some(bookmark),
+            BookmarkNode::Folder(_) => None,
+        }
+    }
+
+    /// Adds a bookmark at the end of a folder. Returns its ID.
+    pub fn add(&mut self, folder_id: &str, mut bookmark: Bookmark) -> Result<String, BookmarkError> {
+        bookmark.keyword = bookmark.keyword.as_deref().and_then(normalize_keyword);
+        if let Some(keyword) = &bookmark.keyword {
+            self.check_keyword(keyword, &bookmark.id)?;
+        }
+        let id = bookmark.id.clone();
+        self.folder_mut(folder_id)?.children.push(BookmarkNode::Bookmark(bookmark));
+        Ok(id)
+    }
+
+    /// Adds an empty folder at the end of a folder. Returns its ID.
+    pub fn add_folder(&mut self, parent_id: &str, title: &str) -> Result<String, BookmarkError> {
+        let folder = Folder::new(title);
+        let id = folder.id.clone();
+        self.folder_mut(parent_id)?.children.push(BookmarkNode::Folder(folder));
+        Ok(id)
+    }
+
+    /// Removes a bookmark, or a folder with everything in it.
+    pub fn remove(&mut self, id: &str) -> Option<BookmarkNode> {
+        self.root.remove(id)
+    }
+
+    /// Moves a bookmark or folder to the end of another folder.
+    pub fn move_to(&mut self, id: &str, folder_id: &str) -> Result<(), BookmarkError> {
+        let node = self.get(id).ok_or_else(|| BookmarkError::UnknownBookmark(id.to_string()))?;
+        if let BookmarkNode::Folder(folder) = node
+            && (folder.id == folder_id || folder.find(folder_id).is_some())
+        {
+            return Err(BookmarkError::FolderCycle);
+        }
+        self.folder_mut(folder_id)?;
+        let node = self.root.remove(id).ok_or_else(|| BookmarkError::UnknownBookmark(id.to_string()))?;
+        self.folder_mut(folder_id)?.children.push(node);
+        Ok(())
+    }
+
+    pub fn rename(&mut self, id: &str, title: &str) -> Result<(), BookmarkError> {
+        match self.root.find_mut(id) {
+            Some(BookmarkNode::Bookmark(bookmark)) => bookmark.title = title.to_string(),
+            Some(BookmarkNode::Folder(folder)) => folder.title = title.to_string(),
+            None => return Err(BookmarkError::UnknownBookmark(id.to_string())),
+        }
+        Ok(())
+    }
+
+    pub fn set_url(&mut self, id: &str, url: &str) -> Result<(), BookmarkError> {
+        self.bookmark_mut(id)?.url = url.to_string();
+        Ok(())
+    }
+
+    /// Sets or clears a bookmark's keyword. Keywords are trimmed and lowercased.
+    pub fn set_keyword(&mut self, id: &str, keyword: Option<&str>) -> Result<(), BookmarkError> {
+        let keyword = keyword.and_then(normalize_keyword);
+        if let Some(keyword) = &keyword {
+            self.check_keyword(keyword, id)?;
+        }
+        self.bookmark_mut(id)?.keyword = keyword;
+        Ok(())
+    }
+
+    /// Replaces a bookmark's tags; blank and repeated tags are dropped.
+    pub fn set_tags(&mut self, id: &str, tags: &[&str]) -> Result<(), BookmarkError> {
+        let mut seen = BTreeSet::new();
+        let tags = tags.iter().map(|tag| tag.trim()).filter(|tag| !tag.is_empty() && seen.insert(tag.to_lowercase())).map(str::to_string).collect();
+        self.bookmark_mut(id)?.tags = tags;
+        Ok(())
+    }
+
+    /// Remembers a page's icon on every bookmark of it.
+    pub fn set_favicon(&mut self, url: &str, favicon: &str) -> bool {
+        let mut changed = false;
+        for bookmark in self.root.bookmarks_mut().into_iter().filter(|bookmark| bookmark.url == url) {
+            if bookmark.favicon.as_deref() != Some(favicon) {
+                bookmark.favicon = Some(favicon.to_string());
+                changed = true;
+            }
+        }
+        changed
+    }
+
+    /// The first bookmark of `url`, if it is bookmarked.
+    pub fn find_url(&self, url: &str) -> Option<&Bookmark> {
+        self.root.bookmarks().into_iter().find(|bookmark| bookmark.url == url)
+    }
+
+    pub fn with_tag(&self, tag: &str) -> Vec<&Bookmark> {
+        self.root.bookmarks().into_iter().filter(|bookmark| bookmark.tags.iter().any(|own| own.eq_ignore_ascii_case(tag))).collect()
+    }
+
+    /// Every tag in use, sorted.
+    pub fn tags(&self) -> BTreeSet<&str> {
+        self.root.bookmarks().into_iter().flat_map(|bookmark| bookmark.tags.iter().map(String::as_str)).collect()
+    }
+
+    /// Bookmarks whose title, URL or tags contain every word of `query`, ignoring case.
+    pub fn search(&self, query: &str) -> Vec<&Bookmark> {
+        let words: Vec<String> = query.split_whitespace().map(str::to_lowercase).collect();
+        if words.is_empty() {
+            return Vec::new();
+        }
+        self.root
+            .bookmarks()
+            .into_iter()
+            .filter(|bookmark| {
+                let haystack = format!("{} {} {}", bookmark.title, bookmark.url, bookmark.tags.join(" ")).to_lowercase();
+                words.iter().all(|word| haystack.contains(word))
+            })
+            .collect()
+    }
+
+    /// Turns omnibox input starting with a keyword into the URL to open: `%s` in the bookmark's
+    /// URL is replaced by the rest of the input, percent-encoded. `None` if the first word
+    /// isn't a keyword.
+    pub fn expand_keyword(&self, input: &str) -> Option<String> {
+        let input = input.trim();
+        let (keyword, rest) = input.split_once(char::is_whitespace).unwrap_or((input, ""));
+        let keyword = keyword.to_lowercase();
+        let bookmark = self.root.bookmarks().into_iter().find(|bookmark| bookmark.keyword.as_deref() == Some(keyword.as_str()))?;
+        let terms: String = url::form_urlencoded::byte_serialize(rest.trim().as_bytes()).collect();
+        Some(bookmark.url.replace("%s", &terms))
+    }
+
+    /// Adds what an import produced as a new folder at the end of the root. Keywords another
+    /// bookmark already has are dropped from the imported ones. Returns the folder's ID.
+    pub fn import(&mut self, mut folder: Folder) -> String {
+        let mut taken: BTreeSet<String> = self.root.bookmarks().into_iter().filter_map(|bookmark| bookmark.keyword.clone()).collect();
+        for bookmark in folder.bookmarks_mut() {
+            if let Some(keyword) = bookmark.keyword.take().as_deref().and_then(normalize_keyword)
+                && taken.insert(keyword.clone())
+            {
+                bookmark.keyword = Some(keyword);
+            }
+        }
+        let id = folder.id.clone();
+        self.root.children.push(BookmarkNode::Folder(folder));
+        id
+    }
+
+    fn folder_mut(&mut self, id: &str) -> Result<&mut Folder, BookmarkError> {
+        self.root.folder_mut(id).ok_or_else(|| BookmarkError::UnknownFolder(id.to_string()))
+    }
+
+    fn bookmark_mut(&mut self, id: &str) -> Result<&mut Bookmark, BookmarkError> {
+        match self.root.find_mut(id) {
+            Some(BookmarkNode::Bookmark(bookmark)) => Ok(bookmark),
+            _ => Err(BookmarkError::UnknownBookmark(id.to_string())),
+        }
+    }
+
+    fn check_keyword(&self, keyword: &str, owner: &str) -> Result<(), BookmarkError> {
+        let taken = self.root.bookmarks().into_iter().any(|bookmark| bookmark.id != owner && bookmark.keyword.as_deref() == Some(keyword));
+        if taken {
+            return Err(BookmarkError::KeywordTaken(keyword.to_string()));
+        }
+        Ok(())
+    }
+}
+
+pub struct BookmarksStore {
+    store: JsonStore,
+}
+
+impl BookmarksStore {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { store: JsonStore::new(path.into(), SCHEMA_VERSION) }
+    }
+
+    /// The store inside a profile directory.
+    pub fn in_profile(profile_dir: &Path) -> Self {
+        Self::new(profile_dir.join(FILE_NAME))
+    }
+
+    pub fn path(&self) -> &Path {
+        self.store.path()
+    }
+
+    /// The saved bookmarks; none for a profile that never saved any.
+    pub fn load(&self) -> Result<Bookmarks, BookmarkError> {
+        Ok(self.store.load()?)
+    }
+
+    /// Loads the saved bookmarks, falling back to none.
+    pub fn load_or_default(&self) -> Bookmarks {
+        self.store.load_or_default(BookmarkError::from)
+    }
+
+    pub fn save(&self, bookmarks: &Bookmarks) -> Result<(), BookmarkError> {
+        Ok(self.store.save(bookmarks)?)
+    }
+}
+
+/// Reads a bookmarks export of any supported format into a folder named `title`.
+pub fn import_file(path: &Path, title: &str) -> Result<Folder, BookmarkError> {
+    let data = fs::read_to_string(path)?;
+    let mut folder = if data.trim_start().starts_with('{') {
+        browser_json::parse(&data)?
+    } else {
+        netscape::parse(&data).ok_or(BookmarkError::UnrecognizedFormat)?
+    };
+    folder.title = title.to_string();
+    Ok(folder)
+}
+
+/// Keywords are matched trimmed and lowercased; a blank one is no keyword.
+fn normalize_keyword(keyword: &str) -> Option<String> {
+    Some(keyword.trim().to_lowercase()).filter(|keyword| !keyword.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_folders_tags_and_keywords() {
+        let mut bookmarks = Bookmarks::default();
+        let work = bookmarks.add_folder(ROOT_ID, "Work").unwrap();
+        let docs = bookmarks.add(&work, Bookmark::new("Rust docs", "https://doc.rust-lang.org/std/?search=%s")).unwrap();
+        let news = bookmarks.add(ROOT_ID, Bookmark::new("News", "https://news.test")).unwrap();
+
+        bookmarks.set_keyword(&docs, Some(" RS ")).unwrap();
+        assert!(matches!(bookmarks.set_keyword(&news, Some("rs")), Err(BookmarkError::KeywordTaken(_))));
+        assert_eq!(bookmarks.expand_keyword("rs Vec push").as_deref(), Some("https://doc.rust-lang.org/std/?search=Vec+push"));
+        assert_eq!(bookmarks.expand_keyword("news"), None);
+
+        let mut wiki = Bookmark::new("Wiki", "https://wiki.test/?q=%s");
+        wiki.keyword = Some(" W ".into());
+        let wiki = bookmarks.add(ROOT_ID, wiki).unwrap();
+        assert_eq!(bookmarks.bookmark(&wiki).unwrap().keyword.as_deref(), Some("w"));
+        assert_eq!(bookmarks.expand_keyword("W tea").as_deref(), Some("https://wiki.test/?q=tea"));
+        let mut taken = Bookmark::new("Other wiki", "https://other.test/?q=%s");
+        taken.keyword = Some("w".into());
+        assert!(matches!(bookmarks.add(ROOT_ID, taken), Err(BookmarkError::KeywordTaken(_))));
+        bookmarks.remove(&wiki);
+
+        bookmarks.set_tags(&docs, &["rust", " Rust", "reference", ""]).unwrap();
+        bookmarks.set_tags(&news, &["daily"]).unwrap();
+        assert_eq!(bookmarks.bookmark(&docs).unwrap().tags, vec!["rust", "reference"]);
+        assert_eq!(bookmarks.tags().into_iter().collect::<Vec<_>>(), vec!["daily", "reference", "rust"]);
+        assert_eq!(bookmarks.with_tag("RUST").len(), 1);
+        assert_eq!(bookmarks.search("rust ref").iter().map(|bookmark| bookmark.id.as_str()).collect::<Vec<_>>(), vec![docs.as_str()]);
+
+        assert!(bookmarks.set_favicon("https://news.test", "https://news.test/icon.png"));
+        assert!(!bookmarks.set_favicon("https://news.test", "https://news.test/icon.png"));
+
+        bookmarks.move_to(&news, &work).unwrap();
+        assert!(matches!(bookmarks.move_to(&work, &work), Err(BookmarkError::FolderCycle)));
+        assert_eq!(bookmarks.root().children.len(), 1);
+        assert_eq!(bookmarks.remove(&work).map(|node| node.title().to_string()), Some("Work".to_string()));
+        assert_eq!(bookmarks.find_url("https://news.test"), None);
+    }
+
+    #[test]
+    fn test_store_round_trip_and_import_keeps_keywords_unique() {
+        let dir = std::env::temp_dir().join(format!("wave-bookmarks-{}", uuid::Uuid::new_v4()));
+        let store = BookmarksStore::in_profile(&dir);
+        assert_eq!(store.load().unwrap(), Bookmarks::default());
+
+        let mut bookmarks = Bookmarks::default();
+        let mut wiki = Bookmark::new("Wikipedia", "https://en.wikipedia.org/wiki/%s");
+        wiki.keyword = Some("w".into());
+        bookmarks.add(ROOT_ID, wiki.clone()).unwrap();
+
+        let mut imported = Folder::new("Imported");
+        wiki.id = new_id();
+        imported.children.push(BookmarkNode::Bookmark(wiki));
+        bookmarks.import(imported);
+        assert_eq!(bookmarks.root().bookmarks().iter().filter(|bookmark| bookmark.keyword.is_some()).count(), 1);
+
+        store.save(&bookmarks).unwrap();
+        assert_eq!(store.load().unwrap(), bookmarks);
+
+        // Bookmarks from a newer build survive a run of this one
+        fs::write(store.path(), r#"{ "version": 2, "root": {} }"#).unwrap();
+        let store = BookmarksStore::in_profile(&dir);
+        assert_eq!(store.load_or_default(), Bookmarks::default());
+        assert!(matches!(store.save(&bookmarks), Err(BookmarkError::UnsupportedVersion(2))));
+        assert!(fs::read_to_string(store.path()).unwrap().contains(r#""version": 2"#));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,137 @@
+//! Bookmarks from the JSON other browsers keep: Firefox backups (`bookmarks-*.json`) and
+//! Chromium's `Bookmarks` file.
+
+use super::{Bookmark, BookmarkError, BookmarkNode, Folder};
+use serde_json::Value;
+
+/// Seconds between 1601-01-01, where Chromium counts from, and the Unix epoch.
+const WINDOWS_EPOCH_OFFSET: u64 = 11_644_473_600;
+
+/// Reads either format; which one is told by its top level.
+pub fn parse(data: &str) -> Result<Folder, BookmarkError> {
+    let document: Value = serde_json::from_str(data)?;
+    if document.get("roots").is_some() {
+        Ok(from_chromium(&document))
+    } else if document["type"] == "text/x-moz-place-container" {
+        Ok(from_firefox(&document))
+    } else {
+        Err(BookmarkError::UnrecognizedFormat)
+    }
+}
+
+fn from_firefox(node: &Value) -> Folder {
+    let mut folder = Folder::new(firefox_title(node));
+    for child in node["children"].as_array().into_iter().flatten() {
+        match child["type"].as_str() {
+            Some("text/x-moz-place-container") => folder.children.push(BookmarkNode::Folder(from_firefox(child))),
+            Some("text/x-moz-place") => {
+                let Some(url) = child["uri"].as_str() else {
+                    continue;
+                };
+                // Saved searches and other `place:` queries aren't pages
+                if url.starts_with("place:") {
+                    continue;
+                }
+                let mut bookmark = Bookmark::new(child["title"].as_str().unwrap_or(url), url);
+                if let Some(added) = child["dateAdded"].as_u64() {
+                    bookmark.added = added / 1_000_000;
+                }
+                bookmark.keyword = child["keyword"].as_str().map(str::to_lowercase);
+                bookmark.tags = child["tags"].as_str().map(|tags| tags.split(',').map(str::trim).filter(|tag| !tag.is_empty()).map(str::to_string).collect()).unwrap_or_default();
+                bookmark.favicon = child["iconUri"].as_str().map(str::to_string);
+                folder.children.push(BookmarkNode::Bookmark(bookmark));
+            }
+            // Separators
+            _ => {}
+        }
+    }
+    folder
+}
+
+/// Firefox names its built-in folders by role; the backup has the role, not the name.
+fn firefox_title(node: &Value) -> &str {
+    match node["root"].as_str() {
+        Some("toolbarFolder") => "Bookmarks Toolbar",
+        Some("bookmarksMenuFolder") => "Bookmarks Menu",
+        Some("unfiledBookmarksFolder") => "Other Bookmarks",
+        Some("mobileFolder") => "Mobile Bookmarks",
+        _ => node["title"].as_str().unwrap_or_default(),
+    }
+}
+
+fn from_chromium(document: &Value) -> Folder {
+    let mut folder = Folder::new("");
+    for root in ["bookmark_bar", "other", "synced"] {
+        let node = &document["roots"][root];
+        if node.is_object() {
+            folder.children.push(chromium_node(node));
+        }
+    }
+    folder
+}
+
+fn chromium_node(node: &Value) -> BookmarkNode {
+    let title = node["name"].as_str().unwrap_or_default();
+    if node["type"] == "url" {
+        let url = node["url"].as_str().unwrap_or_default();
+        let mut bookmark = Bookmark::new(title, url);
+        // Microseconds since 1601, as a string
+        if let Some(added) = node["date_added"].as_str().and_then(|date| date.parse::<u64>().ok()) {
+            bookmark.added = (added / 1_000_000).saturating_sub(WINDOWS_EPOCH_OFFSET);
+        }
+        return BookmarkNode::Bookmark(bookmark);
+    }
+    let mut folder = Folder::new(title);
+    folder.children = node["children"].as_array().into_iter().flatten().map(chromium_node).collect();
+    BookmarkNode::Folder(folder)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_firefox_backup() {
+        let backup = r#"{
+            "guid": "root________", "title": "", "type": "text/x-moz-place-container", "root": "placesRoot",
+            "children": [
+                { "title": "toolbar", "type": "text/x-moz-place-container", "root": "toolbarFolder", "children": [
+                    { "title": "MDN", "type": "text/x-moz-place", "uri": "https://developer.mozilla.org/search?q=%s",
+                      "keyword": "MDN", "tags": "web,docs", "dateAdded": 1700000000123456, "iconUri": "https://developer.mozilla.org/favicon.ico" },
+                    { "type": "text/x-moz-place-separator" },
+                    { "title": "Most Visited", "type": "text/x-moz-place", "uri": "place:sort=8&maxResults=10" }
+                ] }
+            ]
+        }"#;
+        let root = parse(backup).unwrap();
+        let BookmarkNode::Folder(toolbar) = &root.children[0] else {
+            panic!("expected the toolbar folder");
+        };
+        assert_eq!(toolbar.title, "Bookmarks Toolbar");
+        let bookmarks = root.bookmarks();
+        assert_eq!(bookmarks.len(), 1);
+        assert_eq!((bookmarks[0].keyword.as_deref(), bookmarks[0].added), (Some("mdn"), 1700000000));
+        assert_eq!(bookmarks[0].tags, vec!["web", "docs"]);
+    }
+
+    #[test]
+    fn test_chromium_bookmarks() {
+        let file = r#"{
+            "checksum": "0", "version": 1,
+            "roots": {
+                "bookmark_bar": { "name": "Bookmarks bar", "type": "folder", "children": [
+                    { "name": "Rust", "type": "url", "url": "https://www.rust-lang.org/", "date_added": "13345678901234567" },
+                    { "name": "Work", "type": "folder", "children": [] }
+                ] },
+                "other": { "name": "Other bookmarks", "type": "folder", "children": [] }
+            }
+        }"#;
+        let root = parse(file).unwrap();
+        assert_eq!(root.children.iter().map(BookmarkNode::title).collect::<Vec<_>>(), vec!["Bookmarks bar", "Other bookmarks"]);
+        let bookmarks = root.bookmarks();
+        assert_eq!(bookmarks[0].url, "https://www.rust-lang.org/");
+        assert_eq!(bookmarks[0].added, 13345678901 - WINDOWS_EPOCH_OFFSET);
+
+        assert!(matches!(parse(r#"{ "spaces": [] }"#), Err(BookmarkError::UnrecognizedFormat)));
+    }
+}
@@ -0,0 +1,219 @@
+//! The Netscape bookmark file: the HTML every browser imports and exports.
+//!
+//! It is nested `<DL>` lists: `<DT><H3>` names a folder whose contents are the `<DL>` after it,
+//! `<DT><A HREF=...>` is a bookmark. Browsers add attributes of their own; Firefox's
+//! `SHORTCUTURL` (keyword), `TAGS` and `ICON_URI` are kept. The markup is rarely valid HTML, so
+//! this reads tags loosely and ignores anything else.
+
+use super::{Bookmark, BookmarkNode, Bookmarks, Folder};
+use std::collections::HashMap;
+
+/// The folders and bookmarks in a Netscape bookmark file, or `None` if it has no bookmark list.
+pub fn parse(html: &str) -> Option<Folder> {
+    let mut root: Option<Folder> = None;
+    let mut open: Vec<Folder> = Vec::new();
+    // A folder's heading, until its list starts
+    let mut heading: Option<Folder> = None;
+
+    let mut rest = html;
+    while let Some(start) = rest.find('<') {
+        rest = &rest[start + 1..];
+        let Some(end) = rest.find('>') else {
+            break;
+        };
+        let tag = &rest[..end];
+        rest = &rest[end + 1..];
+        let text = decode(rest[..rest.find('<').unwrap_or(rest.len())].trim());
+
+        let name = tag.split_whitespace().next().unwrap_or_default().to_ascii_uppercase();
+        // A heading followed by anything but a list is an empty folder
+        if matches!(name.as_str(), "H3" | "A" | "/DL")
+            && let Some(empty) = heading.take()
+            && let Some(parent) = open.last_mut()
+        {
+            parent.children.push(BookmarkNode::Folder(empty));
+        }
+        match name.as_str() {
+            "DL" => open.push(heading.take().unwrap_or_else(|| Folder::new(""))),
+            "/DL" => {
+                if let Some(folder) = open.pop() {
+                    close(folder, &mut open, &mut root);
+                }
+            }
+            "H3" => heading = Some(Folder::new(&text)),
+            "A" => {
+                let attributes = attributes(tag);
+                let (Some(url), Some(parent)) = (attributes.get("HREF"), open.last_mut()) else {
+                    continue;
+                };
+                let mut bookmark = Bookmark::new(&text, url);
+                if let Some(added) = attributes.get("ADD_DATE").and_then(|date| date.parse().ok()) {
+                    bookmark.added = added;
+                }
+                bookmark.keyword = attributes.get("SHORTCUTURL").filter(|keyword| !keyword.is_empty()).map(|keyword| keyword.to_lowercase());
+                bookmark.tags = attributes
+                    .get("TAGS")
+                    .map(|tags| tags.split(',').map(str::trim).filter(|tag| !tag.is_empty()).map(str::to_string).collect())
+                    .unwrap_or_default();
+                bookmark.favicon = attributes.get("ICON_URI").or_else(|| attributes.get("ICON")).cloned();
+                parent.children.push(BookmarkNode::Bookmark(bookmark));
+            }
+            _ => {}
+        }
+    }
+    // Lists left open at the end of the file
+    while let Some(folder) = open.pop() {
+        close(folder, &mut open, &mut root);
+    }
+    root
+}
+
+/// Writes all bookmarks as a Netscape bookmark file.
+pub fn export(bookmarks: &Bookmarks) -> String {
+    let mut html = String::from(concat!(
+        "<!DOCTYPE NETSCAPE-Bookmark-file-1>\n",
+        "<META HTTP-EQUIV=\"Content-Type\" CONTENT=\"text/html; charset=UTF-8\">\n",
+        "<TITLE>Bookmarks</TITLE>\n",
+        "<H1>Bookmarks</H1>\n",
+        "<DL><p>\n",
+    ));
+    write_children(&mut html, bookmarks.root(), 1);
+    html.push_str("</DL><p>\n");
+    html
+}
+
+fn write_children(html: &mut String, folder: &Folder, depth: usize) {
+    let indent = "    ".repeat(depth);
+    for child in &folder.children {
+        match child {
+            BookmarkNode::Folder(folder) => {
+                html.push_str(&format!("{}<DT><H3>{}</H3>\n{}<DL><p>\n", indent, encode(&folder.title), indent));
+                write_children(html, folder, depth + 1);
+                html.push_str(&format!("{}</DL><p>\n", indent));
+            }
+            BookmarkNode::Bookmark(bookmark) => {
+                let mut attributes = format!("HREF=\"{}\" ADD_DATE=\"{}\"", encode(&bookmark.url), bookmark.added);
+                if let Some(favicon) = &bookmark.favicon {
+                    attributes.push_str(&format!(" ICON_URI=\"{}\"", encode(favicon)));
+                }
+                if let Some(keyword) = &bookmark.keyword {
+                    attributes.push_str(&format!(" SHORTCUTURL=\"{}\"", encode(keyword)));
+                }
+                if !bookmark.tags.is_empty() {
+                    attributes.push_str(&format!(" TAGS=\"{}\"", encode(&bookmark.tags.join(","))));
+                }
+                html.push_str(&format!("{}<DT><A {}>{}</A>\n", indent, attributes, encode(&bookmark.title)));
+            }
+        }
+    }
+}
+
+/// Files the finished `folder` in the list it was opened in, or makes it the root.
+fn close(folder: Folder, open: &mut [Folder], root: &mut Option<Folder>) {
+    match (open.last_mut(), root) {
+        (Some(parent), _) => parent.children.push(BookmarkNode::Folder(folder)),
+        // Several top-level lists are read as one
+        (None, Some(root)) => root.children.extend(folder.children),
+        (None, root) => *root = Some(folder),
+    }
+}
+
+/// A tag's attributes, names in upper case.
+fn attributes(tag: &str) -> HashMap<String, String> {
+    let mut attributes = HashMap::new();
+    let mut rest = tag.split_once(char::is_whitespace).map_or("", |(_, rest)| rest);
+    while let Some(equals) = rest.find('=') {
+        let name = rest[..equals].split_whitespace().last().unwrap_or_default().to_ascii_uppercase();
+        rest = rest[equals + 1..].trim_start();
+        let value = match rest.strip_prefix('"') {
+            Some(quoted) => {
+                let close = quoted.find('"').unwrap_or(quoted.len());
+                rest = quoted.get(close + 1..).unwrap_or_default();
+                &quoted[..close]
+            }
+            None => {
+                let close = rest.find(char::is_whitespace).unwrap_or(rest.len());
+                let value = &rest[..close];
+                rest = &rest[close..];
+                value
+            }
+        };
+        attributes.insert(name, decode(value));
+    }
+    attributes
+}
+
+fn decode(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&#x27;", "'")
+        .replace("&amp;", "&")
+}
+
+fn encode(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bookmarks::ROOT_ID;
+
+    const FIREFOX_EXPORT: &str = r#"<!DOCTYPE NETSCAPE-Bookmark-file-1>
+<META HTTP-EQUIV="Content-Type" CONTENT="text/html; charset=UTF-8">
+<TITLE>Bookmarks</TITLE>
+<H1>Bookmarks Menu</H1>
+
+<DL><p>
+    <DT><H3 ADD_DATE="1700000000" LAST_MODIFIED="1700000001" PERSONAL_TOOLBAR_FOLDER="true">Bookmarks Toolbar</H3>
+    <DL><p>
+        <DT><A HREF="https://en.wikipedia.org/wiki/Special:Search?search=%s" ADD_DATE="1700000002" SHORTCUTURL="w" TAGS="reference,wiki">Wikipedia</A>
+        <DT><H3>Empty</H3>
+        <DT><H3>Tools &amp; Docs</H3>
+        <DL><p>
+            <DT><A HREF="https://docs.test/?a=1&amp;b=2" ICON_URI="https://docs.test/favicon.ico">Docs &lt;beta&gt;</A>
+        </DL><p>
+    </DL><p>
+    <HR>
+    <DT><A HREF="https://news.test/">News</A>
+</DL>
+"#;
+
+    #[test]
+    fn test_parse_firefox_export() {
+        let root = parse(FIREFOX_EXPORT).unwrap();
+        let [BookmarkNode::Folder(toolbar), BookmarkNode::Bookmark(news)] = root.children.as_slice() else {
+            panic!("unexpected tree: {:?}", root);
+        };
+        assert_eq!(toolbar.title, "Bookmarks Toolbar");
+        assert_eq!(news.url, "https://news.test/");
+        assert_eq!(toolbar.children.iter().map(BookmarkNode::title).collect::<Vec<_>>(), vec!["Wikipedia", "Empty", "Tools & Docs"]);
+
+        let bookmarks = root.bookmarks();
+        assert_eq!((bookmarks[0].keyword.as_deref(), bookmarks[0].added), (Some("w"), 1700000002));
+        assert_eq!(bookmarks[0].tags, vec!["reference", "wiki"]);
+        assert_eq!((bookmarks[1].title.as_str(), bookmarks[1].url.as_str()), ("Docs <beta>", "https://docs.test/?a=1&b=2"));
+        assert_eq!(bookmarks[1].favicon.as_deref(), Some("https://docs.test/favicon.ico"));
+
+        assert_eq!(parse("<html><body>No bookmarks</body></html>"), None);
+    }
+
+    #[test]
+    fn test_export_round_trip() {
+        let mut bookmarks = Bookmarks::default();
+        bookmarks.import(parse(FIREFOX_EXPORT).unwrap());
+        let reparsed = parse(&export(&bookmarks)).unwrap();
+
+        let strip = |folder: &Folder| -> Vec<(String, String, Option<String>, Vec<String>)> {
+            folder
+                .bookmarks()
+                .into_iter()
+                .map(|bookmark| (bookmark.title.clone(), bookmark.url.clone(), bookmark.keyword.clone(), bookmark.tags.clone()))
+                .collect()
+        };
+        assert_eq!(strip(&reparsed), strip(bookmarks.root()));
+        assert_eq!(bookmarks.root().id, ROOT_ID);
+    }
+}
@@ -0,0 +1,168 @@
+//! Cookie access policy.
+//!
+//! Decides whether a request or an embedded document may read and write cookies (and other
+//! partitionable storage) in its top-level context. First-party access is always allowed;
+//! third-party access follows [`ThirdPartyCookiePolicy`], with per-pair exceptions granted by
+//! the user through the Storage Access API prompt.
+
+use crate::shield::site_of;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use url::Url;
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum ThirdPartyCookiePolicy {
+    #[default]
+    BlockAll,
+    /// Block third-party cookies only for hosts the shield classifies as trackers
+    BlockTrackers,
+    Allow,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CookieAccess {
+    Allowed,
+    Blocked,
+}
+
+/// Result of `document.requestStorageAccess()`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StorageAccessOutcome {
+    Granted,
+    Denied,
+    /// The user has to decide; the shell shows a prompt
+    Prompt,
+}
+
+#[derive(Debug, Default)]
+pub struct CookieLayer {
+    policy: ThirdPartyCookiePolicy,
+    // (embedded site, top-level site) pairs granted by the user
+    grants: HashSet<(String, String)>,
+}
+
+impl CookieLayer {
+    pub fn new(policy: ThirdPartyCookiePolicy) -> Self {
+        Self {
+            policy,
+            grants: HashSet::new(),
+        }
+    }
+
+    pub fn policy(&self) -> ThirdPartyCookiePolicy {
+        self.policy
+    }
+
+    pub fn set_policy(&mut self, policy: ThirdPartyCookiePolicy) {
+        self.policy = policy;
+    }
+
+    /// Whether `request_url`, loaded under the top-level document `top_level_url`, may use its
+    /// cookies. `is_tracker` is the shield's classification of the request.
+    pub fn cookie_access(&self, request_url: &str, top_level_url: &str, is_tracker: bool) -> CookieAccess {
+        let (Some(site), Some(top_site)) = (cookie_site(request_url), cookie_site(top_level_url)) else {
+            return CookieAccess::Blocked;
+        };
+        if site == top_site || self.grants.contains(&(site, top_site)) {
+            return CookieAccess::Allowed;
+        }
+
+        match self.policy {
+            ThirdPartyCookiePolicy::Allow => CookieAccess::Allowed,
+            ThirdPartyCookiePolicy::BlockTrackers if !is_tracker => CookieAccess::Allowed,
+            _ => CookieAccess::Blocked,
+        }
+    }
+
+    /// Handles `document.requestStorageAccess()` from a document at `embedded_url`.
+    /// Trackers are denied outright rather than bothering the user.
+    pub fn request_storage_access(&self, embedded_url: &str, top_level_url: &str, is_tracker: bool) -> StorageAccessOutcome {
+        match self.cookie_access(embedded_url, top_level_url, is_tracker) {
+            CookieAccess::Allowed => StorageAccessOutcome::Granted,
+            CookieAccess::Blocked if is_tracker => StorageAccessOutcome::Denied,
+            CookieAccess::Blocked => StorageAccessOutcome::Prompt,
+        }
+    }
+
+    pub fn grant_storage_access(&mut self, embedded_url: &str, top_level_url: &str) {
+        if let (Some(site), Some(top_site)) = (cookie_site(embedded_url), cookie_site(top_level_url)) {
+            self.grants.insert((site, top_site));
+        }
+    }
+
+    pub fn revoke_storage_access(&mut self, embedded_url: &str, top_level_url: &str) {
+        if let (Some(site), Some(top_site)) = (cookie_site(embedded_url), cookie_site(top_level_url)) {
+            self.grants.remove(&(site, top_site));
+        }
+    }
+
+    /// Forgets what `site` (a registrable domain) was allowed: its grants, embedded or as the
+    /// top-level site. The engine clears its cookies and storage alongside.
+    pub fn clear_site(&mut self, site: &str) {
+        self.grants.retain(|(embedded, top_level)| embedded != site && top_level != site);
+    }
+}
+
+/// The site a URL's cookies belong to: its registrable domain, or its host when it has none
+/// (IP addresses, `localhost`, intranet names).
+fn cookie_site(url: &str) -> Option<String> {
+    site_of(url)
+        .filter(|site| !site.is_empty())
+        .or_else(|| Url::parse(url).ok()?.host_str().map(str::to_string))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TOP: &str = "https://news.example.com/article";
+
+    #[test]
+    fn test_first_party_is_always_allowed() {
+        let cookies = CookieLayer::new(ThirdPartyCookiePolicy::BlockAll);
+        assert_eq!(cookies.cookie_access("https://static.example.com/app.js", TOP, false), CookieAccess::Allowed);
+
+        // Hosts without a registrable domain are their own site
+        for url in ["http://localhost:8080/app.js", "http://192.168.1.10/app.js", "http://intranet/app.js"] {
+            assert_eq!(cookies.cookie_access(url, url, false), CookieAccess::Allowed, "{}", url);
+        }
+        assert_eq!(cookies.cookie_access("http://192.168.1.10/", "http://localhost/", false), CookieAccess::Blocked);
+    }
+
+    #[test]
+    fn test_policies() {
+        let mut cookies = CookieLayer::new(ThirdPartyCookiePolicy::BlockAll);
+        assert_eq!(cookies.cookie_access("https://comments.test/embed", TOP, false), CookieAccess::Blocked);
+
+        cookies.set_policy(ThirdPartyCookiePolicy::BlockTrackers);
+        assert_eq!(cookies.cookie_access("https://comments.test/embed", TOP, false), CookieAccess::Allowed);
+        assert_eq!(cookies.cookie_access("https://tracker.test/pixel", TOP, true), CookieAccess::Blocked);
+
+        cookies.set_policy(ThirdPartyCookiePolicy::Allow);
+        assert_eq!(cookies.cookie_access("https://tracker.test/pixel", TOP, true), CookieAccess::Allowed);
+    }
+
+    #[test]
+    fn test_storage_access_grants() {
+        let mut cookies = CookieLayer::default();
+        let embed = "https://comments.test/embed";
+
+        assert_eq!(cookies.request_storage_access(embed, TOP, false), StorageAccessOutcome::Prompt);
+        assert_eq!(cookies.request_storage_access("https://tracker.test/", TOP, true), StorageAccessOutcome::Denied);
+
+        cookies.grant_storage_access(embed, TOP);
+        assert_eq!(cookies.request_storage_access(embed, TOP, false), StorageAccessOutcome::Granted);
+        assert_eq!(cookies.cookie_access("https://www.comments.test/api", TOP, false), CookieAccess::Allowed);
+        // Grants are per top-level site
+        assert_eq!(cookies.cookie_access(embed, "https://other.test/", false), CookieAccess::Blocked);
+
+        cookies.revoke_storage_access(embed, TOP);
+        assert_eq!(cookies.cookie_access(embed, TOP, false), CookieAccess::Blocked);
+
+        cookies.grant_storage_access(embed, TOP);
+        cookies.grant_storage_access("https://video.test/", embed);
+        cookies.clear_site("comments.test");
+        assert_eq!(cookies.cookie_access(embed, TOP, false), CookieAccess::Blocked);
+        assert_eq!(cookies.cookie_access("https://video.test/", embed, false), CookieAccess::Blocked);
+    }
+}
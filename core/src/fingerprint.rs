@@ -0,0 +1,324 @@
+//! Fingerprinting defenses.
+//!
+//! Scripts can identify a user without cookies by reading back high-entropy browser state:
+//! canvas and audio rendering, GPU strings, navigator properties, screen size and installed
+//! fonts. Depending on the shield level, each surface is either *farbled* (tiny per-site noise,
+//! so the value is stable on one site but useless for cross-site linking) or *normalized*
+//! (replaced with a value shared by every Wave user).
+//!
+//! Noise is derived from a random per-session key mixed with the site, so a site sees the same
+//! values for the whole session while two sites, or two sessions, never agree.
+
+use crate::shield::ShieldLevel;
+use serde::{Deserialize, Serialize};
+
+/// Fonts every platform ships; always visible so pages keep rendering correctly.
+const BASE_FONTS: &[&str] = &[
+    "arial",
+    "courier new",
+    "georgia",
+    "helvetica",
+    "times new roman",
+    "trebuchet ms",
+    "verdana",
+    "serif",
+    "sans-serif",
+    "monospace",
+    "cursive",
+    "fantasy",
+    "system-ui",
+];
+
+/// Session-wide secret all per-site seeds are derived from. Regenerated on every launch.
+pub struct FingerprintSession {
+    key: u64,
+}
+
+impl FingerprintSession {
+    pub fn new() -> Self {
+        Self {
+            key: uuid::Uuid::new_v4().as_u64_pair().0,
+        }
+    }
+
+    /// The policy the engine applies to documents of `site` (the top-level eTLD+1).
+    pub fn policy_for(&self, level: ShieldLevel, site: &str) -> FingerprintPolicy {
+        FingerprintPolicy {
+            level,
+            seed: fnv1a(self.key, site.to_ascii_lowercase().as_bytes()),
+        }
+    }
+}
+
+impl Default for FingerprintSession {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The navigator properties exposed to scripts.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct NavigatorInfo {
+    pub hardware_concurrency: u32,
+    pub device_memory_gb: f32,
+    pub platform: String,
+    pub languages: Vec<String>,
+    pub plugins: Vec<String>,
+}
+
+/// The `window.screen` values and device pixel ratio exposed to scripts.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct ScreenInfo {
+    pub width: u32,
+    pub height: u32,
+    pub avail_width: u32,
+    pub avail_height: u32,
+    pub color_depth: u32,
+    pub device_pixel_ratio: f32,
+}
+
+/// Per-site fingerprinting policy. Cheap to copy; the engine asks for one per document.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FingerprintPolicy {
+    level: ShieldLevel,
+    seed: u64,
+}
+
+impl FingerprintPolicy {
+    pub fn level(&self) -> ShieldLevel {
+        self.level
+    }
+
+    /// Adds noise to RGBA pixels returned by `toDataURL` / `getImageData`.
+    ///
+    /// Standard flips the low bit of a color channel on roughly one pixel in sixteen; Strict
+    /// randomizes the low bit of every color channel.
+    pub fn farble_canvas(&self, rgba: &mut [u8]) {
+        let mut rng = SplitMix64::new(self.seed ^ 0x63616e766173); // "canvas"
+        match self.level {
+            ShieldLevel::Off => {}
+            ShieldLevel::Standard => {
+                for pixel in rgba.chunks_exact_mut(4) {
+                    let r = rng.next();
+                    if r & 0xf == 0 {
+                        pixel[((r >> 4) % 3) as usize] ^= 1;
+                    }
+                }
+            }
+            ShieldLevel::Strict => {
+                for pixel in rgba.chunks_exact_mut(4) {
+                    let r = rng.next();
+                    for (channel, value) in pixel.iter_mut().take(3).enumerate() {
+                        *value = (*value & !1) | ((r >> channel) & 1) as u8;
+                    }
+                }
+            }
+        }
+    }
+
+    /// The `UNMASKED_VENDOR_WEBGL` / `UNMASKED_RENDERER_WEBGL` strings reported to the page.
+    pub fn webgl_strings(&self, vendor: &str, renderer: &str) -> (String, String) {
+        match self.level {
+            ShieldLevel::Off => (vendor.to_string(), renderer.to_string()),
+            // The exact GPU model is the high-entropy part; the vendor is kept for compatibility
+            ShieldLevel::Standard => (vendor.to_string(), format!("{} GPU", vendor)),
+            ShieldLevel::Strict => ("Wave".to_string(), "Wave WebGL".to_string()),
+        }
+    }
+
+    /// Adds inaudible noise to samples read back from an `AnalyserNode` or `OfflineAudioContext`.
+    pub fn farble_audio(&self, samples: &mut [f32]) {
+        let amplitude = match self.level {
+            ShieldLevel::Off => return,
+            ShieldLevel::Standard => 1e-7,
+            ShieldLevel::Strict => 1e-5,
+        };
+        let mut rng = SplitMix64::new(self.seed ^ 0x617564696f); // "audio"
+        for sample in samples.iter_mut() {
+            *sample *= 1.0 + amplitude * rng.next_signed_unit();
+        }
+    }
+
+    pub fn navigator(&self, real: &NavigatorInfo) -> NavigatorInfo {
+        match self.level {
+            ShieldLevel::Off => real.clone(),
+            ShieldLevel::Standard => {
+                // Report a core count between 2 and the real one
+                let mut rng = SplitMix64::new(self.seed ^ 0x6e6176); // "nav"
+                let cores = if real.hardware_concurrency > 2 {
+                    2 + (rng.next() % (real.hardware_concurrency as u64 - 1)) as u32
+                } else {
+                    real.hardware_concurrency
+                };
+                NavigatorInfo {
+                    hardware_concurrency: cores,
+                    device_memory_gb: real.device_memory_gb,
+                    platform: real.platform.clone(),
+                    languages: real.languages.clone(),
+                    plugins: Vec::new(),
+                }
+            }
+            ShieldLevel::Strict => NavigatorInfo {
+                hardware_concurrency: 4,
+                device_memory_gb: 8.0,
+                platform: real.platform.clone(),
+                languages: real.languages.iter().take(1).cloned().collect(),
+                plugins: Vec::new(),
+            },
+        }
+    }
+
+    /// The screen as seen by the page, given the real screen and the panel's viewport size.
+    pub fn screen(&self, real: ScreenInfo, viewport: (u32, u32)) -> ScreenInfo {
+        let (width, height) = match self.level {
+            ShieldLevel::Off => return real,
+            // The screen is no bigger than the window the page already knows about
+            ShieldLevel::Standard => viewport,
+            // Letterbox into coarse buckets, as Tor Browser does
+            ShieldLevel::Strict => ((viewport.0 / 200).max(1) * 200, (viewport.1 / 100).max(1) * 100),
+        };
+        ScreenInfo {
+            width,
+            height,
+            avail_width: width,
+            avail_height: height,
+            color_depth: 24,
+            device_pixel_ratio: if self.level == ShieldLevel::Strict { 1.0 } else { real.device_pixel_ratio },
+        }
+    }
+
+    /// Whether an installed font family may be used by (and therefore detected by) the page.
+    ///
+    /// Standard hides a stable per-site quarter of non-standard fonts; Strict only allows the
+    /// fonts every platform ships.
+    pub fn allow_font(&self, family: &str) -> bool {
+        let family = family.trim().trim_matches(|c| c == '"' || c == '\'').to_ascii_lowercase();
+        if self.level == ShieldLevel::Off || BASE_FONTS.contains(&family.as_str()) {
+            return true;
+        }
+        match self.level {
+            ShieldLevel::Strict => false,
+            _ => !fnv1a(self.seed, family.as_bytes()).is_multiple_of(4),
+        }
+    }
+}
+
+fn fnv1a(seed: u64, bytes: &[u8]) -> u64 {
+    let mut hash = 0xcbf29ce484222325 ^ seed;
+    for byte in bytes {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+/// Small deterministic PRNG; noise must be reproducible from the seed alone.
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform value in [-1, 1].
+    fn next_signed_unit(&mut self) -> f32 {
+        (self.next() >> 40) as f32 / (1u64 << 23) as f32 - 1.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn canvas() -> Vec<u8> {
+        (0..64 * 64 * 4).map(|i| (i % 251) as u8).collect()
+    }
+
+    fn farbled(policy: &FingerprintPolicy) -> Vec<u8> {
+        let mut pixels = canvas();
+        policy.farble_canvas(&mut pixels);
+        pixels
+    }
+
+    #[test]
+    fn test_deterministic_within_session_and_site() {
+        let session = FingerprintSession::new();
+        let a = session.policy_for(ShieldLevel::Standard, "example.com");
+        let b = session.policy_for(ShieldLevel::Standard, "EXAMPLE.com");
+
+        assert_eq!(farbled(&a), farbled(&b));
+        assert_ne!(farbled(&a), canvas());
+
+        let mut audio_a = vec![0.5f32; 128];
+        let mut audio_b = vec![0.5f32; 128];
+        a.farble_audio(&mut audio_a);
+        b.farble_audio(&mut audio_b);
+        assert_eq!(audio_a, audio_b);
+
+        let fonts = ["Fira Code", "Inconsolata", "Roboto", "Ubuntu", "Noto Sans", "Lato", "JetBrains Mono"];
+        for font in fonts {
+            assert_eq!(a.allow_font(font), b.allow_font(font));
+        }
+    }
+
+    #[test]
+    fn test_sites_and_sessions_do_not_agree() {
+        let session = FingerprintSession { key: 1 };
+        let site_a = session.policy_for(ShieldLevel::Standard, "a.com");
+        let site_b = session.policy_for(ShieldLevel::Standard, "b.com");
+        assert_ne!(farbled(&site_a), farbled(&site_b));
+
+        let next_session = FingerprintSession { key: 2 };
+        let site_a_later = next_session.policy_for(ShieldLevel::Standard, "a.com");
+        assert_ne!(farbled(&site_a), farbled(&site_a_later));
+    }
+
+    #[test]
+    fn test_off_passes_everything_through() {
+        let policy = FingerprintSession::new().policy_for(ShieldLevel::Off, "example.com");
+        assert_eq!(farbled(&policy), canvas());
+        assert_eq!(
+            policy.webgl_strings("NVIDIA Corporation", "GeForce RTX 4090"),
+            ("NVIDIA Corporation".to_string(), "GeForce RTX 4090".to_string())
+        );
+        assert!(policy.allow_font("Some Rare Font"));
+    }
+
+    #[test]
+    fn test_strict_normalizes() {
+        let policy = FingerprintSession::new().policy_for(ShieldLevel::Strict, "example.com");
+        let navigator = policy.navigator(&NavigatorInfo {
+            hardware_concurrency: 24,
+            device_memory_gb: 32.0,
+            platform: "Linux x86_64".to_string(),
+            languages: vec!["en-GB".to_string(), "de".to_string()],
+            plugins: vec!["PDF Viewer".to_string()],
+        });
+        assert_eq!(navigator.hardware_concurrency, 4);
+        assert_eq!(navigator.languages, vec!["en-GB".to_string()]);
+        assert!(navigator.plugins.is_empty());
+
+        let real = ScreenInfo {
+            width: 2560,
+            height: 1440,
+            avail_width: 2560,
+            avail_height: 1400,
+            color_depth: 30,
+            device_pixel_ratio: 1.5,
+        };
+        let screen = policy.screen(real, (1234, 789));
+        assert_eq!((screen.width, screen.height), (1200, 700));
+        assert_eq!(screen.device_pixel_ratio, 1.0);
+
+        assert!(policy.allow_font("Arial"));
+        assert!(!policy.allow_font("Fira Code"));
+    }
+}
@@ -0,0 +1,477 @@
+//! Browsing history: every visit, kept in an SQLite database in the profile directory.
+//!
+//! A page's frecency ranks it for the omnibox. It is computed from its most recent visits, each
+//! weighted by how long ago it was and how the user got there (typing a URL counts for more
+//! than following a link), scaled by how often the page was visited. Titles and URLs are
+//! indexed for full-text search.
+
+use crate::spaces::unix_now;
+use rusqlite::{Connection, OptionalExtension, Row, params};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use url::Url;
+
+/// Version written by this build, kept in the database's `user_version`.
+pub const SCHEMA_VERSION: u32 = 1;
+
+const FILE_NAME: &str = "history.sqlite";
+
+/// Visits a page's frecency is computed from, newest first.
+const SAMPLED_VISITS: usize = 10;
+
+const SCHEMA: &str = "
+    CREATE TABLE pages (
+        id INTEGER PRIMARY KEY,
+        url TEXT NOT NULL UNIQUE,
+        host TEXT NOT NULL,
+        title TEXT NOT NULL DEFAULT '',
+        visit_count INTEGER NOT NULL DEFAULT 0,
+        typed_count INTEGER NOT NULL DEFAULT 0,
+        last_visit INTEGER NOT NULL DEFAULT 0,
+        frecency REAL NOT NULL DEFAULT 0
+    );
+    CREATE INDEX pages_host ON pages (host);
+    CREATE INDEX pages_frecency ON pages (frecency DESC);
+
+    CREATE TABLE visits (
+        id INTEGER PRIMARY KEY,
+        page_id INTEGER NOT NULL REFERENCES pages (id) ON DELETE CASCADE,
+        visited_at INTEGER NOT NULL,
+        transition TEXT NOT NULL
+    );
+    CREATE INDEX visits_page ON visits (page_id, visited_at);
+    CREATE INDEX visits_time ON visits (visited_at);
+
+    CREATE VIRTUAL TABLE pages_fts USING fts5 (title, url, content = 'pages', content_rowid = 'id');
+    CREATE TRIGGER pages_fts_insert AFTER INSERT ON pages BEGIN
+        INSERT INTO pages_fts (rowid, title, url) VALUES (new.id, new.title, new.url);
+    END;
+    CREATE TRIGGER pages_fts_delete AFTER DELETE ON pages BEGIN
+        INSERT INTO pages_fts (pages_fts, rowid, title, url) VALUES ('delete', old.id, old.title, old.url);
+    END;
+    CREATE TRIGGER pages_fts_update AFTER UPDATE OF title, url ON pages BEGIN
+        INSERT INTO pages_fts (pages_fts, rowid, title, url) VALUES ('delete', old.id, old.title, old.url);
+        INSERT INTO pages_fts (rowid, title, url) VALUES (new.id, new.title, new.url);
+    END;
+";
+
+/// How a visit came about.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transition {
+    /// Followed a link, or anything not listed below
+    Link,
+    /// Typed into the omnibox
+    Typed,
+    /// A bookmark keyword typed into the omnibox
+    Keyword,
+    /// Opened from the bookmarks
+    Bookmark,
+    /// Back or forward
+    BackForward,
+    Reload,
+}
+
+impl Transition {
+    fn as_str(self) -> &'static str {
+        match self {
+            Transition::Link => "link",
+            Transition::Typed => "typed",
+            Transition::Keyword => "keyword",
+            Transition::Bookmark => "bookmark",
+            Transition::BackForward => "back_forward",
+            Transition::Reload => "reload",
+        }
+    }
+
+    fn parse(name: &str) -> Self {
+        match name {
+            "typed" => Transition::Typed,
+            "keyword" => Transition::Keyword,
+            "bookmark" => Transition::Bookmark,
+            "back_forward" => Transition::BackForward,
+            "reload" => Transition::Reload,
+            _ => Transition::Link,
+        }
+    }
+
+    /// How much a visit of this kind says about the user wanting the page.
+    fn bonus(self) -> f64 {
+        match self {
+            Transition::Typed | Transition::Keyword => 2.0,
+            Transition::Bookmark => 1.5,
+            Transition::Link => 1.0,
+            Transition::BackForward => 0.5,
+            Transition::Reload => 0.0,
+        }
+    }
+}
+
+/// A visited page, with what is known across all its visits.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HistoryEntry {
+    pub url: String,
+    pub title: String,
+    pub visit_count: u64,
+    /// Visits the user typed the URL (or a keyword) for
+    pub typed_count: u64,
+    /// Unix time (seconds)
+    pub last_visit: u64,
+    pub frecency: f64,
+}
+
+/// One visit to a page.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Visit {
+    pub url: String,
+    pub title: String,
+    /// Unix time (seconds)
+    pub visited_at: u64,
+    pub transition: Transition,
+}
+
+#[derive(Debug)]
+pub enum HistoryError {
+    Io(io::Error),
+    Database(rusqlite::Error),
+    /// The database's `user_version`, newer than [`SCHEMA_VERSION`]
+    UnsupportedVersion(u32),
+}
+
+impl fmt::Display for HistoryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HistoryError::Io(e) => write!(f, "I/O error: {}", e),
+            HistoryError::Database(e) => write!(f, "history database error: {}", e),
+            HistoryError::UnsupportedVersion(version) => {
+                write!(f, "history database has schema version {}, newer than the supported {}", version, SCHEMA_VERSION)
+            }
+        }
+    }
+}
+
+impl std::error::Error for HistoryError {}
+
+impl From<io::Error> for HistoryError {
+    fn from(e: io::Error) -> Self {
+        HistoryError::Io(e)
+    }
+}
+
+impl From<rusqlite::Error> for HistoryError {
+    fn from(e: rusqlite::Error) -> Self {
+        HistoryError::Database(e)
+    }
+}
+
+pub struct History {
+    conn: Connection,
+}
+
+impl History {
+    /// Opens the database at `path`, creating it if needed.
+    pub fn open(path: &Path) -> Result<Self, HistoryError> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        Self::with_connection(Connection::open(path)?)
+    }
+
+    /// A history kept only until it is dropped.
+    pub fn in_memory() -> Result<Self, HistoryError> {
+        Self::with_connection(Connection::open_in_memory()?)
+    }
+
+    /// The history in a profile directory. A database that can't be opened is renamed to
+    /// `history.sqlite.unreadable-<unix time>` and a new one started; failing that, history
+    /// lasts only for this session.
+    pub fn open_in_profile(profile_dir: &Path) -> Self {
+        let path = profile_dir.join(FILE_NAME);
+        Self::open(&path)
+            .or_else(|e| {
+                log::warn!("Could not open {}: {}", path.display(), e);
+                let backup = path.with_extension(format!("sqlite.unreadable-{}", unix_now()));
+                fs::rename(&path, &backup)?;
+                Self::open(&path)
+            })
+            .or_else(|e| {
+                log::error!("History will not be kept: {}", e);
+                Self::in_memory()
+            })
+            .expect("in-memory SQLite database")
+    }
+
+    fn with_connection(conn: Connection) -> Result<Self, HistoryError> {
+        conn.execute_batch("PRAGMA foreign_keys = ON; PRAGMA journal_mode = WAL;")?;
+        let version: u32 = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+        if version > SCHEMA_VERSION {
+            return Err(HistoryError::UnsupportedVersion(version));
+        }
+        if version == 0 {
+            conn.execute_batch(&format!("BEGIN; {} PRAGMA user_version = {}; COMMIT;", SCHEMA, SCHEMA_VERSION))?;
+        }
+        Ok(Self { conn })
+    }
+
+    /// Records a visit to `url` now. Only web and file pages are kept; `about:` pages and the
+    /// like are ignored.
+    pub fn record_visit(&mut self, url: &str, transition: Transition) -> Result<(), HistoryError> {
+        self.record_visit_at(url, transition, unix_now())
+    }
+
+    fn record_visit_at(&mut self, url: &str, transition: Transition, at: u64) -> Result<(), HistoryError> {
+        let Some(host) = host_of(url) else {
+            return Ok(());
+        };
+        let tx = self.conn.transaction()?;
+        tx.execute("INSERT INTO pages (url, host) VALUES (?1, ?2) ON CONFLICT (url) DO NOTHING", params![url, host])?;
+        let page_id: i64 = tx.query_row("SELECT id FROM pages WHERE url = ?1", [url], |row| row.get(0))?;
+        tx.execute(
+            "INSERT INTO visits (page_id, visited_at, transition) VALUES (?1, ?2, ?3)",
+            params![page_id, at as i64, transition.as_str()],
+        )?;
+        update_page(&tx, page_id, unix_now())?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Titles arrive after the visit; this names the page for search and `about:history`.
+    pub fn set_title(&self, url: &str, title: &str) -> Result<(), HistoryError> {
+        self.conn.execute("UPDATE pages SET title = ?2 WHERE url = ?1 AND title != ?2", params![url, title])?;
+        Ok(())
+    }
+
+    pub fn page(&self, url: &str) -> Result<Option<HistoryEntry>, HistoryError> {
+        Ok(self
+            .conn
+            .query_row(&format!("SELECT {} FROM pages WHERE url = ?1", ENTRY_COLUMNS), [url], entry_from_row)
+            .optional()?)
+    }
+
+    /// Pages whose title or URL has words starting with each word of `query`, highest frecency
+    /// first. An empty query gives the most frecent pages.
+    pub fn search(&self, query: &str, limit: usize) -> Result<Vec<HistoryEntry>, HistoryError> {
+        let terms: Vec<String> = query
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|word| !word.is_empty())
+            .map(|word| format!("\"{}\"*", word))
+            .collect();
+        if terms.is_empty() {
+            let mut statement = self
+                .conn
+                .prepare(&format!("SELECT {} FROM pages WHERE frecency > 0 ORDER BY frecency DESC LIMIT ?1", ENTRY_COLUMNS))?;
+            let entries = statement.query_map([limit as i64], entry_from_row)?.collect::<Result<_, _>>()?;
+            return Ok(entries);
+        }
+        let mut statement = self.conn.prepare(&format!(
+            "SELECT {} FROM pages_fts JOIN pages ON pages.id = pages_fts.rowid
+             WHERE pages_fts MATCH ?1 ORDER BY frecency DESC, last_visit DESC LIMIT ?2",
+            ENTRY_COLUMNS
+        ))?;
+        let entries = statement.query_map(params![terms.join(" "), limit as i64], entry_from_row)?.collect::<Result<_, _>>()?;
+        Ok(entries)
+    }
+
+    /// Visits from `since` on (Unix seconds), newest first.
+    pub fn visits(&self, since: u64, limit: usize) -> Result<Vec<Visit>, HistoryError> {
+        let mut statement = self.conn.prepare(
+            "SELECT pages.url, pages.title, visits.visited_at, visits.transition
+             FROM visits JOIN pages ON pages.id = visits.page_id
+             WHERE visits.visited_at >= ?1 ORDER BY visits.visited_at DESC, visits.id DESC LIMIT ?2",
+        )?;
+        let visits = statement
+            .query_map(params![since as i64, limit as i64], |row| {
+                Ok(Visit {
+                    url: row.get(0)?,
+                    title: row.get(1)?,
+                    visited_at: row.get::<_, i64>(2)? as u64,
+                    transition: Transition::parse(&row.get::<_, String>(3)?),
+                })
+            })?
+            .collect::<Result<_, _>>()?;
+        Ok(visits)
+    }
+
+    /// Forgets visits from `from` up to (not including) `to`, in Unix seconds; `u64::MAX` leaves
+    /// the range open. Pages left with no visits go too. Returns how many visits were removed.
+    pub fn delete_range(&mut self, from: u64, to: u64) -> Result<usize, HistoryError> {
+        // SQLite integers are signed
+        let (from, to) = (i64::try_from(from).unwrap_or(i64::MAX), i64::try_from(to).unwrap_or(i64::MAX));
+        let tx = self.conn.transaction()?;
+        let page_ids: Vec<i64> = {
+            let mut statement = tx.prepare("SELECT DISTINCT page_id FROM visits WHERE visited_at >= ?1 AND visited_at < ?2")?;
+            statement.query_map(params![from, to], |row| row.get(0))?.collect::<Result<_, _>>()?
+        };
+        let removed = tx.execute("DELETE FROM visits WHERE visited_at >= ?1 AND visited_at < ?2", params![from, to])?;
+        tx.execute("DELETE FROM pages WHERE NOT EXISTS (SELECT 1 FROM visits WHERE visits.page_id = pages.id)", [])?;
+        let now = unix_now();
+        for page_id in page_ids {
+            update_page(&tx, page_id, now)?;
+        }
+        tx.commit()?;
+        Ok(removed)
+    }
+
+    /// Forgets every page on `host` and its subdomains. Returns how many pages were removed.
+    pub fn delete_domain(&self, host: &str) -> Result<usize, HistoryError> {
+        let host = host.trim_start_matches("www.").to_ascii_lowercase();
+        Ok(self.conn.execute(
+            "DELETE FROM pages WHERE host = ?1 OR substr(host, -length(?1) - 1) = '.' || ?1",
+            [host],
+        )?)
+    }
+
+    /// Forgets one page and its visits.
+    pub fn delete_url(&self, url: &str) -> Result<bool, HistoryError> {
+        Ok(self.conn.execute("DELETE FROM pages WHERE url = ?1", [url])? > 0)
+    }
+
+    pub fn clear(&self) -> Result<(), HistoryError> {
+        self.conn.execute("DELETE FROM pages", [])?;
+        Ok(())
+    }
+
+    /// Recomputes every page's frecency; visits count for less as they age, so this is worth
+    /// doing once a session.
+    pub fn refresh_frecency(&mut self) -> Result<(), HistoryError> {
+        let tx = self.conn.transaction()?;
+        let page_ids: Vec<i64> = {
+            let mut statement = tx.prepare("SELECT id FROM pages")?;
+            statement.query_map([], |row| row.get(0))?.collect::<Result<_, _>>()?
+        };
+        let now = unix_now();
+        for page_id in page_ids {
+            update_page(&tx, page_id, now)?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+}
+
+const ENTRY_COLUMNS: &str = "pages.url, pages.title, pages.visit_count, pages.typed_count, pages.last_visit, pages.frecency";
+
+fn entry_from_row(row: &Row) -> rusqlite::Result<HistoryEntry> {
+    Ok(HistoryEntry {
+        url: row.get(0)?,
+        title: row.get(1)?,
+        visit_count: row.get::<_, i64>(2)? as u64,
+        typed_count: row.get::<_, i64>(3)? as u64,
+        last_visit: row.get::<_, i64>(4)? as u64,
+        frecency: row.get(5)?,
+    })
+}
+
+/// The host a page is filed under, or `None` for pages history doesn't keep.
+pub fn host_of(url: &str) -> Option<String> {
+    let url = Url::parse(url).ok()?;
+    match url.scheme() {
+        "http" | "https" => url.host_str().map(|host| host.trim_start_matches("www.").to_string()),
+        "file" => Some(String::new()),
+        _ => None,
+    }
+}
+
+/// Brings a page's counts and frecency up to date with its visits, as of `now`.
+fn update_page(conn: &Connection, page_id: i64, now: u64) -> rusqlite::Result<()> {
+    let (visit_count, typed_count, last_visit): (i64, i64, Option<i64>) = conn.query_row(
+        "SELECT count(*), count(*) FILTER (WHERE transition IN ('typed', 'keyword')), max(visited_at)
+         FROM visits WHERE page_id = ?1",
+        [page_id],
+        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+    )?;
+    let mut statement = conn.prepare_cached("SELECT visited_at, transition FROM visits WHERE page_id = ?1 ORDER BY visited_at DESC LIMIT ?2")?;
+    let sample: Vec<(u64, Transition)> = statement
+        .query_map(params![page_id, SAMPLED_VISITS as i64], |row| {
+            Ok((row.get::<_, i64>(0)? as u64, Transition::parse(&row.get::<_, String>(1)?)))
+        })?
+        .collect::<Result<_, _>>()?;
+    conn.execute(
+        "UPDATE pages SET visit_count = ?2, typed_count = ?3, last_visit = ?4, frecency = ?5 WHERE id = ?1",
+        params![page_id, visit_count, typed_count, last_visit.unwrap_or(0), frecency(visit_count as u64, &sample, now)],
+    )?;
+    Ok(())
+}
+
+/// The average weight of the sampled visits, times how many visits there were in all.
+fn frecency(visit_count: u64, sample: &[(u64, Transition)], now: u64) -> f64 {
+    if sample.is_empty() {
+        return 0.0;
+    }
+    let points: f64 = sample
+        .iter()
+        .map(|(visited_at, transition)| recency_weight(now.saturating_sub(*visited_at)) * transition.bonus())
+        .sum();
+    visit_count as f64 * points / sample.len() as f64
+}
+
+fn recency_weight(age: u64) -> f64 {
+    match age / 86_400 {
+        0..=4 => 100.0,
+        5..=14 => 70.0,
+        15..=31 => 50.0,
+        32..=90 => 30.0,
+        _ => 10.0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: u64 = 86_400;
+
+    #[test]
+    fn test_frecency_ranks_search_results() {
+        let now = unix_now();
+        let mut history = History::in_memory().unwrap();
+        // Visited often, but long ago
+        for day in 0..5 {
+            history.record_visit_at("https://docs.rs/serde", Transition::Link, now - (200 + day) * DAY).unwrap();
+        }
+        history.record_visit_at("https://www.rust-lang.org/learn", Transition::Typed, now - DAY).unwrap();
+        history.record_visit_at("https://www.rust-lang.org/learn", Transition::Link, now).unwrap();
+        history.record_visit_at("https://rust.test/", Transition::Reload, now).unwrap();
+        history.record_visit("about:history", Transition::Typed).unwrap();
+        history.set_title("https://docs.rs/serde", "serde - Rust").unwrap();
+
+        let learn = history.page("https://www.rust-lang.org/learn").unwrap().unwrap();
+        assert_eq!((learn.visit_count, learn.typed_count), (2, 1));
+        assert_eq!(learn.frecency, 300.0);
+        assert_eq!(history.page("https://docs.rs/serde").unwrap().unwrap().frecency, 50.0);
+        assert_eq!(history.page("about:history").unwrap(), None);
+
+        let urls = |entries: Vec<HistoryEntry>| entries.into_iter().map(|entry| entry.url).collect::<Vec<_>>();
+        assert_eq!(urls(history.search("rus", 10).unwrap()), vec!["https://www.rust-lang.org/learn", "https://docs.rs/serde", "https://rust.test/"]);
+        assert_eq!(urls(history.search("rust-lang lea", 10).unwrap()), vec!["https://www.rust-lang.org/learn"]);
+        assert_eq!(urls(history.search("\"serde\" OR", 10).unwrap()), Vec::<String>::new());
+        assert_eq!(urls(history.search("", 1).unwrap()), vec!["https://www.rust-lang.org/learn"]);
+    }
+
+    #[test]
+    fn test_deleting_by_time_and_domain() {
+        let now = unix_now();
+        let mut history = History::in_memory().unwrap();
+        history.record_visit_at("https://a.test/", Transition::Link, now - 2 * DAY).unwrap();
+        history.record_visit_at("https://a.test/", Transition::Link, now - 60).unwrap();
+        history.record_visit_at("https://b.test/", Transition::Link, now - 30).unwrap();
+        history.record_visit_at("https://mail.c.test/", Transition::Link, now - 3 * DAY).unwrap();
+        history.record_visit_at("https://notc.test/", Transition::Link, now - 3 * DAY).unwrap();
+
+        assert_eq!(history.delete_range(now - 3600, now + 1).unwrap(), 2);
+        assert_eq!(history.page("https://b.test/").unwrap(), None);
+        let a = history.page("https://a.test/").unwrap().unwrap();
+        assert_eq!((a.visit_count, a.last_visit), (1, now - 2 * DAY));
+
+        assert_eq!(history.delete_domain("www.c.test").unwrap(), 1);
+        let visits = history.visits(0, 10).unwrap();
+        assert_eq!(visits.iter().map(|visit| visit.url.as_str()).collect::<Vec<_>>(), vec!["https://a.test/", "https://notc.test/"]);
+        assert!(history.search("mail", 10).unwrap().is_empty());
+
+        // Open-ended: everything since
+        history.record_visit_at("https://d.test/", Transition::Link, now + 60).unwrap();
+        assert_eq!(history.delete_range(now - 2 * DAY, u64::MAX).unwrap(), 2);
+        let visits = history.visits(0, 10).unwrap();
+        assert_eq!(visits.iter().map(|visit| visit.url.as_str()).collect::<Vec<_>>(), vec!["https://notc.test/"]);
+    }
+}
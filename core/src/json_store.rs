@@ -0,0 +1,158 @@
+//! A JSON file in the profile directory with a schema `version`, as the bookmarks and the
+//! reading list are kept.
+//!
+//! A file written by a newer build is left alone rather than downgraded: it isn't read, and
+//! isn't saved over, so going back to the newer build finds it intact. One that can't be read
+//! is moved aside (not deleted) so the next save doesn't destroy it. Saves are atomic.
+
+use crate::spaces::unix_now;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_json::json;
+use std::cell::Cell;
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+#[derive(Debug)]
+pub(crate) enum JsonStoreError {
+    Io(io::Error),
+    Parse(serde_json::Error),
+    UnsupportedVersion(u64),
+}
+
+impl fmt::Display for JsonStoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JsonStoreError::Io(e) => write!(f, "I/O error: {}", e),
+            JsonStoreError::Parse(e) => write!(f, "malformed file: {}", e),
+            JsonStoreError::UnsupportedVersion(version) => write!(f, "unsupported schema version {}", version),
+        }
+    }
+}
+
+impl From<io::Error> for JsonStoreError {
+    fn from(e: io::Error) -> Self {
+        JsonStoreError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for JsonStoreError {
+    fn from(e: serde_json::Error) -> Self {
+        JsonStoreError::Parse(e)
+    }
+}
+
+pub(crate) struct JsonStore {
+    path: PathBuf,
+    /// Version written by this build; anything newer isn't read
+    version: u64,
+    // Set once the file turns out to be newer, so saves leave it alone
+    newer: Cell<Option<u64>>,
+}
+
+impl JsonStore {
+    pub(crate) fn new(path: PathBuf, version: u64) -> Self {
+        Self { path, version, newer: Cell::new(None) }
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The saved value; the default if nothing was saved yet.
+    pub(crate) fn load<T: DeserializeOwned + Default>(&self) -> Result<T, JsonStoreError> {
+        let data = match fs::read_to_string(&self.path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(T::default()),
+            Err(e) => return Err(e.into()),
+        };
+        let document: serde_json::Value = serde_json::from_str(&data)?;
+        if let Some(version) = document["version"].as_u64().filter(|version| *version > self.version) {
+            self.newer.set(Some(version));
+            return Err(JsonStoreError::UnsupportedVersion(version));
+        }
+        Ok(serde_json::from_value(document)?)
+    }
+
+    /// Loads the saved value, falling back to the default; `error` says why the file couldn't
+    /// be read. A corrupt file then goes to `<file>.unreadable-<unix time>`; a newer one stays.
+    pub(crate) fn load_or_default<T, E>(&self, error: impl Fn(JsonStoreError) -> E) -> T
+    where
+        T: DeserializeOwned + Default,
+        E: fmt::Display,
+    {
+        self.load().unwrap_or_else(|e| {
+            let newer = matches!(e, JsonStoreError::UnsupportedVersion(_));
+            log::warn!("Could not load {}: {}", self.path.display(), error(e));
+            if !newer {
+                let backup = self.path.with_extension(format!("json.unreadable-{}", unix_now()));
+                if let Err(e) = fs::rename(&self.path, &backup) {
+                    log::warn!("Could not move {} aside: {}", self.path.display(), e);
+                }
+            }
+            T::default()
+        })
+    }
+
+    /// Writes `value` with this build's version; refused if the file was found to be newer.
+    pub(crate) fn save<T: Serialize>(&self, value: &T) -> Result<(), JsonStoreError> {
+        if let Some(version) = self.newer.get() {
+            return Err(JsonStoreError::UnsupportedVersion(version));
+        }
+        let mut document = serde_json::to_value(value)?;
+        document["version"] = json!(self.version);
+        write_atomically(&self.path, &serde_json::to_string_pretty(&document)?)?;
+        Ok(())
+    }
+}
+
+/// Writes to a temporary file next to `path`, then renames it over `path`.
+pub(crate) fn write_atomically(path: &Path, data: &str) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let tmp = path.with_extension("json.tmp");
+    {
+        let mut file = fs::File::create(&tmp)?;
+        file.write_all(data.as_bytes())?;
+        file.sync_all()?;
+    }
+    fs::rename(&tmp, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn test_versions_and_unreadable_files() {
+        let dir = std::env::temp_dir().join(format!("wave-json-store-{}", uuid::Uuid::new_v4()));
+        let store = JsonStore::new(dir.join("store.json"), 2);
+        assert_eq!(store.load::<BTreeMap<String, u64>>().unwrap(), BTreeMap::new());
+
+        let value = BTreeMap::from([("count".to_string(), 3)]);
+        store.save(&value).unwrap();
+        let saved: serde_json::Value = serde_json::from_str(&fs::read_to_string(store.path()).unwrap()).unwrap();
+        assert_eq!(saved, json!({ "count": 3, "version": 2 }));
+
+        // A newer file is refused, and kept as it is for the build that wrote it
+        let newer = r#"{ "version": 3 }"#;
+        fs::write(store.path(), newer).unwrap();
+        let store = JsonStore::new(dir.join("store.json"), 2);
+        assert_eq!(store.load_or_default::<BTreeMap<String, u64>, _>(|e| e), BTreeMap::new());
+        assert!(matches!(store.save(&value), Err(JsonStoreError::UnsupportedVersion(3))));
+        assert_eq!(fs::read_to_string(store.path()).unwrap(), newer);
+
+        // A corrupt one is moved aside, and saving starts afresh
+        let store = JsonStore::new(dir.join("corrupt.json"), 2);
+        fs::write(store.path(), "{ not json").unwrap();
+        assert_eq!(store.load_or_default::<BTreeMap<String, u64>, _>(|e| e), BTreeMap::new());
+        assert!(!store.path().exists());
+        assert!(fs::read_dir(&dir).unwrap().any(|entry| entry.unwrap().file_name().to_string_lossy().starts_with("corrupt.json.unreadable-")));
+        store.save(&value).unwrap();
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,19 +1,83 @@
+pub mod bookmarks;
+pub mod cookies;
+pub mod fingerprint;
+pub mod history;
+mod json_store;
+pub mod omnibox;
+pub mod profile;
+pub mod reading_list;
 pub mod shield;
 pub mod spaces;
 
+use cookies::{CookieAccess, CookieLayer, StorageAccessOutcome};
+use fingerprint::{FingerprintPolicy, FingerprintSession};
+use shield::{Decision, ResourceType, ShieldLevel, WaveShield};
+use shield::consent::ConsentPlan;
+use shield::picker::{ElementPicker, PickedElement};
+use shield::safe_browsing::ThreatType;
+use std::collections::HashSet;
+use std::time::SystemTime;
 use url::Url;
 
+/// Page zoom range accepted by `SetZoom`.
+pub const MIN_ZOOM: f32 = 0.3;
+pub const MAX_ZOOM: f32 = 5.0;
+
 /// Event types that the shell (UI) sends to the engine.
 #[derive(Debug, Clone)]
 pub enum EngineEvent {
     LoadUrl(String),
     Resize { width: u32, height: u32 },
     InputClick { x: f32, y: f32 },
+    InputHover { x: f32, y: f32 },
     InputKey { keycode: u32 },
     Back,
     Forward,
     Refresh,
     Stop,
+    InterstitialGoBack,
+    InterstitialProceed,
+    StorageAccessResponse { grant: bool },
+    StartElementPicker,
+    StopElementPicker,
+    SetZoom(f32),
+    ScrollTo { x: f32, y: f32 },
+    /// Keeps navigations the page starts on one origin (`None` lifts it); see
+    /// [`EngineNotification::OpenInNewPanel`]
+    LockOrigin(Option<String>),
+}
+
+/// What the engine reports back to the shell about the page it shows; see
+/// [`WaveEngine::take_notifications`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum EngineNotification {
+    /// A navigation committed
+    UrlChanged(String),
+    TitleChanged(String),
+    /// URL of the page's icon
+    FaviconChanged(String),
+    ScrollChanged { x: f32, y: f32 },
+    ZoomChanged(f32),
+    /// Bytes the document is using
+    MemoryUsage(u64),
+    /// The page tried to leave the locked origin; the shell should open this URL elsewhere
+    OpenInNewPanel(String),
+    /// The page at this URL couldn't be fetched, e.g. because the network is down
+    LoadFailed(String),
+}
+
+/// An embedded document asking for its cookies via `document.requestStorageAccess()`.
+#[derive(Debug, Clone)]
+pub struct StorageAccessPrompt {
+    pub embedded_url: String,
+    pub top_level_url: String,
+}
+
+/// A full-page warning shown in place of a site the shield flagged as dangerous.
+#[derive(Debug, Clone)]
+pub struct Interstitial {
+    pub url: String,
+    pub threat: ThreatType,
 }
 
 /// Helper struct to manage the Servo instance and communication channels.
@@ -23,6 +87,23 @@ pub struct WaveEngine {
     // events_proxy: EventLoopProxy<...>,
     current_url: String,
     is_loading: bool,
+    fingerprinting: FingerprintSession,
+    interstitial: Option<Interstitial>,
+    // Hosts the user chose to visit despite a warning, for this session only
+    bypassed_hosts: HashSet<String>,
+    cookies: CookieLayer,
+    storage_access_prompt: Option<StorageAccessPrompt>,
+    // Active while the user is choosing an element to hide
+    picker: Option<ElementPicker>,
+    zoom: f32,
+    // Origin that navigations the page starts may not leave
+    locked_origin: Option<String>,
+    // Markup of the loaded document, for reader mode
+    document: Option<String>,
+    // Cookie banner rejections to run in the current document
+    consent_plans: Vec<ConsentPlan>,
+    // Reported to the shell, oldest first, until it takes them
+    notifications: Vec<EngineNotification>,
 }
 
 impl WaveEngine {
@@ -40,6 +121,17 @@ impl WaveEngine {
         Self {
             current_url: String::from("about:blank"),
             is_loading: false,
+            fingerprinting: FingerprintSession::new(),
+            interstitial: None,
+            bypassed_hosts: HashSet::new(),
+            cookies: CookieLayer::default(),
+            storage_access_prompt: None,
+            picker: None,
+            zoom: 1.0,
+            locked_origin: None,
+            document: None,
+            consent_plans: Vec::new(),
+            notifications: Vec::new(),
         }
     }
 
@@ -52,24 +144,291 @@ impl WaveEngine {
             EngineEvent::Forward => log::info!("Engine: Forward"),
             EngineEvent::Refresh => log::info!("Engine: Refresh"),
             EngineEvent::Stop => log::info!("Engine: Stop"),
-            EngineEvent::InputClick { x, y } => log::info!("Engine: Click at {}, {}", x, y),
+            EngineEvent::InputClick { x, y } => match &mut self.picker {
+                // The page never sees clicks while picking
+                Some(picker) => {
+                    if picker.pick() {
+                        self.preview_picker_selector();
+                    }
+                }
+                None => log::info!("Engine: Click at {}, {}", x, y),
+            },
+            EngineEvent::InputHover { x, y } => {
+                if self.picker.as_ref().is_some_and(|picker| !picker.is_picked()) {
+                    log::trace!("Engine: Hit test at {}, {}", x, y);
+                    // self.constellation.send(ConstellationMsg::HitTest(x, y)); answered via element_hovered()
+                }
+            }
             EngineEvent::InputKey { keycode } => log::info!("Engine: Key {}", keycode),
+            EngineEvent::InterstitialGoBack => self.interstitial = None,
+            EngineEvent::InterstitialProceed => {
+                if let Some(interstitial) = self.interstitial.take() {
+                    if let Some(host) = host_of(&interstitial.url) {
+                        self.bypassed_hosts.insert(host);
+                    }
+                    self.load_url(&interstitial.url);
+                }
+            }
+            EngineEvent::StorageAccessResponse { grant } => {
+                if let Some(prompt) = self.storage_access_prompt.take() {
+                    log::info!("Engine: Storage access for {} {}", prompt.embedded_url, if grant { "granted" } else { "denied" });
+                    if grant {
+                        self.cookies.grant_storage_access(&prompt.embedded_url, &prompt.top_level_url);
+                    }
+                    // self.constellation.send(ConstellationMsg::StorageAccessResolved(grant));
+                }
+            }
+            EngineEvent::StartElementPicker => self.picker = Some(ElementPicker::new()),
+            EngineEvent::StopElementPicker => self.stop_element_picker(),
+            EngineEvent::SetZoom(zoom) => {
+                self.zoom = zoom.clamp(MIN_ZOOM, MAX_ZOOM);
+                log::info!("Engine: Zoom {}", self.zoom);
+                // self.compositor.send(CompositorMsg::SetPageZoom(self.zoom));
+                self.notifications.push(EngineNotification::ZoomChanged(self.zoom));
+            }
+            EngineEvent::ScrollTo { x, y } => {
+                log::info!("Engine: Scroll to {}, {}", x, y);
+                // self.constellation.send(ConstellationMsg::ScrollTo(x, y)); answered via scrolled()
+            }
+            EngineEvent::LockOrigin(origin) => self.locked_origin = origin,
+        }
+    }
+
+    /// Called when the page starts a navigation (a link, a form, `location = ...`). Off the
+    /// locked origin it doesn't happen here; the shell is asked to open it in a new panel.
+    pub fn navigation_requested(&mut self, url: &str) {
+        let leaves_origin = self.locked_origin.as_ref().is_some_and(|locked| {
+            !Url::parse(url).is_ok_and(|target| target.origin().ascii_serialization() == *locked)
+        });
+        if leaves_origin {
+            log::info!("Engine: {} leaves the locked origin", url);
+            self.notifications.push(EngineNotification::OpenInNewPanel(url.to_string()));
+        } else {
+            self.load_url(url);
+        }
+    }
+
+    /// Everything the engine reported since the last call, oldest first.
+    pub fn take_notifications(&mut self) -> Vec<EngineNotification> {
+        std::mem::take(&mut self.notifications)
+    }
+
+    /// Called when the document's title changes.
+    pub fn title_changed(&mut self, title: &str) {
+        self.notifications.push(EngineNotification::TitleChanged(title.to_string()));
+    }
+
+    /// Called when the document announces its icon.
+    pub fn favicon_changed(&mut self, favicon_url: &str) {
+        self.notifications.push(EngineNotification::FaviconChanged(favicon_url.to_string()));
+    }
+
+    /// Called when a document starts parsing, before any of its scripts run: applies the
+    /// shield's cosmetic filters, scriptlets and fingerprinting policy, and starts rejecting
+    /// cookie banners.
+    pub fn document_started(&mut self, shield: &WaveShield) {
+        self.clear_bounce_trackers(shield, SystemTime::now());
+        let fingerprint = self.fingerprint_policy(shield.level());
+        let selectors = shield.hide_selectors(&self.current_url);
+        let script = shield.injected_script(&self.current_url);
+        self.consent_plans = shield.cookie_banner_plans(&self.current_url);
+        log::debug!(
+            "Engine: {} hiding rules, {} scriptlets, {} cookie banner plans, {:?} for {}",
+            selectors.len(),
+            if script.is_some() { "with" } else { "no" },
+            self.consent_plans.len(),
+            fingerprint,
+            self.current_url
+        );
+        // self.constellation.send(ConstellationMsg::SetUserStylesheet(
+        //     selectors.iter().map(|s| format!("{} {{ display: none !important; }}", s)).collect()));
+        // if let Some(script) = script { self.constellation.send(ConstellationMsg::InjectScript(script)); }
+        // self.constellation.send(ConstellationMsg::RunConsentPlans(self.consent_plans.clone()));
+        // self.constellation.send(ConstellationMsg::SetFingerprintPolicy(fingerprint));
+    }
+
+    /// Called by the network layer before a request leaves, with the headers it would send:
+    /// the shield decides whether it goes out, and rewrites the headers of one that does.
+    pub fn request_will_be_sent(&mut self, url: &str, resource_type: ResourceType, headers: &mut Vec<(String, String)>, shield: &WaveShield) -> Decision {
+        // A navigation is its own source
+        let source_url = if resource_type == ResourceType::Document { url } else { &self.current_url };
+        let decision = shield.should_allow_request(url, source_url, resource_type).unwrap_or_else(|e| {
+            log::debug!("Engine: {}", e);
+            Decision::Allow
+        });
+        if matches!(decision, Decision::Allow | Decision::Sanitize) {
+            shield.apply_request_headers(url, headers);
+        }
+        decision
+    }
+
+    /// Called when the server redirects a request. A navigation redirected on to somewhere else
+    /// never showed the user `from`, so its site is recorded as a possible bounce tracker.
+    pub fn request_redirected(&mut self, from: &str, to: &str, resource_type: ResourceType, shield: &WaveShield) {
+        log::debug!("Engine: {} redirected to {}", from, to);
+        if resource_type == ResourceType::Document {
+            shield.record_redirect_hop(from);
         }
     }
 
+    /// Clears the cookies and storage of sites the shield found only ever bounced the user.
+    fn clear_bounce_trackers(&mut self, shield: &WaveShield, now: SystemTime) {
+        for site in shield.bounce_sites_to_clear(now) {
+            log::info!("Engine: Clearing storage of bounce tracker {}", site);
+            self.cookies.clear_site(&site);
+            // self.constellation.send(ConstellationMsg::ClearSiteData(site));
+        }
+    }
+
+    /// Cookie banner rejections running in the current document.
+    pub fn consent_plans(&self) -> &[ConsentPlan] {
+        &self.consent_plans
+    }
+
+    /// Called when a navigation fails before anything is shown, with the network error.
+    pub fn load_failed(&mut self, url: &str, error: &str) {
+        log::info!("Engine: could not load {}: {}", url, error);
+        self.is_loading = false;
+        self.notifications.push(EngineNotification::LoadFailed(url.to_string()));
+    }
+
+    /// Called once the document has loaded, with its serialized DOM.
+    pub fn document_loaded(&mut self, html: &str) {
+        self.document = Some(html.to_string());
+    }
+
+    /// The loaded document's markup; `None` while a page is loading.
+    pub fn document_html(&self) -> Option<&str> {
+        self.document.as_deref()
+    }
+
+    /// Called when the document scrolls, by the user or after `ScrollTo`.
+    pub fn scrolled(&mut self, x: f32, y: f32) {
+        self.notifications.push(EngineNotification::ScrollChanged { x, y });
+    }
+
+    /// Called with the document's memory use, as measured by Servo's memory reporter.
+    pub fn memory_reported(&mut self, bytes: u64) {
+        self.notifications.push(EngineNotification::MemoryUsage(bytes));
+    }
+
+    pub fn zoom(&self) -> f32 {
+        self.zoom
+    }
+
+    pub fn cookies(&self) -> &CookieLayer {
+        &self.cookies
+    }
+
+    pub fn cookies_mut(&mut self) -> &mut CookieLayer {
+        &mut self.cookies
+    }
+
+    /// Whether a request from the current document may send and receive cookies.
+    pub fn cookie_access(&self, request_url: &str, resource_type: ResourceType, shield: &WaveShield) -> CookieAccess {
+        let is_tracker = shield.is_tracker(request_url, &self.current_url, resource_type);
+        self.cookies.cookie_access(request_url, &self.current_url, is_tracker)
+    }
+
+    /// Called when an embedded document invokes `document.requestStorageAccess()`. If the user
+    /// has to decide, the request is held until the shell answers with `StorageAccessResponse`.
+    pub fn request_storage_access(&mut self, embedded_url: &str, shield: &WaveShield) -> StorageAccessOutcome {
+        let is_tracker = shield.is_tracker(embedded_url, &self.current_url, ResourceType::Subdocument);
+        let outcome = self.cookies.request_storage_access(embedded_url, &self.current_url, is_tracker);
+        if outcome == StorageAccessOutcome::Prompt {
+            self.storage_access_prompt = Some(StorageAccessPrompt {
+                embedded_url: embedded_url.to_string(),
+                top_level_url: self.current_url.clone(),
+            });
+        }
+        outcome
+    }
+
+    /// The Storage Access request waiting for the user, if any.
+    pub fn storage_access_prompt(&self) -> Option<&StorageAccessPrompt> {
+        self.storage_access_prompt.as_ref()
+    }
+
+    /// The element picker, while it is active.
+    pub fn element_picker(&self) -> Option<&ElementPicker> {
+        self.picker.as_ref()
+    }
+
+    /// Hit test result for the last `InputHover`: the element under the pointer, if any.
+    pub fn element_hovered(&mut self, element: Option<PickedElement>) {
+        if let Some(picker) = &mut self.picker {
+            picker.hover(element);
+        }
+    }
+
+    /// Chooses how specific the picked element's selector is, and previews it.
+    pub fn set_picker_specificity(&mut self, specificity: usize) {
+        if let Some(picker) = &mut self.picker {
+            picker.set_specificity(specificity);
+            self.preview_picker_selector();
+        }
+    }
+
+    /// Drops the picked element and goes back to hovering.
+    pub fn repick_element(&mut self) {
+        if let Some(picker) = &mut self.picker {
+            picker.unpick();
+            self.preview_picker_selector();
+        }
+    }
+
+    /// Hides whatever the picker's selector matches, so the user sees what the rule would do.
+    fn preview_picker_selector(&self) {
+        let selector = self.picker.as_ref().and_then(ElementPicker::selector);
+        log::debug!("Engine: Previewing hiding rule {:?}", selector);
+        // self.constellation.send(ConstellationMsg::SetPreviewStylesheet(
+        //     selector.map(|s| format!("{} {{ display: none !important; }}", s))));
+    }
+
+    fn stop_element_picker(&mut self) {
+        if self.picker.take().is_some() {
+            // self.constellation.send(ConstellationMsg::SetPreviewStylesheet(None));
+            log::debug!("Engine: Element picker closed");
+        }
+    }
+
+    /// Show a warning page instead of loading `url`.
+    pub fn show_interstitial(&mut self, url: &str, threat: ThreatType) {
+        log::warn!("Engine: Blocking navigation to {} ({:?})", url, threat);
+        self.is_loading = false;
+        self.interstitial = Some(Interstitial {
+            url: url.to_string(),
+            threat,
+        });
+    }
+
+    pub fn interstitial(&self) -> Option<&Interstitial> {
+        self.interstitial.as_ref()
+    }
+
+    /// Whether the user already chose to proceed to this site despite a warning.
+    pub fn is_bypassed(&self, url: &str) -> bool {
+        host_of(url).is_some_and(|host| self.bypassed_hosts.contains(&host))
+    }
+
     fn load_url(&mut self, url: &str) {
         // Validate URL
         let parsed = match Url::parse(url) {
             Ok(u) => u,
             Err(_) => match Url::parse(&format!("https://{}", url)) {
                 Ok(u) => u,
-                Err(_) => return, // Handle search query fallback here
+                Err(e) => return self.load_failed(url, &e.to_string()),
             },
         };
 
+        self.interstitial = None;
+        self.stop_element_picker();
+        self.document = None;
+        self.consent_plans.clear();
         self.current_url = parsed.to_string();
         self.is_loading = true;
         log::info!("Engine loading URL: {}", self.current_url);
+        self.notifications.push(EngineNotification::UrlChanged(self.current_url.clone()));
         
         // In real Servo:
         // self.constellation.send(ConstellationMsg::Load(parsed_url));
@@ -84,10 +443,22 @@ impl WaveEngine {
         &self.current_url
     }
 
+    pub fn current_host(&self) -> Option<String> {
+        host_of(&self.current_url)
+    }
+
     pub fn is_loading(&self) -> bool {
         self.is_loading
     }
 
+    /// Fingerprinting policy for the current document, consulted by canvas, WebGL, audio,
+    /// navigator, screen and font APIs before exposing values to scripts. It is the same across
+    /// a site's subdomains, so they can't tell visitors apart by comparing noise.
+    pub fn fingerprint_policy(&self, level: ShieldLevel) -> FingerprintPolicy {
+        let site = shield::site_of(&self.current_url).or_else(|| host_of(&self.current_url)).unwrap_or_default();
+        self.fingerprinting.policy_for(level, &site)
+    }
+
     /// Trigger the engine to paint the current frame to the active OpenGL context.
     /// 
     /// # Arguments
@@ -108,3 +479,113 @@ impl WaveEngine {
         // servo.recomposite();
     }
 }
+
+fn host_of(url: &str) -> Option<String> {
+    Url::parse(url).ok().and_then(|u| u.host_str().map(str::to_string))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reading_list::ReadingList;
+    use shield::debounce::BOUNCE_GRACE_PERIOD;
+
+    fn engine_at(url: &str) -> WaveEngine {
+        let mut engine = WaveEngine::new(std::ptr::null_mut());
+        engine.dispatch(EngineEvent::LoadUrl(url.to_string()));
+        engine
+    }
+
+    #[test]
+    fn test_outgoing_requests_are_filtered_and_rewritten() {
+        let shield = WaveShield::new();
+        shield.load_filters(vec!["||ads.test^".to_string()]);
+        let mut engine = engine_at("https://news.test/article");
+        let sent = || {
+            vec![
+                ("Referer".to_string(), "https://news.test/article".to_string()),
+                ("Sec-CH-UA".to_string(), "\"Wave\"".to_string()),
+                ("Sec-CH-UA-Full-Version-List".to_string(), "\"Wave\";v=\"1.2.3\"".to_string()),
+                ("X-Client-Data".to_string(), "CJa2yQE=".to_string()),
+            ]
+        };
+
+        let mut headers = sent();
+        assert_eq!(engine.request_will_be_sent("https://cdn.other.test/app.js", ResourceType::Script, &mut headers, &shield), Decision::Allow);
+        assert_eq!(
+            headers,
+            vec![
+                ("Referer".to_string(), "https://news.test/".to_string()),
+                ("Sec-CH-UA".to_string(), "\"Wave\"".to_string()),
+                ("Sec-GPC".to_string(), "1".to_string()),
+            ]
+        );
+
+        // Same-site requests keep the full referrer
+        let mut headers = sent();
+        engine.request_will_be_sent("https://static.news.test/app.js", ResourceType::Script, &mut headers, &shield);
+        assert_eq!(headers[0].1, "https://news.test/article");
+
+        // Blocked requests never leave, so there's nothing to rewrite
+        let mut headers = sent();
+        assert_eq!(engine.request_will_be_sent("https://ads.test/ad.js", ResourceType::Script, &mut headers, &shield), Decision::Block);
+        assert_eq!(headers, sent());
+    }
+
+    #[test]
+    fn test_failed_load_falls_back_to_the_offline_copy() {
+        let mut list = ReadingList::default();
+        list.add("https://Blog.test", "Home").has_snapshot = true;
+        let mut engine = engine_at("https://blog.test");
+        engine.take_notifications();
+
+        let url = engine.get_url().to_string();
+        engine.load_failed(&url, "net::ERR_INTERNET_DISCONNECTED");
+        assert!(!engine.is_loading());
+        let notifications = engine.take_notifications();
+        let [EngineNotification::LoadFailed(failed)] = notifications.as_slice() else {
+            panic!("{:?}", notifications);
+        };
+        assert_eq!(list.offline_copy(failed).map(|item| item.title.as_str()), Some("Home"));
+
+        // Nor does a URL that can't be loaded at all leave the shell waiting
+        engine.dispatch(EngineEvent::LoadUrl("not a url".to_string()));
+        assert_eq!(engine.take_notifications(), vec![EngineNotification::LoadFailed("not a url".to_string())]);
+    }
+
+    #[test]
+    fn test_fingerprint_policy_is_per_site() {
+        let policy = |engine: &mut WaveEngine, url: &str| {
+            engine.dispatch(EngineEvent::LoadUrl(url.to_string()));
+            engine.fingerprint_policy(ShieldLevel::Standard)
+        };
+        let mut engine = engine_at("about:blank");
+        let mail = policy(&mut engine, "https://mail.example.com/inbox");
+        assert_eq!(policy(&mut engine, "https://docs.example.com/"), mail);
+        assert_ne!(policy(&mut engine, "https://example.org/"), mail);
+    }
+
+    #[test]
+    fn test_bounce_trackers_lose_their_storage() {
+        let shield = WaveShield::new();
+        let mut engine = engine_at("https://news.test/");
+        for embedded in ["https://bounce.test/", "https://clicked.test/", "https://cdn.test/"] {
+            engine.cookies_mut().grant_storage_access(embedded, "https://news.test/");
+        }
+        let access = |engine: &WaveEngine, url: &str| engine.cookie_access(url, ResourceType::Subdocument, &shield);
+
+        engine.request_redirected("https://bounce.test/r?to=shop", "https://shop.test/", ResourceType::Document, &shield);
+        engine.request_redirected("https://clicked.test/go", "https://shop.test/", ResourceType::Document, &shield);
+        shield.record_user_interaction("https://www.clicked.test/");
+        // Only navigations bounce
+        engine.request_redirected("https://cdn.test/a.png", "https://img.test/a.png", ResourceType::Image, &shield);
+
+        engine.clear_bounce_trackers(&shield, SystemTime::now());
+        assert_eq!(access(&engine, "https://bounce.test/"), CookieAccess::Allowed);
+
+        engine.clear_bounce_trackers(&shield, SystemTime::now() + BOUNCE_GRACE_PERIOD);
+        assert_eq!(access(&engine, "https://bounce.test/"), CookieAccess::Blocked);
+        assert_eq!(access(&engine, "https://clicked.test/"), CookieAccess::Allowed);
+        assert_eq!(access(&engine, "https://cdn.test/"), CookieAccess::Allowed);
+    }
+}
@@ -0,0 +1,202 @@
+//! Omnibox autocompletion: providers each offer suggestions for what is typed, which are merged
+//! into one ranked list.
+//!
+//! A provider scores its own suggestions on a shared relevance scale (see the constants below),
+//! so that, say, an open panel outranks a page merely visited before. Suggestions leading to
+//! the same page are collapsed into the most relevant one. Providers live in [`providers`];
+//! anything else, such as an extension, can offer suggestions by implementing [`Provider`].
+
+pub mod providers;
+pub mod search;
+
+use std::collections::HashMap;
+
+/// What is typed, the way providers match against it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Input {
+    /// As typed, trimmed
+    pub text: String,
+    /// Lowercased words
+    pub terms: Vec<String>,
+}
+
+impl Input {
+    pub fn new(text: &str) -> Self {
+        let text = text.trim().to_string();
+        let terms = text.split_whitespace().map(str::to_lowercase).collect();
+        Self { text, terms }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.text.is_empty()
+    }
+
+    /// Whether every word appears in one of `fields`, ignoring case.
+    pub fn matches(&self, fields: &[&str]) -> bool {
+        let fields: Vec<String> = fields.iter().map(|field| field.to_lowercase()).collect();
+        self.terms.iter().all(|term| fields.iter().any(|field| field.contains(term.as_str())))
+    }
+
+    /// Whether `url`, without its scheme and `www.`, starts with what is typed.
+    pub fn is_url_prefix_of(&self, url: &str) -> bool {
+        let typed = self.text.to_lowercase();
+        let typed = strip_url(&typed);
+        !typed.is_empty() && strip_url(&url.to_lowercase()).starts_with(typed)
+    }
+}
+
+/// Which provider a suggestion came from; the UI picks its icon by it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SuggestionKind {
+    /// What was typed, as an address or a search
+    Typed,
+    History,
+    Bookmark,
+    /// A bookmark keyword's expansion
+    Keyword,
+    OpenPanel,
+    /// Offered by the search engine
+    Search,
+    AboutPage,
+    Extension,
+}
+
+/// What choosing a suggestion does.
+#[derive(Debug, Clone, PartialEq)]
+pub enum OmniboxAction {
+    Navigate(String),
+    /// Show a panel that is already open; `space` is `None` for an essential
+    SwitchToPanel { space: Option<String>, panel: String },
+    /// Hand the text after an extension's keyword to the extension
+    Extension { extension_id: String, text: String },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Suggestion {
+    pub kind: SuggestionKind,
+    pub title: String,
+    /// Shown under the title, usually the URL
+    pub detail: String,
+    pub action: OmniboxAction,
+    /// Higher comes first
+    pub relevance: u32,
+}
+
+impl Suggestion {
+    /// Suggestions with the same key lead to the same place; only the most relevant is kept.
+    fn dedup_key(&self) -> String {
+        match &self.action {
+            OmniboxAction::Navigate(url) => strip_url(&url.to_lowercase()).trim_end_matches('/').to_string(),
+            // An open panel stands in for visiting its page again
+            OmniboxAction::SwitchToPanel { .. } => strip_url(&self.detail.to_lowercase()).trim_end_matches('/').to_string(),
+            OmniboxAction::Extension { extension_id, text } => format!("extension:{}:{}", extension_id, text),
+        }
+    }
+}
+
+/// Relevance of what was typed when it is an address or a keyword.
+pub const RELEVANCE_TYPED: u32 = 1300;
+/// A keyword the user gave a bookmark or an extension registered.
+pub const RELEVANCE_KEYWORD: u32 = 1400;
+/// A page whose address starts with what is typed.
+pub const RELEVANCE_URL_PREFIX: u32 = 1200;
+pub const RELEVANCE_OPEN_PANEL: u32 = 1100;
+pub const RELEVANCE_BOOKMARK: u32 = 1000;
+pub const RELEVANCE_HISTORY: u32 = 900;
+pub const RELEVANCE_SEARCH: u32 = 600;
+
+/// Anything that suggests completions for the omnibox.
+pub trait Provider {
+    fn suggest(&self, input: &Input, out: &mut Vec<Suggestion>);
+}
+
+/// Asks every provider, then returns the best `limit` suggestions, most relevant first.
+/// Suggestions leading to the same page are merged; among equally relevant ones, earlier
+/// providers win.
+pub fn suggest(text: &str, providers: &[&dyn Provider], limit: usize) -> Vec<Suggestion> {
+    let input = Input::new(text);
+    if input.is_empty() {
+        return Vec::new();
+    }
+    let mut suggestions = Vec::new();
+    for provider in providers {
+        provider.suggest(&input, &mut suggestions);
+    }
+
+    let mut best: HashMap<String, usize> = HashMap::new();
+    let mut kept: Vec<Option<Suggestion>> = Vec::new();
+    for suggestion in suggestions {
+        let key = suggestion.dedup_key();
+        match best.get(&key) {
+            Some(&index) if kept[index].as_ref().is_some_and(|existing| existing.relevance >= suggestion.relevance) => {}
+            Some(&index) => kept[index] = Some(suggestion),
+            None => {
+                best.insert(key, kept.len());
+                kept.push(Some(suggestion));
+            }
+        }
+    }
+    let mut ranked: Vec<Suggestion> = kept.into_iter().flatten().collect();
+    // Stable, so ties keep provider order
+    ranked.sort_by_key(|suggestion| std::cmp::Reverse(suggestion.relevance));
+    ranked.truncate(limit);
+    ranked
+}
+
+/// A URL without its scheme and `www.`, for comparing what users type.
+fn strip_url(url: &str) -> &str {
+    let rest = url.split_once("://").map_or(url, |(_, rest)| rest);
+    rest.strip_prefix("www.").unwrap_or(rest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Fixed(Vec<(u32, &'static str, OmniboxAction)>);
+
+    impl Provider for Fixed {
+        fn suggest(&self, _input: &Input, out: &mut Vec<Suggestion>) {
+            for (relevance, title, action) in &self.0 {
+                out.push(Suggestion {
+                    kind: SuggestionKind::History,
+                    title: title.to_string(),
+                    detail: match action {
+                        OmniboxAction::Navigate(url) => url.clone(),
+                        _ => "https://docs.test/".to_string(),
+                    },
+                    action: action.clone(),
+                    relevance: *relevance,
+                });
+            }
+        }
+    }
+
+    #[test]
+    fn test_merges_and_ranks_providers() {
+        let navigate = |url: &str| OmniboxAction::Navigate(url.to_string());
+        let history = Fixed(vec![
+            (900, "Docs (history)", navigate("https://www.docs.test/")),
+            (800, "Blog", navigate("https://blog.test/")),
+        ]);
+        let panels = Fixed(vec![(1100, "Docs (panel)", OmniboxAction::SwitchToPanel { space: None, panel: "p".into() })]);
+        let search = Fixed(vec![(800, "blog", navigate("https://search.test/?q=blog")), (900, "Docs again", navigate("http://docs.test"))]);
+
+        let ranked = suggest("d", &[&history, &panels, &search], 10);
+        assert_eq!(ranked.iter().map(|s| s.title.as_str()).collect::<Vec<_>>(), vec!["Docs (panel)", "Blog", "blog"]);
+        assert_eq!(suggest("d", &[&history, &panels, &search], 2).len(), 2);
+        assert!(suggest("  ", &[&history], 10).is_empty());
+    }
+
+    #[test]
+    fn test_input_matching() {
+        let input = Input::new("  Rust  BOOK ");
+        assert_eq!(input.text, "Rust  BOOK");
+        assert!(input.matches(&["The Rust Programming Language", "https://doc.rust-lang.org/book/"]));
+        assert!(!input.matches(&["Rust by Example", "https://doc.rust-lang.org/rust-by-example/"]));
+
+        assert!(Input::new("git").is_url_prefix_of("https://www.github.com/"));
+        assert!(Input::new("http://github.com/r").is_url_prefix_of("https://github.com/rust-lang"));
+        assert!(!Input::new("hub").is_url_prefix_of("https://github.com/"));
+    }
+}
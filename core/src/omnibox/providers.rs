@@ -0,0 +1,223 @@
+//! The omnibox's own providers, each over a part of the profile.
+
+use super::search::{self, SearchEngine};
+use super::{
+    Input, OmniboxAction, Provider, RELEVANCE_BOOKMARK, RELEVANCE_HISTORY, RELEVANCE_KEYWORD, RELEVANCE_OPEN_PANEL,
+    RELEVANCE_SEARCH, RELEVANCE_TYPED, RELEVANCE_URL_PREFIX, Suggestion, SuggestionKind,
+};
+use crate::bookmarks::Bookmarks;
+use crate::history::History;
+use crate::spaces::{Essentials, Panel, Space};
+
+/// What was typed: the address it looks like, or else a search for it.
+pub struct TypedProvider<'a> {
+    pub engine: &'a SearchEngine,
+}
+
+impl Provider for TypedProvider<'_> {
+    fn suggest(&self, input: &Input, out: &mut Vec<Suggestion>) {
+        let (detail, url) = if search::looks_like_url(&input.text) {
+            let url = search::fixup_url(&input.text);
+            (url.clone(), url)
+        } else {
+            (format!("Search with {}", self.engine.name), self.engine.search_url(&input.text))
+        };
+        out.push(Suggestion {
+            kind: SuggestionKind::Typed,
+            title: input.text.clone(),
+            detail,
+            action: OmniboxAction::Navigate(url),
+            relevance: RELEVANCE_TYPED,
+        });
+    }
+}
+
+/// Visited pages, by frecency.
+pub struct HistoryProvider<'a> {
+    pub history: &'a History,
+    pub limit: usize,
+}
+
+impl Provider for HistoryProvider<'_> {
+    fn suggest(&self, input: &Input, out: &mut Vec<Suggestion>) {
+        let entries = match self.history.search(&input.text, self.limit) {
+            Ok(entries) => entries,
+            Err(e) => {
+                log::warn!("History search failed: {}", e);
+                return;
+            }
+        };
+        for (rank, entry) in entries.into_iter().enumerate() {
+            let relevance = if input.is_url_prefix_of(&entry.url) { RELEVANCE_URL_PREFIX } else { RELEVANCE_HISTORY };
+            out.push(Suggestion {
+                kind: SuggestionKind::History,
+                title: if entry.title.is_empty() { entry.url.clone() } else { entry.title },
+                detail: entry.url.clone(),
+                action: OmniboxAction::Navigate(entry.url),
+                relevance: relevance.saturating_sub(rank as u32 * 10),
+            });
+        }
+    }
+}
+
+/// Bookmarks matching what is typed, and a bookmark keyword's expansion.
+pub struct BookmarksProvider<'a> {
+    pub bookmarks: &'a Bookmarks,
+}
+
+impl Provider for BookmarksProvider<'_> {
+    fn suggest(&self, input: &Input, out: &mut Vec<Suggestion>) {
+        if let Some(url) = self.bookmarks.expand_keyword(&input.text) {
+            out.push(Suggestion {
+                kind: SuggestionKind::Keyword,
+                title: input.text.clone(),
+                detail: url.clone(),
+                action: OmniboxAction::Navigate(url),
+                relevance: RELEVANCE_KEYWORD,
+            });
+        }
+        for (rank, bookmark) in self.bookmarks.search(&input.text).into_iter().enumerate() {
+            // Ahead of a history entry for the same kind of match
+            let relevance = if input.is_url_prefix_of(&bookmark.url) { RELEVANCE_URL_PREFIX + 50 } else { RELEVANCE_BOOKMARK };
+            out.push(Suggestion {
+                kind: SuggestionKind::Bookmark,
+                title: bookmark.title.clone(),
+                detail: bookmark.url.clone(),
+                action: OmniboxAction::Navigate(bookmark.url.clone()),
+                relevance: relevance.saturating_sub(rank as u32 * 10),
+            });
+        }
+    }
+}
+
+/// Panels open in any space or among the essentials, offered as "switch to panel".
+pub struct PanelsProvider<'a> {
+    pub spaces: &'a [Space],
+    pub essentials: &'a Essentials,
+    /// The panel being shown, which isn't worth switching to
+    pub shown: Option<&'a str>,
+}
+
+impl Provider for PanelsProvider<'_> {
+    fn suggest(&self, input: &Input, out: &mut Vec<Suggestion>) {
+        let panels = self
+            .essentials
+            .iter()
+            .map(|panel| (None, panel))
+            .chain(self.spaces.iter().flat_map(|space| space.panels.iter().map(move |panel| (Some(space), panel))));
+        for (space, panel) in panels {
+            if Some(panel.id.as_str()) == self.shown || panel.url == "about:blank" || !input.matches(&[&panel.title, &panel.url]) {
+                continue;
+            }
+            out.push(Suggestion {
+                kind: SuggestionKind::OpenPanel,
+                title: panel_title(panel, space),
+                detail: panel.url.clone(),
+                action: OmniboxAction::SwitchToPanel { space: space.map(|space| space.id.clone()), panel: panel.id.clone() },
+                relevance: RELEVANCE_OPEN_PANEL,
+            });
+        }
+    }
+}
+
+fn panel_title(panel: &Panel, space: Option<&Space>) -> String {
+    let title = if panel.title.is_empty() { &panel.url } else { &panel.title };
+    match space {
+        Some(space) => format!("{} — {}", title, space.name),
+        None => title.to_string(),
+    }
+}
+
+/// The browser's own `about:` pages.
+pub struct AboutPagesProvider<'a> {
+    /// URL and title of each page
+    pub pages: &'a [(&'a str, &'a str)],
+}
+
+impl Provider for AboutPagesProvider<'_> {
+    fn suggest(&self, input: &Input, out: &mut Vec<Suggestion>) {
+        let typed = input.text.to_lowercase();
+        for (url, title) in self.pages {
+            let relevance = if url.starts_with(&typed) {
+                RELEVANCE_URL_PREFIX
+            } else if input.matches(&[url, title]) {
+                RELEVANCE_HISTORY
+            } else {
+                continue;
+            };
+            out.push(Suggestion {
+                kind: SuggestionKind::AboutPage,
+                title: title.to_string(),
+                detail: url.to_string(),
+                action: OmniboxAction::Navigate(url.to_string()),
+                relevance,
+            });
+        }
+    }
+}
+
+/// Completions the search engine offered for an earlier query, while they still fit what is
+/// typed.
+pub struct SearchSuggestionsProvider<'a> {
+    pub engine: &'a SearchEngine,
+    pub completions: &'a [String],
+}
+
+impl Provider for SearchSuggestionsProvider<'_> {
+    fn suggest(&self, input: &Input, out: &mut Vec<Suggestion>) {
+        let typed = input.text.to_lowercase();
+        let fitting = self.completions.iter().filter(|completion| completion.to_lowercase().starts_with(&typed));
+        for (rank, completion) in fitting.enumerate() {
+            out.push(Suggestion {
+                kind: SuggestionKind::Search,
+                title: completion.clone(),
+                detail: format!("Search with {}", self.engine.name),
+                action: OmniboxAction::Navigate(self.engine.search_url(completion)),
+                relevance: RELEVANCE_SEARCH.saturating_sub(rank as u32 * 10),
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bookmarks::Bookmark;
+    use crate::omnibox::suggest;
+
+    #[test]
+    fn test_builtin_providers() {
+        let engine = SearchEngine::default();
+        let mut bookmarks = Bookmarks::default();
+        let wiki = bookmarks.add(crate::bookmarks::ROOT_ID, Bookmark::new("Wikipedia", "https://en.wikipedia.org/wiki/Special:Search?search=%s")).unwrap();
+        bookmarks.set_keyword(&wiki, Some("w")).unwrap();
+        bookmarks.add(crate::bookmarks::ROOT_ID, Bookmark::new("Rust blog", "https://blog.rust-lang.org/")).unwrap();
+
+        let mut space = Space::new("Work");
+        let shown = space.add_panel("https://doc.rust-lang.org/book/");
+        let other = space.add_panel("https://www.rust-lang.org/");
+        space.panels[1].title = "Rust".to_string();
+        let spaces = vec![space];
+        let essentials = Essentials::default();
+        let completions = vec!["rust book".to_string(), "rustup".to_string(), "python".to_string()];
+
+        let typed = TypedProvider { engine: &engine };
+        let bookmarks = BookmarksProvider { bookmarks: &bookmarks };
+        let panels = PanelsProvider { spaces: &spaces, essentials: &essentials, shown: Some(&shown) };
+        let about = AboutPagesProvider { pages: &[("about:history", "History")] };
+        let search = SearchSuggestionsProvider { engine: &engine, completions: &completions };
+        let providers: [&dyn Provider; 5] = [&typed, &bookmarks, &panels, &about, &search];
+
+        let ranked = suggest("rust", &providers, 10);
+        let titles: Vec<&str> = ranked.iter().map(|suggestion| suggestion.title.as_str()).collect();
+        assert_eq!(titles, vec!["rust", "Rust — Work", "Rust blog", "rust book", "rustup"]);
+        assert_eq!(ranked[1].action, OmniboxAction::SwitchToPanel { space: Some(spaces[0].id.clone()), panel: other });
+        assert_eq!(ranked[0].action, OmniboxAction::Navigate("https://duckduckgo.com/?q=rust".into()));
+
+        let ranked = suggest("w rust", &providers, 1);
+        assert_eq!(ranked[0].action, OmniboxAction::Navigate("https://en.wikipedia.org/wiki/Special:Search?search=rust".into()));
+        assert_eq!(ranked[0].kind, SuggestionKind::Keyword);
+        let ranked = suggest("about:h", &providers, 10);
+        assert_eq!(ranked.iter().map(|suggestion| suggestion.detail.as_str()).collect::<Vec<_>>(), vec!["about:h", "about:history"]);
+    }
+}
@@ -0,0 +1,133 @@
+//! The search engine the omnibox sends searches to, and where it asks for suggestions.
+//!
+//! Suggestion endpoints answer in the OpenSearch suggestions format, a JSON array whose first
+//! element is the query and second the suggested completions: `["rust", ["rust book", ...]]`.
+//! Fetching is left to the shell; this builds the request and reads the answer. Nothing typed
+//! leaves the device unless the profile's `search_engine.json` sets a `suggest_url`.
+
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+const FILE_NAME: &str = "search_engine.json";
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SearchEngine {
+    pub name: String,
+    /// Results page; `%s` is replaced by the query
+    pub search_url: String,
+    /// OpenSearch suggestions endpoint; `%s` is replaced by the query. `None`, the default,
+    /// sends nothing while typing.
+    #[serde(default)]
+    pub suggest_url: Option<String>,
+}
+
+impl Default for SearchEngine {
+    fn default() -> Self {
+        Self {
+            name: "DuckDuckGo".to_string(),
+            search_url: "https://duckduckgo.com/?q=%s".to_string(),
+            // Opt-in: DuckDuckGo's is `https://duckduckgo.com/ac/?q=%s&type=list`
+            suggest_url: None,
+        }
+    }
+}
+
+impl SearchEngine {
+    /// The engine configured in a profile directory's `search_engine.json`, or the default.
+    pub fn in_profile(profile_dir: &Path) -> Self {
+        let path = profile_dir.join(FILE_NAME);
+        match fs::read_to_string(&path) {
+            Ok(data) => serde_json::from_str(&data).unwrap_or_else(|e| {
+                log::warn!("Ignoring {}: {}", path.display(), e);
+                Self::default()
+            }),
+            Err(e) => {
+                if e.kind() != io::ErrorKind::NotFound {
+                    log::warn!("Could not read {}: {}", path.display(), e);
+                }
+                Self::default()
+            }
+        }
+    }
+
+    pub fn search_url(&self, query: &str) -> String {
+        fill(&self.search_url, query)
+    }
+
+    /// Where to ask for suggestions for `query`, if anywhere.
+    pub fn suggest_url(&self, query: &str) -> Option<String> {
+        self.suggest_url.as_deref().map(|template| fill(template, query))
+    }
+}
+
+/// The completions in an OpenSearch suggestions response; nothing if it isn't one.
+pub fn parse_suggestions(body: &str) -> Vec<String> {
+    let Ok(serde_json::Value::Array(response)) = serde_json::from_str(body) else {
+        return Vec::new();
+    };
+    response
+        .get(1)
+        .and_then(|completions| completions.as_array())
+        .into_iter()
+        .flatten()
+        .filter_map(|completion| completion.as_str())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Whether typed text is meant as an address rather than a search.
+pub fn looks_like_url(text: &str) -> bool {
+    let text = text.trim();
+    if text.is_empty() || text.contains(char::is_whitespace) {
+        return false;
+    }
+    if text.starts_with("about:") || text.contains("://") {
+        return true;
+    }
+    let host = text.split(['/', '?', '#']).next().unwrap_or_default();
+    let host = host.rsplit_once(':').filter(|(_, port)| port.parse::<u16>().is_ok()).map_or(host, |(host, _)| host);
+    host == "localhost" || (host.contains('.') && !host.starts_with('.') && !host.ends_with('.'))
+}
+
+/// The address typed text stands for, given it [looks like a URL](looks_like_url).
+pub fn fixup_url(text: &str) -> String {
+    let text = text.trim();
+    if text.contains("://") || text.starts_with("about:") {
+        text.to_string()
+    } else {
+        format!("https://{}", text)
+    }
+}
+
+fn fill(template: &str, query: &str) -> String {
+    let encoded: String = url::form_urlencoded::byte_serialize(query.as_bytes()).collect();
+    template.replace("%s", &encoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_search_engine_urls_and_suggestions() {
+        let mut engine = SearchEngine::default();
+        assert_eq!(engine.search_url("rust & c++"), "https://duckduckgo.com/?q=rust+%26+c%2B%2B");
+        assert_eq!(engine.suggest_url("a b"), None);
+        engine.suggest_url = Some("https://duckduckgo.com/ac/?q=%s&type=list".to_string());
+        assert_eq!(engine.suggest_url("a b").as_deref(), Some("https://duckduckgo.com/ac/?q=a+b&type=list"));
+
+        assert_eq!(parse_suggestions(r#"["rust", ["rust book", "rustup"]]"#), vec!["rust book", "rustup"]);
+        assert!(parse_suggestions(r#"{"error": "rate limited"}"#).is_empty());
+
+        assert_eq!(fixup_url(" example.com/a "), "https://example.com/a");
+        assert_eq!(fixup_url("about:history"), "about:history");
+        for url in ["example.com", "localhost:8080/x", "about:history", "http://intranet", "10.0.0.1"] {
+            assert!(looks_like_url(url), "{}", url);
+        }
+        for query in ["rust book", "rust", "what is 1.5", "file.", ""] {
+            assert!(!looks_like_url(query), "{}", query);
+        }
+    }
+}
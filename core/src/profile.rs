@@ -0,0 +1,21 @@
+//! Profile directory: where the browser keeps spaces, rules and other user data.
+
+use std::path::PathBuf;
+
+/// `WAVE_PROFILE_DIR` if set, otherwise the platform's per-user data directory.
+pub fn default_profile_dir() -> PathBuf {
+    if let Some(dir) = std::env::var_os("WAVE_PROFILE_DIR") {
+        return PathBuf::from(dir);
+    }
+
+    let env_dir = |name: &str| std::env::var_os(name).map(PathBuf::from);
+    let data_dir = if cfg!(target_os = "windows") {
+        env_dir("APPDATA")
+    } else if cfg!(target_os = "macos") {
+        env_dir("HOME").map(|home| home.join("Library").join("Application Support"))
+    } else {
+        env_dir("XDG_DATA_HOME").or_else(|| env_dir("HOME").map(|home| home.join(".local").join("share")))
+    };
+
+    data_dir.unwrap_or_else(|| PathBuf::from(".")).join("Wave")
+}
@@ -0,0 +1,276 @@
+//! Reading list: pages saved to read later, each with a reader-mode snapshot for reading
+//! offline.
+//!
+//! The list is `reading_list.json` in the profile directory, saved the same way as the
+//! bookmarks. Snapshots are kept apart, one file per item in `reading_list/`, so the list
+//! stays small however long the articles are.
+
+pub mod reader;
+
+use crate::json_store::{self, JsonStore, JsonStoreError};
+use crate::spaces::unix_now;
+use reader::Article;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use url::Url;
+
+/// Version written by this build.
+pub const SCHEMA_VERSION: u64 = 1;
+
+const FILE_NAME: &str = "reading_list.json";
+const SNAPSHOT_DIR: &str = "reading_list";
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ReadingItem {
+    pub id: String,
+    pub url: String,
+    pub title: String,
+    /// Unix time (seconds)
+    pub added: u64,
+    pub read: bool,
+    /// Whether a reader-mode snapshot was saved
+    #[serde(default)]
+    pub has_snapshot: bool,
+    /// Words in the snapshot
+    #[serde(default)]
+    pub word_count: usize,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct ReadingList {
+    /// Newest first
+    items: Vec<ReadingItem>,
+}
+
+impl ReadingList {
+    /// Saves `url` for later. A page already on the list moves back to the top, unread.
+    pub fn add(&mut self, url: &str, title: &str) -> &mut ReadingItem {
+        let item = match self.items.iter().position(|item| same_page(&item.url, url)) {
+            Some(index) => {
+                let mut item = self.items.remove(index);
+                item.title = title.to_string();
+                item.added = unix_now();
+                item.read = false;
+                item
+            }
+            None => ReadingItem {
+                id: uuid::Uuid::new_v4().to_string(),
+                url: url.to_string(),
+                title: title.to_string(),
+                added: unix_now(),
+                read: false,
+                has_snapshot: false,
+                word_count: 0,
+            },
+        };
+        self.items.insert(0, item);
+        &mut self.items[0]
+    }
+
+    pub fn remove(&mut self, id: &str) -> Option<ReadingItem> {
+        let index = self.items.iter().position(|item| item.id == id)?;
+        Some(self.items.remove(index))
+    }
+
+    pub fn get(&self, id: &str) -> Option<&ReadingItem> {
+        self.items.iter().find(|item| item.id == id)
+    }
+
+    pub fn find_url(&self, url: &str) -> Option<&ReadingItem> {
+        self.items.iter().find(|item| same_page(&item.url, url))
+    }
+
+    /// What to show when `url` fails to load: the item saved for it, if it has a snapshot.
+    pub fn offline_copy(&self, url: &str) -> Option<&ReadingItem> {
+        self.find_url(url).filter(|item| item.has_snapshot)
+    }
+
+    /// Returns `false` if no item has this ID or it already was.
+    pub fn set_read(&mut self, id: &str, read: bool) -> bool {
+        match self.items.iter_mut().find(|item| item.id == id) {
+            Some(item) if item.read != read => {
+                item.read = read;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Newest first.
+    pub fn iter(&self) -> impl Iterator<Item = &ReadingItem> {
+        self.items.iter()
+    }
+
+    pub fn unread_count(&self) -> usize {
+        self.items.iter().filter(|item| !item.read).count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+}
+
+/// Whether two URLs name the same page, however each was written (`HTTPS://Blog.test` and
+/// `https://blog.test/` do).
+fn same_page(a: &str, b: &str) -> bool {
+    match (Url::parse(a), Url::parse(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+#[derive(Debug)]
+pub enum ReadingListError {
+    Io(io::Error),
+    Parse(serde_json::Error),
+    /// The file's schema version, newer than [`SCHEMA_VERSION`]
+    UnsupportedVersion(u64),
+}
+
+impl fmt::Display for ReadingListError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReadingListError::Io(e) => write!(f, "I/O error: {}", e),
+            ReadingListError::Parse(e) => write!(f, "malformed reading list: {}", e),
+            ReadingListError::UnsupportedVersion(version) => {
+                write!(f, "reading list has schema version {}, newer than the supported {}", version, SCHEMA_VERSION)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ReadingListError {}
+
+impl From<io::Error> for ReadingListError {
+    fn from(e: io::Error) -> Self {
+        ReadingListError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for ReadingListError {
+    fn from(e: serde_json::Error) -> Self {
+        ReadingListError::Parse(e)
+    }
+}
+
+impl From<JsonStoreError> for ReadingListError {
+    fn from(e: JsonStoreError) -> Self {
+        match e {
+            JsonStoreError::Io(e) => ReadingListError::Io(e),
+            JsonStoreError::Parse(e) => ReadingListError::Parse(e),
+            JsonStoreError::UnsupportedVersion(version) => ReadingListError::UnsupportedVersion(version),
+        }
+    }
+}
+
+pub struct ReadingListStore {
+    store: JsonStore,
+    snapshot_dir: PathBuf,
+}
+
+impl ReadingListStore {
+    pub fn new(path: impl Into<PathBuf>, snapshot_dir: impl Into<PathBuf>) -> Self {
+        Self { store: JsonStore::new(path.into(), SCHEMA_VERSION), snapshot_dir: snapshot_dir.into() }
+    }
+
+    /// The store inside a profile directory.
+    pub fn in_profile(profile_dir: &Path) -> Self {
+        Self::new(profile_dir.join(FILE_NAME), profile_dir.join(SNAPSHOT_DIR))
+    }
+
+    pub fn path(&self) -> &Path {
+        self.store.path()
+    }
+
+    /// The saved list; empty for a profile that never saved one.
+    pub fn load(&self) -> Result<ReadingList, ReadingListError> {
+        Ok(self.store.load()?)
+    }
+
+    /// Loads the saved list, falling back to an empty one.
+    pub fn load_or_default(&self) -> ReadingList {
+        self.store.load_or_default(ReadingListError::from)
+    }
+
+    pub fn save(&self, list: &ReadingList) -> Result<(), ReadingListError> {
+        Ok(self.store.save(list)?)
+    }
+
+    /// Keeps `article` as the item's snapshot, replacing any earlier one.
+    pub fn save_snapshot(&self, id: &str, article: &Article) -> Result<(), ReadingListError> {
+        Ok(json_store::write_atomically(&self.snapshot_path(id), &serde_json::to_string(article)?)?)
+    }
+
+    pub fn load_snapshot(&self, id: &str) -> Result<Article, ReadingListError> {
+        Ok(serde_json::from_str(&fs::read_to_string(self.snapshot_path(id))?)?)
+    }
+
+    /// Deletes the item's snapshot, if it has one.
+    pub fn remove_snapshot(&self, id: &str) -> Result<(), ReadingListError> {
+        match fs::remove_file(self.snapshot_path(id)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    fn snapshot_path(&self, id: &str) -> PathBuf {
+        // IDs are UUIDs; anything else can't name a file outside the directory
+        let id: String = id.chars().filter(|c| c.is_ascii_alphanumeric() || *c == '-').collect();
+        self.snapshot_dir.join(format!("{}.json", id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reader::Block;
+
+    #[test]
+    fn test_list_and_snapshots() {
+        let dir = std::env::temp_dir().join(format!("wave-reading-list-{}", uuid::Uuid::new_v4()));
+        let store = ReadingListStore::in_profile(&dir);
+        assert_eq!(store.load().unwrap(), ReadingList::default());
+
+        let mut list = ReadingList::default();
+        let first = list.add("https://a.test/post", "A post").id.clone();
+        let second = list.add("https://b.test/", "B").id.clone();
+        assert!(list.set_read(&first, true));
+        assert!(!list.set_read(&first, true));
+        assert_eq!(list.unread_count(), 1);
+
+        // Saving it again brings it back to the top, unread
+        assert_eq!(list.add("https://A.test/post", "A post, revised").id, first);
+        assert_eq!(list.iter().map(|item| item.id.as_str()).collect::<Vec<_>>(), vec![first.as_str(), second.as_str()]);
+        assert_eq!(list.unread_count(), 2);
+
+        let article = Article {
+            url: "https://a.test/post".into(),
+            title: "A post".into(),
+            byline: None,
+            blocks: vec![Block::Paragraph("Text".into())],
+        };
+        store.save_snapshot(&first, &article).unwrap();
+        assert_eq!(list.offline_copy("https://a.test/post"), None);
+        list.add("https://a.test/post", "A post").has_snapshot = true;
+        assert_eq!(list.offline_copy("https://A.TEST:443/post").map(|item| item.id.as_str()), Some(first.as_str()));
+        assert_eq!(list.offline_copy("https://b.test"), None);
+        store.save(&list).unwrap();
+        assert_eq!(store.load().unwrap(), list);
+        assert_eq!(store.load_snapshot(&first).unwrap(), article);
+
+        store.remove_snapshot(&first).unwrap();
+        store.remove_snapshot(&first).unwrap();
+        assert!(matches!(store.load_snapshot(&first), Err(ReadingListError::Io(_))));
+
+        // A list from a newer build isn't moved aside or saved over
+        fs::write(store.path(), r#"{ "version": 2, "items": [] }"#).unwrap();
+        let store = ReadingListStore::in_profile(&dir);
+        assert_eq!(store.load_or_default(), ReadingList::default());
+        assert!(matches!(store.save(&list), Err(ReadingListError::UnsupportedVersion(2))));
+        assert!(fs::read_to_string(store.path()).unwrap().contains(r#""version": 2"#));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,353 @@
+//! Reader mode: the article in a page, without the navigation, ads and scripts around it.
+//!
+//! Like Readability, this looks for the element holding most of the page's paragraph text.
+//! Each paragraph scores its container and, at half weight, the container's parent; the
+//! best-scoring container is the article, and its headings, paragraphs, lists, quotes and code
+//! are kept as plain text blocks. The HTML is read loosely, as browsers do: unclosed `<p>`s
+//! end at the next block.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Paragraphs shorter than this (in characters) are captions and buttons, not article text.
+const MIN_PARAGRAPH_LEN: usize = 25;
+
+/// Elements whose contents are never article text.
+const SKIPPED: &[&str] = &["script", "style", "noscript", "template", "svg", "nav", "header", "footer", "aside", "form", "button", "iframe"];
+const CONTAINERS: &[&str] = &["div", "article", "main", "section", "td", "body"];
+const BLOCKS: &[&str] = &["p", "h1", "h2", "h3", "h4", "h5", "h6", "li", "pre", "blockquote", "figcaption"];
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum Block {
+    /// Level 1-6
+    Heading(u8, String),
+    Paragraph(String),
+    ListItem(String),
+    Quote(String),
+    /// Whitespace kept as is
+    Code(String),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Article {
+    pub url: String,
+    pub title: String,
+    #[serde(default)]
+    pub byline: Option<String>,
+    pub blocks: Vec<Block>,
+}
+
+impl Article {
+    /// Words in the article, for "n min read".
+    pub fn word_count(&self) -> usize {
+        self.blocks
+            .iter()
+            .map(|block| match block {
+                Block::Heading(_, text) | Block::Paragraph(text) | Block::ListItem(text) | Block::Quote(text) | Block::Code(text) => {
+                    text.split_whitespace().count()
+                }
+            })
+            .sum()
+    }
+}
+
+/// A block of text found on the page, with the containers it sits in (outermost first).
+struct Found {
+    block: Block,
+    containers: Vec<usize>,
+}
+
+/// The article in `html`, or `None` if the page has no paragraph text to speak of.
+pub fn extract(html: &str, url: &str) -> Option<Article> {
+    let mut title = String::new();
+    let mut meta: HashMap<String, String> = HashMap::new();
+    let mut found: Vec<Found> = Vec::new();
+
+    // Open elements that matter: containers (with an ID) and skipped elements
+    let mut open: Vec<(String, Option<usize>)> = Vec::new();
+    let mut next_container = 0;
+    let mut skipping = 0;
+    let mut in_title = false;
+    // The block being read: its tag and text so far
+    let mut block: Option<(String, String)> = None;
+
+    let mut rest = html;
+    while !rest.is_empty() {
+        let Some(start) = rest.find('<') else {
+            text(&mut block, rest, skipping, in_title, &mut title);
+            break;
+        };
+        text(&mut block, &rest[..start], skipping, in_title, &mut title);
+        rest = &rest[start + 1..];
+        if let Some(comment) = rest.strip_prefix("!--") {
+            rest = comment.find("-->").map_or("", |end| &comment[end + 3..]);
+            continue;
+        }
+        let Some(end) = rest.find('>') else {
+            break;
+        };
+        let tag = &rest[..end];
+        rest = &rest[end + 1..];
+
+        let closing = tag.starts_with('/');
+        let name = tag.trim_start_matches('/').split(|c: char| c.is_whitespace() || c == '/').next().unwrap_or_default().to_ascii_lowercase();
+        let containers = || open.iter().filter_map(|(_, id)| *id).collect::<Vec<_>>();
+
+        // Their contents aren't markup, and may well contain `<`
+        if !closing && (name == "script" || name == "style") {
+            let close = format!("</{}", name);
+            let end = rest.as_bytes().windows(close.len()).position(|window| window.eq_ignore_ascii_case(close.as_bytes()));
+            rest = end.map_or("", |end| &rest[end..]);
+            continue;
+        }
+        if name == "title" {
+            in_title = !closing;
+            continue;
+        }
+        if name == "meta" && !closing {
+            let attributes = attributes(tag);
+            if let (Some(key), Some(content)) = (attributes.get("property").or_else(|| attributes.get("name")), attributes.get("content")) {
+                meta.insert(key.to_ascii_lowercase(), content.clone());
+            }
+            continue;
+        }
+        if name == "br" && let Some((_, text)) = &mut block {
+            text.push('\n');
+            continue;
+        }
+
+        // A quote's paragraph is the quote
+        if name == "p" && !closing && block.as_ref().is_some_and(|(tag, text)| tag == "blockquote" && text.trim().is_empty()) {
+            continue;
+        }
+        if BLOCKS.contains(&name.as_str()) {
+            // A new block ends the one being read; so does the end of any block
+            if let Some((tag, text)) = block.take() {
+                finish(&tag, &text, containers(), &mut found);
+            }
+            if !closing && skipping == 0 {
+                block = Some((name, String::new()));
+            }
+            continue;
+        }
+
+        let skipped = SKIPPED.contains(&name.as_str());
+        if !skipped && !CONTAINERS.contains(&name.as_str()) {
+            // Inline markup: links, emphasis, spans
+            continue;
+        }
+        if closing {
+            let Some(index) = open.iter().rposition(|(open_name, _)| *open_name == name) else {
+                continue;
+            };
+            if let Some((tag, text)) = block.take() {
+                finish(&tag, &text, containers(), &mut found);
+            }
+            for (closed, _) in open.drain(index..) {
+                if SKIPPED.contains(&closed.as_str()) {
+                    skipping -= 1;
+                }
+            }
+        } else if !tag.ends_with('/') {
+            if skipped {
+                skipping += 1;
+                open.push((name, None));
+            } else {
+                open.push((name, Some(next_container)));
+                next_container += 1;
+            }
+        }
+    }
+    if let Some((tag, text)) = block.take() {
+        finish(&tag, &text, open.iter().filter_map(|(_, id)| *id).collect(), &mut found);
+    }
+
+    let best = best_container(&found)?;
+    let blocks: Vec<Block> = found.into_iter().filter(|found| found.containers.contains(&best)).map(|found| found.block).collect();
+    let title = meta.get("og:title").cloned().filter(|title| !title.is_empty()).unwrap_or_else(|| collapse(&decode(&title)));
+    Some(Article {
+        url: url.to_string(),
+        title,
+        byline: meta.get("author").cloned().filter(|author| !author.is_empty()),
+        blocks,
+    })
+}
+
+fn text(block: &mut Option<(String, String)>, raw: &str, skipping: usize, in_title: bool, title: &mut String) {
+    if in_title {
+        title.push_str(raw);
+    } else if skipping == 0
+        && let Some((_, text)) = block
+    {
+        text.push_str(raw);
+    }
+}
+
+fn finish(tag: &str, raw: &str, containers: Vec<usize>, found: &mut Vec<Found>) {
+    let decoded = decode(raw);
+    let block = if tag == "pre" {
+        let code = decoded.trim_matches('\n').to_string();
+        if code.trim().is_empty() {
+            return;
+        }
+        Block::Code(code)
+    } else {
+        let text = collapse(&decoded);
+        if text.is_empty() {
+            return;
+        }
+        match tag {
+            "li" => Block::ListItem(text),
+            "blockquote" => Block::Quote(text),
+            heading if heading.len() == 2 && heading.starts_with('h') => Block::Heading(heading.as_bytes()[1] - b'0', text),
+            _ => Block::Paragraph(text),
+        }
+    };
+    found.push(Found { block, containers });
+}
+
+/// The container with the most paragraph text directly in it, counting its children's at
+/// half weight.
+fn best_container(found: &[Found]) -> Option<usize> {
+    let mut scores: HashMap<usize, usize> = HashMap::new();
+    for found in found {
+        let Block::Paragraph(text) = &found.block else {
+            continue;
+        };
+        let length = text.chars().count();
+        if length < MIN_PARAGRAPH_LEN {
+            continue;
+        }
+        let mut parents = found.containers.iter().rev();
+        if let Some(parent) = parents.next() {
+            *scores.entry(*parent).or_default() += length * 2;
+        }
+        if let Some(grandparent) = parents.next() {
+            *scores.entry(*grandparent).or_default() += length;
+        }
+    }
+    // Ties go to the outer container, which holds more of the article
+    scores.into_iter().max_by_key(|(container, score)| (*score, std::cmp::Reverse(*container))).map(|(container, _)| container)
+}
+
+/// A tag's attributes, names in lower case.
+fn attributes(tag: &str) -> HashMap<String, String> {
+    let mut attributes = HashMap::new();
+    let mut rest = tag.split_once(char::is_whitespace).map_or("", |(_, rest)| rest);
+    while let Some(equals) = rest.find('=') {
+        let name = rest[..equals].split_whitespace().last().unwrap_or_default().to_ascii_lowercase();
+        rest = rest[equals + 1..].trim_start();
+        let quote = rest.chars().next().filter(|c| *c == '"' || *c == '\'');
+        let value = match quote {
+            Some(quote) => {
+                let quoted = &rest[1..];
+                let close = quoted.find(quote).unwrap_or(quoted.len());
+                rest = quoted.get(close + 1..).unwrap_or_default();
+                &quoted[..close]
+            }
+            None => {
+                let close = rest.find(char::is_whitespace).unwrap_or(rest.len());
+                let value = &rest[..close];
+                rest = &rest[close..];
+                value
+            }
+        };
+        attributes.insert(name, decode(value));
+    }
+    attributes
+}
+
+fn collapse(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn decode(text: &str) -> String {
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let entity = rest[1..].find(';').filter(|end| *end <= 10).map(|end| &rest[1..end + 1]);
+        let character = entity.and_then(|entity| match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "nbsp" => Some(' '),
+            "mdash" => Some('—'),
+            "ndash" => Some('–'),
+            "hellip" => Some('…'),
+            _ => entity
+                .strip_prefix("#x")
+                .or_else(|| entity.strip_prefix("#X"))
+                .map_or_else(|| entity.strip_prefix('#').and_then(|code| code.parse().ok()), |hex| u32::from_str_radix(hex, 16).ok())
+                .and_then(char::from_u32),
+        });
+        match (entity, character) {
+            (Some(entity), Some(character)) => {
+                decoded.push(character);
+                rest = &rest[entity.len() + 2..];
+            }
+            _ => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PAGE: &str = r#"<!DOCTYPE html>
+<html><head>
+    <title>Fearless concurrency &mdash; Example Blog</title>
+    <meta name="author" content="Ferris">
+    <script>var ads = "<p>not text</p>";</SCRIPT>
+</head>
+<body>
+    <header><nav><ul><li>Home</li><li>About</li></ul></nav></header>
+    <div class="sidebar"><p>Subscribe to our newsletter for more posts like this one!</p></div>
+    <article>
+        <h1>Fearless concurrency</h1>
+        <p>Handling concurrent programming safely and efficiently is another of Rust&#39;s major goals.
+        <p>Ownership and type checking are <em>powerful tools</em> to help manage memory safety
+           and concurrency problems.</p>
+        <div class="figure"><img src="threads.png"><p>Threads, as a picture</p></div>
+        <pre><code>let handle = thread::spawn(|| {
+    println!("hi");
+});</code></pre>
+        <blockquote><p>Fearless is a strong word.</p></blockquote>
+        <ul><li>Threads</li><li>Message passing &amp; channels</li></ul>
+        <!-- <p>commented out paragraph that should be ignored entirely</p> -->
+    </article>
+    <footer><p>Copyright 2024, all rights reserved by the Example Blog authors.</p></footer>
+</body></html>"#;
+
+    #[test]
+    fn test_extracts_the_article() {
+        let article = extract(PAGE, "https://blog.test/concurrency").unwrap();
+        assert_eq!(article.title, "Fearless concurrency — Example Blog");
+        assert_eq!(article.byline.as_deref(), Some("Ferris"));
+        assert_eq!(
+            article.blocks,
+            vec![
+                Block::Heading(1, "Fearless concurrency".into()),
+                Block::Paragraph("Handling concurrent programming safely and efficiently is another of Rust's major goals.".into()),
+                Block::Paragraph("Ownership and type checking are powerful tools to help manage memory safety and concurrency problems.".into()),
+                Block::Paragraph("Threads, as a picture".into()),
+                Block::Code("let handle = thread::spawn(|| {\n    println!(\"hi\");\n});".into()),
+                Block::Quote("Fearless is a strong word.".into()),
+                Block::ListItem("Threads".into()),
+                Block::ListItem("Message passing & channels".into()),
+            ]
+        );
+        assert_eq!(article.word_count(), 50);
+
+        assert_eq!(extract("<html><body><p>Short.</p></body></html>", "https://a.test/"), None);
+    }
+}
@@ -1,19 +1,101 @@
 use adblock::engine::Engine;
 use adblock::lists::{FilterSet, ParseOptions};
-use adblock::request::Request;
+use adblock::request::{Request, RequestError};
+use adblock::resources::Resource;
 use serde::{Deserialize, Serialize};
-use std::sync::{Arc, Mutex};
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime};
+use url::Url;
+
+pub mod cname;
+pub mod consent;
+pub mod debounce;
+pub mod headers;
+pub mod picker;
+pub mod resources;
+pub mod safe_browsing;
+pub mod user_rules;
+
+use crate::json_store::JsonStore;
+use cname::DnsResolver;
+use consent::{ConsentPlan, ConsentRules, OptOuts};
+use debounce::{BounceTracker, DebounceRules};
+use headers::HeaderPolicy;
+use safe_browsing::{SafeBrowsingDb, ThreatType, UpdateError};
+use user_rules::CompiledRules;
+
+/// How long a CNAME chain is trusted before its host is looked up again.
+const CNAME_CACHE_TTL: Duration = Duration::from_secs(300);
+/// Hosts kept in the CNAME cache; expired entries are dropped when it fills up.
+const CNAME_CACHE_CAPACITY: usize = 1024;
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub enum Decision {
     Allow,
     Block,
     Sanitize, // Remove tracking parameters but allow the request
+    Redirect(String), // Serve this data URL instead of the request (e.g. noop.js, 1x1.gif)
+    Dangerous(ThreatType), // Known phishing/malware site, show a warning instead of navigating
+}
+
+/// How aggressively the shield protects the user. Consulted by every protection layer.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub enum ShieldLevel {
+    Off,
+    #[default]
+    Standard, // Protect without breaking sites
+    Strict,   // Maximum protection, some sites may break
+}
+
+/// Errors returned by shield queries.
+#[derive(Debug, PartialEq)]
+pub enum ShieldError {
+    /// The request or source URL could not be parsed into a request.
+    InvalidRequest { url: String, reason: RequestError },
+    /// A cookie banner rule database could not be parsed.
+    InvalidConsentRules(String),
+    /// A Safe Browsing list update was rejected; the previous list is kept.
+    InvalidSafeBrowsingUpdate(UpdateError),
 }
 
+impl fmt::Display for ShieldError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidRequest { url, reason } => write!(f, "invalid request '{}': {}", url, reason),
+            Self::InvalidConsentRules(reason) => write!(f, "invalid consent rules: {}", reason),
+            Self::InvalidSafeBrowsingUpdate(reason) => write!(f, "invalid safe browsing update: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for ShieldError {}
+
 pub struct WaveShield {
-    enabled: bool,
-    engine: Arc<Mutex<Engine>>,
+    level: ShieldLevel,
+    engine: Mutex<Engine>,
+    // Subscription filters and compiled user rules; the engine is rebuilt from both
+    filters: Mutex<(Vec<String>, Vec<String>)>,
+    // Redirect and scriptlet resources, re-applied every time the engine is rebuilt
+    resources: Vec<Resource>,
+    // Used to uncloak first-party CNAMEs pointing at trackers, when the embedder provides one
+    resolver: Option<Box<dyn DnsResolver>>,
+    cname_cache: Mutex<HashMap<String, (Vec<String>, Instant)>>,
+    // Cookie banner rules, the sites where the user turned auto-handling off, and where those
+    // are saved
+    consent: ConsentRules,
+    consent_opt_outs: OptOuts,
+    consent_opt_outs_store: Option<JsonStore>,
+    // Link decorators to skip, and sites seen only as redirect hops
+    debounce: DebounceRules,
+    bounce_tracker: Mutex<BounceTracker>,
+    header_policy: HeaderPolicy,
+    // Local phishing/malware hash-prefix lists, consulted on top-level navigation, and where
+    // updates to them are saved
+    safe_browsing: Mutex<SafeBrowsingDb>,
+    safe_browsing_store: Mutex<Option<JsonStore>>,
 }
 
 impl WaveShield {
@@ -21,28 +103,237 @@ impl WaveShield {
         // Initialize with an empty filter set for now
         // In a real scenario, we would load EasyList etc. here
         let filter_set = FilterSet::new(false);
-        let engine = Engine::from_filter_set(filter_set, true);
+        let mut engine = Engine::from_filter_set(filter_set, true);
+        let resources = resources::bundled_resources();
+        engine.use_resources(resources.clone());
 
         Self { 
-            enabled: true,
-            engine: Arc::new(Mutex::new(engine)), 
+            level: ShieldLevel::default(),
+            engine: Mutex::new(engine),
+            filters: Mutex::new((Vec::new(), Vec::new())),
+            resources,
+            resolver: None,
+            cname_cache: Mutex::new(HashMap::new()),
+            consent: ConsentRules::bundled(),
+            consent_opt_outs: OptOuts::default(),
+            consent_opt_outs_store: None,
+            debounce: DebounceRules::bundled(),
+            bounce_tracker: Mutex::new(BounceTracker::new()),
+            header_policy: HeaderPolicy::for_level(ShieldLevel::default()),
+            safe_browsing: Mutex::new(SafeBrowsingDb::new()),
+            safe_browsing_store: Mutex::new(None),
         }
     }
 
+    /// Replaces the subscription filters (EasyList etc.), keeping the user's own rules.
     pub fn load_filters(&self, filter_lines: Vec<String>) {
-        let mut engine = self.engine.lock().unwrap();
+        let mut filters = self.filters.lock().unwrap();
+        filters.0 = filter_lines;
+        self.rebuild_engine(&filters.0, &filters.1);
+    }
+
+    /// Replaces the user's rules, compiled by [`user_rules::compile`], keeping the subscriptions.
+    pub fn load_user_rules(&self, compiled: &CompiledRules) {
+        let mut filters = self.filters.lock().unwrap();
+        filters.1 = compiled.filters.clone();
+        self.rebuild_engine(&filters.0, &filters.1);
+    }
+
+    fn rebuild_engine(&self, subscriptions: &[String], user_rules: &[String]) {
         let mut filter_set = FilterSet::new(false);
-        
-        // Add filters to the set
-        filter_set.add_filters(&filter_lines, ParseOptions::default());
-        
-        // Rebuild the engine with new filters
+        filter_set.add_filters(subscriptions, ParseOptions::default());
+        filter_set.add_filters(user_rules, ParseOptions::default());
+
+        let mut engine = self.engine.lock().unwrap();
         *engine = Engine::from_filter_set(filter_set, true);
+        engine.use_resources(self.resources.clone());
+    }
+
+    pub fn level(&self) -> ShieldLevel {
+        self.level
+    }
+
+    /// Changes the protection level, resetting the header policy to that level's defaults.
+    pub fn set_level(&mut self, level: ShieldLevel) {
+        self.level = level;
+        self.header_policy = HeaderPolicy::for_level(level);
+    }
+
+    pub fn header_policy(&self) -> HeaderPolicy {
+        self.header_policy
+    }
+
+    /// Overrides individual header rewrites (e.g. dropping DNT at Standard level).
+    pub fn set_header_policy(&mut self, policy: HeaderPolicy) {
+        self.header_policy = policy;
+    }
+
+    /// Rewrites the headers of an allowed request to `url` before it is sent.
+    pub fn apply_request_headers(&self, url: &str, headers: &mut Vec<(String, String)>) {
+        self.header_policy.apply(url, headers);
+    }
+
+    /// Enables CNAME uncloaking: requests are also checked against the canonical names of their host.
+    pub fn set_dns_resolver(&mut self, resolver: impl DnsResolver + 'static) {
+        self.resolver = Some(Box::new(resolver));
+        self.cname_cache.lock().unwrap().clear();
+    }
+
+    /// Adds cookie banner rules (Consent-O-Matic JSON) on top of the bundled database.
+    pub fn load_consent_rules(&mut self, json: &str) -> Result<(), ShieldError> {
+        let rules = ConsentRules::from_json(json).map_err(|e| ShieldError::InvalidConsentRules(e.to_string()))?;
+        self.consent.extend(rules);
+        Ok(())
+    }
+
+    /// Keeps the sites where cookie banner handling is off in a profile directory: the ones
+    /// saved there replace the current ones, and every change is saved there.
+    pub fn open_cookie_banner_opt_outs(&mut self, profile_dir: &Path) {
+        let store = JsonStore::new(profile_dir.join(consent::OPT_OUTS_FILE_NAME), consent::OPT_OUTS_SCHEMA_VERSION);
+        self.consent_opt_outs = store.load_or_default(|e| e);
+        self.consent_opt_outs_store = Some(store);
+    }
+
+    /// Turns cookie banner auto-handling on or off for a site. `host` stands for its whole
+    /// registrable domain, so `www.mysite.com` and `mysite.com` are the same site.
+    pub fn set_cookie_banner_handling(&mut self, host: &str, enabled: bool) {
+        let site = site_of_host(host);
+        let changed = if enabled {
+            self.consent_opt_outs.sites.remove(&site)
+        } else {
+            self.consent_opt_outs.sites.insert(site)
+        };
+        if changed
+            && let Some(store) = &self.consent_opt_outs_store
+            && let Err(e) = store.save(&self.consent_opt_outs)
+        {
+            log::warn!("Could not save cookie banner settings to {}: {}", store.path().display(), e);
+        }
+    }
+
+    /// Whether the user left cookie banner auto-handling on for the site of `host`.
+    pub fn cookie_banner_handling(&self, host: &str) -> bool {
+        !self.consent_opt_outs.sites.contains(&site_of_host(host))
     }
 
-    pub fn should_allow_request(&self, url: &str, source_url: &str, resource_type: &str) -> Decision {
-        if !self.enabled {
-            return Decision::Allow;
+    /// Sites where the user turned cookie banner auto-handling off, in order.
+    pub fn cookie_banner_opt_outs(&self) -> impl Iterator<Item = &str> {
+        self.consent_opt_outs.sites.iter().map(String::as_str)
+    }
+
+    fn cookie_banner_handling_enabled(&self, page_url: &str) -> bool {
+        if self.level == ShieldLevel::Off {
+            return false;
+        }
+        !site_of(page_url).is_some_and(|site| self.consent_opt_outs.sites.contains(&site))
+    }
+
+    /// Plans the engine runs in a document to reject cookie banners.
+    pub fn cookie_banner_plans(&self, page_url: &str) -> Vec<ConsentPlan> {
+        if !self.cookie_banner_handling_enabled(page_url) {
+            return Vec::new();
+        }
+        self.consent.plans_for(page_url)
+    }
+
+    /// CSS selectors to hide on a document: cosmetic filters plus known cookie banners.
+    pub fn hide_selectors(&self, page_url: &str) -> Vec<String> {
+        if self.level == ShieldLevel::Off {
+            return Vec::new();
+        }
+
+        let engine = self.engine.lock().unwrap();
+        let mut selectors: Vec<String> = engine.url_cosmetic_resources(page_url).hide_selectors.into_iter().collect();
+        selectors.sort();
+        if self.cookie_banner_handling_enabled(page_url) {
+            selectors.extend(self.consent.hide_selectors(page_url));
+        }
+        selectors
+    }
+
+    /// Where a top-level navigation should really go when `url` is a known link decorator.
+    pub fn debounce_navigation(&self, url: &str) -> Option<String> {
+        if self.level == ShieldLevel::Off {
+            return None;
+        }
+        let destination = self.debounce.debounce(url)?;
+        log::info!("Shield: Debounced {} -> {}", url, destination);
+        Some(destination.to_string())
+    }
+
+    /// A top-level navigation went through `url` and was redirected away without user activation.
+    pub fn record_redirect_hop(&self, url: &str) {
+        if let Some(site) = site_of(url) {
+            self.bounce_tracker.lock().unwrap().record_hop(&site, SystemTime::now());
+        }
+    }
+
+    /// The user interacted with a page at `url`.
+    pub fn record_user_interaction(&self, url: &str) {
+        if let Some(site) = site_of(url) {
+            self.bounce_tracker.lock().unwrap().record_interaction(&site);
+        }
+    }
+
+    /// Sites (registrable domains) whose cookies and storage the engine should clear as of
+    /// `now`, because they only ever bounced us.
+    pub fn bounce_sites_to_clear(&self, now: SystemTime) -> Vec<String> {
+        if self.level == ShieldLevel::Off {
+            return Vec::new();
+        }
+        self.bounce_tracker.lock().unwrap().take_sites_to_clear(now)
+    }
+
+    /// Keeps the Safe Browsing lists in a profile directory: the ones saved there replace the
+    /// current lists, and every update is saved there.
+    pub fn open_safe_browsing(&self, profile_dir: &Path) {
+        let store = JsonStore::new(profile_dir.join(safe_browsing::FILE_NAME), safe_browsing::SCHEMA_VERSION);
+        *self.safe_browsing.lock().unwrap() = store.load_or_default(|e| e);
+        *self.safe_browsing_store.lock().unwrap() = Some(store);
+    }
+
+    /// Applies a Safe Browsing `fetchThreatListUpdates` response to the local lists.
+    pub fn apply_safe_browsing_update(&self, json: &str) -> Result<(), ShieldError> {
+        let mut db = self.safe_browsing.lock().unwrap();
+        db.apply_update(json).map_err(ShieldError::InvalidSafeBrowsingUpdate)?;
+        if let Some(store) = &*self.safe_browsing_store.lock().unwrap()
+            && let Err(e) = store.save(&*db)
+        {
+            log::warn!("Could not save the Safe Browsing lists to {}: {}", store.path().display(), e);
+        }
+        Ok(())
+    }
+
+    /// The request body for the next Safe Browsing update; see [`SafeBrowsingDb::update_request`].
+    pub fn safe_browsing_update_request(&self) -> String {
+        self.safe_browsing.lock().unwrap().update_request()
+    }
+
+    /// Whether the filter lists classify `url` as a tracker, regardless of the shield level.
+    /// Used by the cookie layer to decide third-party cookie access.
+    pub fn is_tracker(&self, url: &str, source_url: &str, resource_type: ResourceType) -> bool {
+        let engine = self.engine.lock().unwrap();
+        Request::new(url, source_url, resource_type.as_str())
+            .map(|request| engine.check_network_request(&request).matched)
+            .unwrap_or(false)
+    }
+
+    pub fn should_allow_request(
+        &self,
+        url: &str,
+        source_url: &str,
+        resource_type: ResourceType,
+    ) -> Result<Decision, ShieldError> {
+        if self.level == ShieldLevel::Off {
+            return Ok(Decision::Allow);
+        }
+
+        // Known-bad sites are stopped before anything else on top-level navigation
+        if resource_type == ResourceType::Document
+            && let Some(threat) = self.safe_browsing.lock().unwrap().check_url(url)
+        {
+            log::warn!("Shield: {} is on the {:?} list", url, threat);
+            return Ok(Decision::Dangerous(threat));
         }
 
         let engine = self.engine.lock().unwrap();
@@ -50,26 +341,187 @@ impl WaveShield {
         // Check using the adblock engine
         // 'source_url' is the page making the request (e.g. "https://example.com")
         // 'url' is the request being made (e.g. "https://ads.doubleclick.net/...")
-        let request = Request::new(url, source_url, resource_type).unwrap_or_else(|_| Request::new("http://example.com", "", "").unwrap());
+        let request = Request::new(url, source_url, resource_type.as_str()).map_err(|reason| {
+            ShieldError::InvalidRequest {
+                url: url.to_string(),
+                reason,
+            }
+        })?;
         let check_result = engine.check_network_request(&request);
 
         if check_result.matched {
-            return Decision::Block;
+            // $redirect= rules still block the original request, but hand back a stub body
+            return Ok(match check_result.redirect {
+                Some(data_url) => Decision::Redirect(data_url),
+                None => Decision::Block,
+            });
         }
 
-        Decision::Allow
+        // Only first-party hosts can cloak a tracker, and an exception on the literal URL wins
+        // over anything found behind its CNAMEs
+        let may_be_cloaked = self.resolver.is_some()
+            && !request.is_third_party
+            && engine.check_network_request_subset(&request, false, true).exception.is_none();
+        // The DNS lookup can block; other requests shouldn't wait behind it
+        drop(engine);
+
+        if may_be_cloaked && let Some(decision) = self.uncloaked_decision(url, source_url, resource_type) {
+            return Ok(decision);
+        }
+
+        Ok(Decision::Allow)
+    }
+
+    /// The decision for a request whose host is an alias of a blocked tracker, if it is one.
+    fn uncloaked_decision(&self, url: &str, source_url: &str, resource_type: ResourceType) -> Option<Decision> {
+        let parsed = Url::parse(url).ok()?;
+        // IP literals have no CNAMEs
+        let host = parsed.domain()?;
+        let chain = self.canonical_names(host)?;
+
+        let engine = self.engine.lock().unwrap();
+        chain.iter().find_map(|canonical_name| {
+            let uncloaked = cname::uncloak_url(&parsed, canonical_name)?;
+            // Aliases within the same site (www -> cdn.mysite.com) are not cloaking
+            let same_site = Request::new(&uncloaked, url, resource_type.as_str())
+                .map(|r| !r.is_third_party)
+                .unwrap_or(true);
+            if same_site {
+                return None;
+            }
+
+            let request = Request::new(&uncloaked, source_url, resource_type.as_str()).ok()?;
+            let check_result = engine.check_network_request(&request);
+            if !check_result.matched {
+                return None;
+            }
+            log::info!("Shield: Uncloaked {} -> {}", host, canonical_name);
+            Some(match check_result.redirect {
+                Some(data_url) => Decision::Redirect(data_url),
+                None => Decision::Block,
+            })
+        })
+    }
+
+    /// The CNAME chain of `host`, looked up at most once per [`CNAME_CACHE_TTL`]. Failed lookups
+    /// aren't cached, and fail open.
+    fn canonical_names(&self, host: &str) -> Option<Vec<String>> {
+        let resolver = self.resolver.as_ref()?;
+        if let Some((chain, resolved_at)) = self.cname_cache.lock().unwrap().get(host)
+            && resolved_at.elapsed() < CNAME_CACHE_TTL
+        {
+            return Some(chain.clone());
+        }
+
+        let chain = match resolver.canonical_names(host) {
+            Ok(chain) => chain,
+            Err(e) => {
+                log::debug!("Shield: CNAME lookup for {} failed: {}", host, e);
+                return None;
+            }
+        };
+        let mut cache = self.cname_cache.lock().unwrap();
+        if cache.len() >= CNAME_CACHE_CAPACITY {
+            cache.retain(|_, (_, resolved_at)| resolved_at.elapsed() < CNAME_CACHE_TTL);
+            if cache.len() >= CNAME_CACHE_CAPACITY {
+                cache.clear();
+            }
+        }
+        cache.insert(host.to_string(), (chain.clone(), Instant::now()));
+        Some(chain)
+    }
+
+    /// Scriptlet source (from `##+js()` rules) to inject into a document before its own scripts run.
+    pub fn injected_script(&self, page_url: &str) -> Option<String> {
+        if self.level == ShieldLevel::Off {
+            return None;
+        }
+
+        let engine = self.engine.lock().unwrap();
+        let script = engine.url_cosmetic_resources(page_url).injected_script;
+        if script.is_empty() { None } else { Some(script) }
     }
 }
 
+/// The registrable domain (eTLD+1) of a URL, used to tell same-site from cross-site.
+pub(crate) fn site_of(url: &str) -> Option<String> {
+    adblock::url_parser::parse_url(url).map(|parsed| parsed.domain().to_string())
+}
+
+fn site_of_host(host: &str) -> String {
+    site_of(&format!("https://{}/", host)).unwrap_or_else(|| host.to_string())
+}
+
+impl Default for WaveShield {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The kind of resource being fetched, following the Fetch spec's request destinations.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
 pub enum ResourceType {
+    Document,    // Top-level navigation
+    Subdocument, // iframe / frame
     Script,
-    Image,
-    XHR,
-    Frame,
     Stylesheet,
+    Image,
+    Media, // audio / video / track
+    Font,
+    Object, // object / embed
+    Websocket,
+    Ping,   // <a ping>
+    Beacon, // navigator.sendBeacon
+    Xhr,    // XMLHttpRequest
+    Fetch,  // fetch()
+    CspReport,
+    Manifest,
     Other,
 }
 
+impl ResourceType {
+    pub const ALL: [ResourceType; 16] = [
+        Self::Document,
+        Self::Subdocument,
+        Self::Script,
+        Self::Stylesheet,
+        Self::Image,
+        Self::Media,
+        Self::Font,
+        Self::Object,
+        Self::Websocket,
+        Self::Ping,
+        Self::Beacon,
+        Self::Xhr,
+        Self::Fetch,
+        Self::CspReport,
+        Self::Manifest,
+        Self::Other,
+    ];
+
+    /// The request type string understood by the adblock engine.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Document => "document",
+            Self::Subdocument => "subdocument",
+            Self::Script => "script",
+            Self::Stylesheet => "stylesheet",
+            Self::Image => "image",
+            Self::Media => "media",
+            Self::Font => "font",
+            Self::Object => "object",
+            Self::Websocket => "websocket",
+            Self::Ping => "ping",
+            Self::Beacon => "beacon",
+            // Filter lists match fetch() with the $xhr option as well
+            Self::Xhr | Self::Fetch => "xmlhttprequest",
+            Self::CspReport => "csp_report",
+            Self::Manifest => "web_manifest",
+            Self::Other => "other",
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -92,11 +544,11 @@ mod tests {
         let decision = shield.should_allow_request(
             "https://ads.badsite.com/banner.js", 
             "https://mysite.com", 
-            "script"
+            ResourceType::Script,
         );
 
         match decision {
-            Decision::Block => assert!(true),
+            Ok(Decision::Block) => (),
             _ => panic!("Should have blocked the ad url"),
         }
 
@@ -104,12 +556,371 @@ mod tests {
         let decision_allow = shield.should_allow_request(
             "https://mysite.com/style.css", 
             "https://mysite.com", 
-            "stylesheet"
+            ResourceType::Stylesheet,
         );
 
         match decision_allow {
-            Decision::Allow => assert!(true),
+            Ok(Decision::Allow) => (),
             _ => panic!("Should have allowed the safe url"),
         }
     }
+
+    #[test]
+    fn test_user_rules_survive_subscription_updates() {
+        let shield = WaveShield::new();
+        let compiled = user_rules::compile("[news.example.com]\n||cdn.tracker.test^\n", std::path::Path::new("user.rules"), &|_| {
+            Err(std::io::Error::from(std::io::ErrorKind::NotFound))
+        });
+        shield.load_user_rules(&compiled);
+        shield.load_filters(vec!["||ads.badsite.com^".to_string()]);
+
+        let check = |url: &str, source: &str| shield.should_allow_request(url, source, ResourceType::Script).unwrap();
+        assert_eq!(check("https://cdn.tracker.test/t.js", "https://news.example.com/"), Decision::Block);
+        assert_eq!(check("https://cdn.tracker.test/t.js", "https://other.test/"), Decision::Allow);
+        assert_eq!(check("https://ads.badsite.com/a.js", "https://other.test/"), Decision::Block);
+    }
+
+    #[test]
+    fn test_invalid_request_url_is_an_error() {
+        let shield = WaveShield::new();
+        shield.load_filters(vec!["||example.com^".to_string()]);
+
+        let result = shield.should_allow_request("not a url", "https://mysite.com", ResourceType::Script);
+        assert!(matches!(result, Err(ShieldError::InvalidRequest { .. })));
+    }
+
+    #[test]
+    fn test_generic_rule_matches_every_resource_type() {
+        let shield = WaveShield::new();
+        shield.load_filters(vec!["||tracker.test^".to_string()]);
+
+        // CSP reports are outside the scope of network filters, see below
+        for resource_type in ResourceType::ALL.into_iter().filter(|t| *t != ResourceType::CspReport) {
+            let decision = shield
+                .should_allow_request("https://tracker.test/t", "https://mysite.com", resource_type)
+                .unwrap();
+            assert_eq!(decision, Decision::Block, "{:?} should be blocked", resource_type);
+        }
+    }
+
+    #[test]
+    fn test_type_option_matches_only_its_resource_type() {
+        // Every resource type paired with the filter option that targets it
+        let cases = [
+            (ResourceType::Document, "document"),
+            (ResourceType::Subdocument, "subdocument"),
+            (ResourceType::Script, "script"),
+            (ResourceType::Stylesheet, "stylesheet"),
+            (ResourceType::Image, "image"),
+            (ResourceType::Media, "media"),
+            (ResourceType::Font, "font"),
+            (ResourceType::Object, "object"),
+            (ResourceType::Websocket, "websocket"),
+            (ResourceType::Ping, "ping"),
+            (ResourceType::Beacon, "ping"),
+            (ResourceType::Xhr, "xhr"),
+            (ResourceType::Fetch, "xhr"),
+            (ResourceType::Manifest, "other"),
+            (ResourceType::Other, "other"),
+        ];
+
+        for (resource_type, option) in cases {
+            let shield = WaveShield::new();
+            shield.load_filters(vec![format!("||tracker.test^${}", option)]);
+
+            let blocked = shield
+                .should_allow_request("https://tracker.test/t", "https://mysite.com", resource_type)
+                .unwrap();
+            assert_eq!(blocked, Decision::Block, "${} should block {:?}", option, resource_type);
+
+            let other = if resource_type == ResourceType::Image { ResourceType::Script } else { ResourceType::Image };
+            let allowed = shield
+                .should_allow_request("https://tracker.test/t", "https://mysite.com", other)
+                .unwrap();
+            assert_eq!(allowed, Decision::Allow, "${} should not block {:?}", option, other);
+        }
+    }
+
+    #[test]
+    fn test_csp_report_is_not_matched_by_network_filters() {
+        let shield = WaveShield::new();
+        shield.load_filters(vec![
+            "||tracker.test^".to_string(),
+            "||tracker.test^$script,image,xhr,other".to_string(),
+        ]);
+
+        let decision = shield
+            .should_allow_request("https://tracker.test/report", "https://mysite.com", ResourceType::CspReport)
+            .unwrap();
+        assert_eq!(decision, Decision::Allow);
+    }
+
+    #[test]
+    fn test_redirect_rule_serves_bundled_resource() {
+        let shield = WaveShield::new();
+        shield.load_filters(vec![
+            "||ads.badsite.com/pixel$image,redirect=1x1.gif".to_string(),
+            "||ads.badsite.com/ads.js$script,redirect=noopjs".to_string(),
+        ]);
+
+        let pixel = shield
+            .should_allow_request("https://ads.badsite.com/pixel", "https://mysite.com", ResourceType::Image)
+            .unwrap();
+        assert_eq!(
+            pixel,
+            Decision::Redirect("data:image/gif;base64,R0lGODlhAQABAIAAAAAAAP///yH5BAEAAAAALAAAAAABAAEAAAIBRAA7".to_string())
+        );
+
+        // Looked up through an alias, resolved to the noop.js body
+        let script = shield
+            .should_allow_request("https://ads.badsite.com/ads.js", "https://mysite.com", ResourceType::Script)
+            .unwrap();
+        match script {
+            Decision::Redirect(data_url) => assert!(data_url.starts_with("data:application/javascript;base64,")),
+            other => panic!("Expected a redirect, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_scriptlet_injection() {
+        let shield = WaveShield::new();
+        shield.load_filters(vec!["mysite.com##+js(set-constant, adsEnabled, false)".to_string()]);
+
+        let script = shield.injected_script("https://mysite.com/article").expect("Should inject a scriptlet");
+        assert!(script.contains("const chain = 'adsEnabled';"));
+        assert!(script.contains("const raw = 'false';"));
+
+        assert_eq!(shield.injected_script("https://othersite.com"), None);
+    }
+
+
+    struct StubResolver(Vec<(&'static str, Vec<&'static str>)>);
+
+    impl DnsResolver for StubResolver {
+        fn canonical_names(&self, host: &str) -> std::io::Result<Vec<String>> {
+            Ok(self
+                .0
+                .iter()
+                .find(|(name, _)| *name == host)
+                .map(|(_, chain)| chain.iter().map(|c| c.to_string()).collect())
+                .unwrap_or_default())
+        }
+    }
+
+    #[test]
+    fn test_cname_uncloaking() {
+        let mut shield = WaveShield::new();
+        shield.load_filters(vec!["||tracker.test^".to_string()]);
+        shield.set_dns_resolver(StubResolver(vec![
+            ("metrics.mysite.com", vec!["mysite.tracker-edge.net", "eu.tracker.test"]),
+            ("cdn.mysite.com", vec!["mysite.cdn-provider.net"]),
+        ]));
+
+        let cloaked = shield
+            .should_allow_request("https://metrics.mysite.com/collect", "https://mysite.com", ResourceType::Script)
+            .unwrap();
+        assert_eq!(cloaked, Decision::Block);
+
+        let cdn = shield
+            .should_allow_request("https://cdn.mysite.com/app.js", "https://mysite.com", ResourceType::Script)
+            .unwrap();
+        assert_eq!(cdn, Decision::Allow);
+    }
+
+    #[test]
+    fn test_cname_uncloaking_respects_exceptions() {
+        let mut shield = WaveShield::new();
+        shield.load_filters(vec![
+            "||tracker.test^".to_string(),
+            "@@||metrics.mysite.com^".to_string(),
+        ]);
+        shield.set_dns_resolver(StubResolver(vec![("metrics.mysite.com", vec!["eu.tracker.test"])]));
+
+        let decision = shield
+            .should_allow_request("https://metrics.mysite.com/collect", "https://mysite.com", ResourceType::Script)
+            .unwrap();
+        assert_eq!(decision, Decision::Allow);
+    }
+
+    #[test]
+    fn test_cname_lookups_are_cached_and_first_party_only() {
+        use std::sync::Arc;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        struct CountingResolver(Arc<AtomicUsize>);
+        impl DnsResolver for CountingResolver {
+            fn canonical_names(&self, _host: &str) -> std::io::Result<Vec<String>> {
+                self.0.fetch_add(1, Ordering::SeqCst);
+                Ok(vec!["eu.tracker.test".to_string()])
+            }
+        }
+
+        let lookups = Arc::new(AtomicUsize::new(0));
+        let mut shield = WaveShield::new();
+        shield.load_filters(vec!["||tracker.test^".to_string()]);
+        shield.set_dns_resolver(CountingResolver(lookups.clone()));
+
+        for _ in 0..3 {
+            let decision = shield
+                .should_allow_request("https://metrics.mysite.com/collect", "https://mysite.com", ResourceType::Script)
+                .unwrap();
+            assert_eq!(decision, Decision::Block);
+        }
+        assert_eq!(lookups.load(Ordering::SeqCst), 1);
+
+        // Third-party hosts and IP literals are never looked up
+        for url in ["https://cdn.othersite.com/app.js", "https://192.0.2.1/app.js"] {
+            let decision = shield.should_allow_request(url, "https://mysite.com", ResourceType::Script).unwrap();
+            assert_eq!(decision, Decision::Allow);
+        }
+        assert_eq!(lookups.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_cname_uncloaking_serves_redirect_resource() {
+        let mut shield = WaveShield::new();
+        shield.load_filters(vec!["||tracker.test^$script,redirect=noopjs".to_string()]);
+        shield.set_dns_resolver(StubResolver(vec![("metrics.mysite.com", vec!["eu.tracker.test"])]));
+
+        let decision = shield
+            .should_allow_request("https://metrics.mysite.com/t.js", "https://mysite.com", ResourceType::Script)
+            .unwrap();
+        match decision {
+            Decision::Redirect(data_url) => assert!(data_url.starts_with("data:application/javascript;base64,")),
+            other => panic!("Expected a redirect, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_cname_resolver_failure_fails_open() {
+        struct FailingResolver;
+        impl DnsResolver for FailingResolver {
+            fn canonical_names(&self, _host: &str) -> std::io::Result<Vec<String>> {
+                Err(std::io::Error::other("SERVFAIL"))
+            }
+        }
+
+        let mut shield = WaveShield::new();
+        shield.load_filters(vec!["||tracker.test^".to_string()]);
+        shield.set_dns_resolver(FailingResolver);
+
+        let decision = shield
+            .should_allow_request("https://metrics.mysite.com/collect", "https://mysite.com", ResourceType::Script)
+            .unwrap();
+        assert_eq!(decision, Decision::Allow);
+    }
+
+
+    #[test]
+    fn test_cookie_banner_handling_and_opt_out() {
+        let mut shield = WaveShield::new();
+        shield.load_filters(vec!["mysite.com###newsletter-popup".to_string()]);
+
+        let selectors = shield.hide_selectors("https://mysite.com");
+        assert!(selectors.contains(&"#newsletter-popup".to_string()));
+        assert!(selectors.contains(&"#CybotCookiebotDialog".to_string()));
+        assert!(!shield.cookie_banner_plans("https://mysite.com").is_empty());
+
+        shield.set_cookie_banner_handling("www.mysite.com", false);
+        assert_eq!(shield.hide_selectors("https://mysite.com"), vec!["#newsletter-popup".to_string()]);
+        assert!(shield.cookie_banner_plans("https://mysite.com").is_empty());
+        assert!(shield.cookie_banner_plans("https://shop.mysite.com").is_empty());
+        assert!(!shield.cookie_banner_plans("https://othersite.com").is_empty());
+        assert!(!shield.cookie_banner_handling("mysite.com"));
+        assert_eq!(shield.cookie_banner_opt_outs().collect::<Vec<_>>(), vec!["mysite.com"]);
+
+        // Opt-outs kept in the profile outlive the shield
+        let dir = std::env::temp_dir().join(format!("wave-consent-{}", uuid::Uuid::new_v4()));
+        let mut shield = WaveShield::new();
+        shield.open_cookie_banner_opt_outs(&dir);
+        shield.set_cookie_banner_handling("shop.mysite.com", false);
+        shield.set_cookie_banner_handling("othersite.com", false);
+        shield.set_cookie_banner_handling("othersite.com", true);
+        let mut reopened = WaveShield::new();
+        reopened.open_cookie_banner_opt_outs(&dir);
+        assert_eq!(reopened.cookie_banner_opt_outs().collect::<Vec<_>>(), vec!["mysite.com"]);
+        assert!(reopened.cookie_banner_plans("https://www.mysite.com").is_empty());
+        std::fs::remove_dir_all(dir).unwrap();
+
+        assert!(matches!(
+            shield.load_consent_rules("not json"),
+            Err(ShieldError::InvalidConsentRules(_))
+        ));
+    }
+
+
+    #[test]
+    fn test_debounce_navigation() {
+        let mut shield = WaveShield::new();
+        let decorated = "https://l.facebook.com/l.php?u=https%3A%2F%2Fexample.com%2F";
+        assert_eq!(shield.debounce_navigation(decorated), Some("https://example.com/".to_string()));
+        assert_eq!(shield.debounce_navigation("https://example.com/"), None);
+
+        shield.set_level(ShieldLevel::Off);
+        assert_eq!(shield.debounce_navigation(decorated), None);
+    }
+
+
+    #[test]
+    fn test_dangerous_navigation() {
+        use base64::{Engine as _, prelude::BASE64_STANDARD};
+        use sha2::{Digest, Sha256};
+
+        let shield = WaveShield::new();
+        let hash = Sha256::digest(b"phish.test/");
+        let update = format!(
+            r#"{{ "listUpdateResponses": [{{ "threatType": "SOCIAL_ENGINEERING", "responseType": "FULL_UPDATE",
+                 "additions": [{{ "rawHashes": {{ "prefixSize": 32, "rawHashes": "{}" }} }}] }}] }}"#,
+            BASE64_STANDARD.encode(hash)
+        );
+        shield.apply_safe_browsing_update(&update).unwrap();
+
+        let navigation = shield
+            .should_allow_request("https://phish.test/login", "https://phish.test/login", ResourceType::Document)
+            .unwrap();
+        assert_eq!(navigation, Decision::Dangerous(ThreatType::SocialEngineering));
+
+        // Only top-level navigations get the interstitial
+        let image = shield
+            .should_allow_request("https://phish.test/logo.png", "https://mysite.com", ResourceType::Image)
+            .unwrap();
+        assert_eq!(image, Decision::Allow);
+
+        assert!(matches!(
+            shield.apply_safe_browsing_update("{"),
+            Err(ShieldError::InvalidSafeBrowsingUpdate(_))
+        ));
+
+        // Lists kept in the profile are there on the next launch
+        let dir = std::env::temp_dir().join(format!("wave-safe-browsing-{}", uuid::Uuid::new_v4()));
+        shield.open_safe_browsing(&dir);
+        assert_eq!(shield.safe_browsing.lock().unwrap().check_url("https://phish.test/"), None);
+        shield.apply_safe_browsing_update(&update).unwrap();
+        let next_launch = WaveShield::new();
+        next_launch.open_safe_browsing(&dir);
+        let navigation = next_launch
+            .should_allow_request("https://phish.test/login", "https://phish.test/login", ResourceType::Document)
+            .unwrap();
+        assert_eq!(navigation, Decision::Dangerous(ThreatType::SocialEngineering));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+
+    #[test]
+    fn test_tracker_classification_ignores_level() {
+        let mut shield = WaveShield::new();
+        shield.load_filters(vec![
+            "||tracker.test^$third-party".to_string(),
+            "||analytics.test^$script".to_string(),
+        ]);
+        shield.set_level(ShieldLevel::Off);
+
+        assert!(shield.is_tracker("https://tracker.test/sync", "https://mysite.com", ResourceType::Image));
+        assert!(!shield.is_tracker("https://cdn.mysite.com/app.js", "https://mysite.com", ResourceType::Script));
+
+        // Type-restricted rules count for the type they name
+        assert!(shield.is_tracker("https://analytics.test/a.js", "https://mysite.com", ResourceType::Script));
+        assert!(!shield.is_tracker("https://analytics.test/a.js", "https://mysite.com", ResourceType::Image));
+    }
+
 }
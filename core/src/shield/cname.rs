@@ -0,0 +1,26 @@
+//! CNAME uncloaking.
+//!
+//! Trackers are often served from a first-party subdomain (e.g. `metrics.mysite.com`) that is
+//! a CNAME for the tracker's own host. Filters only see the literal URL, so the shield asks a
+//! [`DnsResolver`] for the canonical name chain and re-checks the request against each name.
+
+use std::io;
+use url::Url;
+
+/// Resolves the CNAME chain of a hostname.
+///
+/// Implemented by the embedder on top of its DNS stack (the engine's network layer or a DoH
+/// client). Only first-party hosts are looked up, outside the engine lock, and the shield
+/// caches answers for a few minutes.
+pub trait DnsResolver: Send + Sync {
+    /// The canonical names `host` points to, in resolution order, not including `host` itself.
+    /// A host without a CNAME record resolves to an empty chain.
+    fn canonical_names(&self, host: &str) -> io::Result<Vec<String>>;
+}
+
+/// Rewrites `url` so that its host is `canonical_name`, keeping scheme, path and query intact.
+pub fn uncloak_url(url: &Url, canonical_name: &str) -> Option<String> {
+    let mut uncloaked = url.clone();
+    uncloaked.set_host(Some(canonical_name.trim_end_matches('.'))).ok()?;
+    Some(uncloaked.to_string())
+}
@@ -0,0 +1,369 @@
+//! Cookie banner handling.
+//!
+//! Rules follow the Consent-O-Matic format: each consent management platform (CMP) has
+//! detectors (CSS or URL matchers telling whether it is present and showing) and named methods
+//! (`HIDE_CMP`, `OPEN_OPTIONS`, `DO_CONSENT`, `SAVE_CONSENT`) made of DOM actions. The shield
+//! turns them into a [`ConsentPlan`] that rejects every consent category, which the engine runs
+//! in the page once a detector matches. `hide` actions double as cosmetic filters so banners
+//! never flash on screen.
+//!
+//! Sites where the user turned the handling off are kept in the profile's `cookie_banners.json`.
+
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Rule database shipped with Wave.
+const BUNDLED_RULES: &str = include_str!("consent_rules.json");
+
+/// Version of the opt-out file this build writes.
+pub(crate) const OPT_OUTS_SCHEMA_VERSION: u64 = 1;
+/// Where the opt-outs are kept in the profile directory.
+pub(crate) const OPT_OUTS_FILE_NAME: &str = "cookie_banners.json";
+
+/// Methods run, in order, to reject consent. Missing methods are skipped.
+const REJECT_SEQUENCE: [&str; 4] = ["HIDE_CMP", "OPEN_OPTIONS", "DO_CONSENT", "SAVE_CONSENT"];
+
+/// A DOM element targeted by a matcher or action.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Target {
+    pub selector: String,
+    /// Only elements whose text contains one of these strings
+    #[serde(default, deserialize_with = "one_or_many", skip_serializing_if = "Vec::is_empty")]
+    pub text_filter: Vec<String>,
+    /// Only elements that are currently displayed
+    #[serde(default)]
+    pub display_filter: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Matcher {
+    Css { target: Target },
+    Checkbox { target: Target },
+    Url {
+        #[serde(deserialize_with = "one_or_many")]
+        url: Vec<String>,
+    },
+    #[serde(other)]
+    Unsupported,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Detector {
+    #[serde(default, deserialize_with = "one_or_many")]
+    pub present_matcher: Vec<Matcher>,
+    #[serde(default, deserialize_with = "one_or_many")]
+    pub showing_matcher: Vec<Matcher>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Action {
+    Click {
+        target: Target,
+    },
+    Hide {
+        target: Target,
+    },
+    List {
+        actions: Vec<Action>,
+    },
+    Wait {
+        #[serde(rename = "waitTime")]
+        wait_time: u64,
+    },
+    WaitCss {
+        target: Target,
+        #[serde(default)]
+        retries: u32,
+        #[serde(default, rename = "waitTime")]
+        wait_time: u64,
+        #[serde(default)]
+        negated: bool,
+    },
+    IfCss {
+        target: Target,
+        #[serde(default, rename = "trueAction")]
+        true_action: Option<Box<Action>>,
+        #[serde(default, rename = "falseAction")]
+        false_action: Option<Box<Action>>,
+    },
+    Consent {
+        consents: Vec<Consent>,
+    },
+    #[serde(other)]
+    Unsupported,
+}
+
+/// One consent category (`type` is the Consent-O-Matic category letter, e.g. `"A"` for
+/// preferences, `"D"` for information storage).
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Consent {
+    #[serde(rename = "type")]
+    pub category: String,
+    #[serde(default)]
+    pub true_action: Option<Action>,
+    #[serde(default)]
+    pub false_action: Option<Action>,
+    #[serde(default)]
+    pub toggle_action: Option<Action>,
+    #[serde(default)]
+    pub matcher: Option<Matcher>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Method {
+    pub name: String,
+    #[serde(default)]
+    pub action: Option<Action>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct CmpRule {
+    #[serde(default)]
+    pub detectors: Vec<Detector>,
+    #[serde(default)]
+    pub methods: Vec<Method>,
+}
+
+/// What the engine runs against a page: if any detector matches, perform `steps` in order.
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct ConsentPlan {
+    pub cmp: String,
+    pub detectors: Vec<Detector>,
+    pub steps: Vec<Action>,
+}
+
+/// A parsed rule database, keyed by CMP name.
+#[derive(Debug, Clone, Default)]
+pub struct ConsentRules {
+    rules: BTreeMap<String, CmpRule>,
+}
+
+impl ConsentRules {
+    pub fn bundled() -> Self {
+        // The bundled database is validated by tests, so this cannot fail at runtime
+        Self::from_json(BUNDLED_RULES).expect("bundled consent rules are valid")
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, serde_json::Error> {
+        Ok(Self {
+            rules: serde_json::from_str(json)?,
+        })
+    }
+
+    /// Adds or replaces CMP rules from another database.
+    pub fn extend(&mut self, other: ConsentRules) {
+        self.rules.extend(other.rules);
+    }
+
+    pub fn len(&self) -> usize {
+        self.rules.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Reject plans for every CMP that may appear on `page_url`. CMPs whose detectors are
+    /// restricted to other URLs are left out.
+    pub fn plans_for(&self, page_url: &str) -> Vec<ConsentPlan> {
+        self.rules
+            .iter()
+            .filter(|(_, rule)| rule.detectors.iter().any(|d| detector_applies_to(d, page_url)))
+            .map(|(name, rule)| ConsentPlan {
+                cmp: name.clone(),
+                detectors: rule.detectors.clone(),
+                steps: reject_steps(rule),
+            })
+            .filter(|plan| !plan.steps.is_empty())
+            .collect()
+    }
+
+    /// Selectors from `HIDE_CMP` methods, suitable for injecting as cosmetic filters.
+    pub fn hide_selectors(&self, page_url: &str) -> Vec<String> {
+        let mut selectors = Vec::new();
+        for rule in self.rules.values() {
+            if !rule.detectors.iter().any(|d| detector_applies_to(d, page_url)) {
+                continue;
+            }
+            for method in rule.methods.iter().filter(|m| m.name == "HIDE_CMP") {
+                if let Some(action) = &method.action {
+                    collect_hidden(action, &mut selectors);
+                }
+            }
+        }
+        selectors
+    }
+}
+
+/// Sites (registrable domains) where the user turned cookie banner handling off.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub(crate) struct OptOuts {
+    pub(crate) sites: BTreeSet<String>,
+}
+
+fn detector_applies_to(detector: &Detector, page_url: &str) -> bool {
+    detector.present_matcher.iter().all(|matcher| match matcher {
+        Matcher::Url { url } => url.iter().any(|u| page_url.contains(u.as_str())),
+        _ => true,
+    })
+}
+
+fn reject_steps(rule: &CmpRule) -> Vec<Action> {
+    REJECT_SEQUENCE
+        .iter()
+        .filter_map(|name| rule.methods.iter().find(|m| m.name == *name))
+        .filter_map(|method| method.action.as_ref())
+        .map(reject_all)
+        .collect()
+}
+
+/// Resolves `consent` actions so that every category is refused.
+fn reject_all(action: &Action) -> Action {
+    match action {
+        Action::Consent { consents } => Action::List {
+            actions: consents.iter().filter_map(refuse).collect(),
+        },
+        Action::List { actions } => Action::List {
+            actions: actions.iter().map(reject_all).collect(),
+        },
+        Action::IfCss {
+            target,
+            true_action,
+            false_action,
+        } => Action::IfCss {
+            target: target.clone(),
+            true_action: true_action.as_deref().map(|a| Box::new(reject_all(a))),
+            false_action: false_action.as_deref().map(|a| Box::new(reject_all(a))),
+        },
+        other => other.clone(),
+    }
+}
+
+fn refuse(consent: &Consent) -> Option<Action> {
+    if let Some(false_action) = &consent.false_action {
+        return Some(reject_all(false_action));
+    }
+    // Toggle-style categories: flip the switch only if it is currently on. A checkbox matcher
+    // means the box is checked; a CSS matcher means its selector matches.
+    let target = match &consent.matcher {
+        Some(Matcher::Checkbox { target }) => Target {
+            selector: format!("{}:checked", target.selector),
+            ..target.clone()
+        },
+        Some(Matcher::Css { target }) => target.clone(),
+        _ => return None,
+    };
+    Some(Action::IfCss {
+        target,
+        true_action: Some(Box::new(reject_all(consent.toggle_action.as_ref()?))),
+        false_action: None,
+    })
+}
+
+fn collect_hidden(action: &Action, selectors: &mut Vec<String>) {
+    match action {
+        Action::Hide { target } => selectors.push(target.selector.clone()),
+        Action::List { actions } => actions.iter().for_each(|a| collect_hidden(a, selectors)),
+        _ => {}
+    }
+}
+
+fn one_or_many<'de, D, T>(deserializer: D) -> Result<Vec<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany<T> {
+        One(T),
+        Many(Vec<T>),
+    }
+
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(one) => vec![one],
+        OneOrMany::Many(many) => many,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bundled_rules_parse() {
+        let rules = ConsentRules::bundled();
+        assert!(!rules.is_empty());
+        for plan in rules.plans_for("https://example.com") {
+            assert!(!plan.steps.is_empty(), "{} has no reject steps", plan.cmp);
+        }
+    }
+
+    #[test]
+    fn test_consent_resolves_to_reject() {
+        let rules = ConsentRules::from_json(
+            r##"{
+                "TestCMP": {
+                    "detectors": [{ "presentMatcher": { "type": "css", "target": { "selector": "#cmp" } } }],
+                    "methods": [
+                        { "name": "HIDE_CMP", "action": { "type": "hide", "target": { "selector": "#cmp" } } },
+                        { "name": "DO_CONSENT", "action": { "type": "consent", "consents": [
+                            { "type": "A",
+                              "trueAction": { "type": "click", "target": { "selector": ".accept-a" } },
+                              "falseAction": { "type": "click", "target": { "selector": ".reject-a" } } },
+                            { "type": "F",
+                              "toggleAction": { "type": "click", "target": { "selector": "#toggle-f" } },
+                              "matcher": { "type": "checkbox", "target": { "selector": "#toggle-f input" } } },
+                            { "type": "X",
+                              "toggleAction": { "type": "click", "target": { "selector": "#toggle-x" } },
+                              "matcher": { "type": "css", "target": { "selector": "#toggle-x.on" } } }
+                        ] } },
+                        { "name": "SAVE_CONSENT", "action": { "type": "click", "target": { "selector": ".save", "textFilter": "Save" } } },
+                        { "name": "UTILITY", "action": { "type": "unknownAction" } }
+                    ]
+                }
+            }"##,
+        )
+        .unwrap();
+
+        let plans = rules.plans_for("https://example.com");
+        assert_eq!(plans.len(), 1);
+        let steps = &plans[0].steps;
+        assert_eq!(steps.len(), 3);
+
+        let Action::List { actions } = &steps[1] else {
+            panic!("DO_CONSENT should resolve to a list, got {:?}", steps[1]);
+        };
+        assert!(matches!(&actions[0], Action::Click { target } if target.selector == ".reject-a"));
+        assert!(matches!(&actions[1], Action::IfCss { target, .. } if target.selector == "#toggle-f input:checked"));
+        assert!(matches!(&actions[2], Action::IfCss { target, .. } if target.selector == "#toggle-x.on"));
+
+        assert!(matches!(&steps[2], Action::Click { target } if target.text_filter == vec!["Save".to_string()]));
+        assert_eq!(rules.hide_selectors("https://example.com"), vec!["#cmp".to_string()]);
+    }
+
+    #[test]
+    fn test_url_matcher_limits_rule_to_site() {
+        let rules = ConsentRules::from_json(
+            r##"{
+                "SiteSpecific": {
+                    "detectors": [{ "presentMatcher": [
+                        { "type": "url", "url": "news.example" },
+                        { "type": "css", "target": { "selector": ".banner" } }
+                    ] }],
+                    "methods": [{ "name": "HIDE_CMP", "action": { "type": "hide", "target": { "selector": ".banner" } } }]
+                }
+            }"##,
+        )
+        .unwrap();
+
+        assert_eq!(rules.plans_for("https://news.example/today").len(), 1);
+        assert!(rules.plans_for("https://other.example").is_empty());
+        assert!(rules.hide_selectors("https://other.example").is_empty());
+    }
+}
@@ -0,0 +1,252 @@
+//! Bounce tracking mitigation.
+//!
+//! Link decorators (`l.facebook.com/l.php?u=...`, newsletter click trackers) send the user
+//! through a tracking domain before the real destination. When the destination is embedded in
+//! the URL, the shield *debounces* the navigation and goes there directly. Hops that cannot be
+//! skipped are recorded, and sites that only ever appear as redirect hops, never as a place
+//! the user interacted with, have their storage cleared.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime};
+use url::Url;
+
+use base64::{Engine as _, prelude::BASE64_STANDARD};
+
+/// Rule database shipped with Wave.
+const BUNDLED_RULES: &str = include_str!("debounce_rules.json");
+
+/// Nested decorators are unwrapped up to this depth.
+const MAX_DEBOUNCE_DEPTH: usize = 5;
+
+/// How long a bounce-only site keeps its storage before it is cleared.
+pub const BOUNCE_GRACE_PERIOD: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DebounceAction {
+    /// The destination is the plain (percent-decoded) value of `param`
+    Redirect,
+    /// The destination is base64-encoded in `param`
+    Base64Redirect,
+}
+
+/// A link decorator: URLs matching `include` (and none of `exclude`) carry their real
+/// destination in the query parameter `param`. Patterns are full URLs with `*` wildcards; in
+/// the scheme and host a wildcard stays within them (`*://*.example.com/*`).
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct DebounceRule {
+    pub include: Vec<String>,
+    #[serde(default)]
+    pub exclude: Vec<String>,
+    pub action: DebounceAction,
+    pub param: String,
+}
+
+impl DebounceRule {
+    fn matches(&self, url: &str) -> bool {
+        self.include.iter().any(|p| glob_match(p, url)) && !self.exclude.iter().any(|p| glob_match(p, url))
+    }
+
+    fn destination(&self, url: &Url) -> Option<Url> {
+        let (_, value) = url.query_pairs().find(|(name, _)| *name == self.param)?;
+        let value = match self.action {
+            DebounceAction::Redirect => value.into_owned(),
+            DebounceAction::Base64Redirect => String::from_utf8(BASE64_STANDARD.decode(value.as_bytes()).ok()?).ok()?,
+        };
+        let destination = Url::parse(&value).ok()?;
+        // Never debounce into javascript:, data: or similar
+        matches!(destination.scheme(), "http" | "https").then_some(destination)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct DebounceRules {
+    rules: Vec<DebounceRule>,
+}
+
+impl DebounceRules {
+    pub fn bundled() -> Self {
+        // The bundled database is validated by tests, so this cannot fail at runtime
+        Self::from_json(BUNDLED_RULES).expect("bundled debounce rules are valid")
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, serde_json::Error> {
+        Ok(Self {
+            rules: serde_json::from_str(json)?,
+        })
+    }
+
+    /// The final destination of a decorated link, or `None` if `url` is not a known decorator.
+    pub fn debounce(&self, url: &str) -> Option<Url> {
+        let mut current = Url::parse(url).ok()?;
+        let mut debounced = false;
+
+        for _ in 0..MAX_DEBOUNCE_DEPTH {
+            let next = self
+                .rules
+                .iter()
+                .find(|rule| rule.matches(current.as_str()))
+                .and_then(|rule| rule.destination(&current));
+            match next {
+                Some(next) => {
+                    current = next;
+                    debounced = true;
+                }
+                None => break,
+            }
+        }
+
+        debounced.then_some(current)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct SiteActivity {
+    first_bounce: Option<SystemTime>,
+    interacted: bool,
+}
+
+/// Tracks which sites (registrable domains) were only ever seen as redirect hops.
+#[derive(Debug, Default)]
+pub struct BounceTracker {
+    sites: HashMap<String, SiteActivity>,
+}
+
+impl BounceTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A top-level navigation passed through `site` and redirected away without user activation.
+    pub fn record_hop(&mut self, site: &str, at: SystemTime) {
+        let activity = self.sites.entry(site.to_string()).or_default();
+        activity.first_bounce.get_or_insert(at);
+    }
+
+    /// The user interacted with a page on `site`, so its storage is legitimate.
+    pub fn record_interaction(&mut self, site: &str) {
+        self.sites.entry(site.to_string()).or_default().interacted = true;
+    }
+
+    /// Sites whose storage should be cleared: bounced through, never interacted with, and past
+    /// the grace period. They are forgotten once reported.
+    pub fn take_sites_to_clear(&mut self, now: SystemTime) -> Vec<String> {
+        let mut expired: Vec<String> = self
+            .sites
+            .iter()
+            .filter(|(_, activity)| !activity.interacted)
+            .filter(|(_, activity)| {
+                activity
+                    .first_bounce
+                    .and_then(|at| now.duration_since(at).ok())
+                    .is_some_and(|age| age >= BOUNCE_GRACE_PERIOD)
+            })
+            .map(|(site, _)| site.clone())
+            .collect();
+        expired.sort();
+
+        for site in &expired {
+            self.sites.remove(site);
+        }
+        expired
+    }
+}
+
+/// Matches `url` against `pattern`, where `*` matches any run of characters. A `*` in the
+/// pattern's scheme or host doesn't match past them, so `*://*.example.com/*` can't be satisfied
+/// by a path or query that happens to contain `.example.com/`.
+fn glob_match(pattern: &str, url: &str) -> bool {
+    // Where the pattern's path begins; wildcards before it stay in the scheme and host
+    let path_start = pattern
+        .find("://")
+        .map(|scheme_end| pattern[scheme_end + 3..].find('/').map_or(pattern.len(), |i| scheme_end + 3 + i))
+        .unwrap_or(0);
+    glob_match_from(pattern.as_bytes(), 0, url.as_bytes(), path_start)
+}
+
+/// Matches `text` against the pattern from its byte `at` on.
+fn glob_match_from(pattern: &[u8], at: usize, text: &[u8], path_start: usize) -> bool {
+    match pattern.get(at) {
+        None => text.is_empty(),
+        Some(b'*') => {
+            for skipped in 0..=text.len() {
+                if glob_match_from(pattern, at + 1, &text[skipped..], path_start) {
+                    return true;
+                }
+                if at < path_start && text.get(skipped).is_some_and(|c| b"/?#@:".contains(c)) {
+                    return false;
+                }
+            }
+            false
+        }
+        Some(c) => text.first() == Some(c) && glob_match_from(pattern, at + 1, &text[1..], path_start),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bundled_decorators() {
+        let rules = DebounceRules::bundled();
+
+        let facebook = "https://l.facebook.com/l.php?u=https%3A%2F%2Fexample.com%2Farticle%3Fid%3D7&h=AT0x";
+        assert_eq!(rules.debounce(facebook).unwrap().as_str(), "https://example.com/article?id=7");
+
+        let google = "https://www.google.com/url?sa=t&url=https%3A%2F%2Fexample.com%2F&usg=AOv";
+        assert_eq!(rules.debounce(google).unwrap().as_str(), "https://example.com/");
+
+        assert!(rules.debounce("https://www.facebook.com/profile.php?id=4").is_none());
+        assert!(rules.debounce("https://example.com/?u=https://other.com").is_none());
+    }
+
+    #[test]
+    fn test_nested_and_encoded_destinations() {
+        let rules = DebounceRules::from_json(
+            r#"[
+                { "include": ["https://track.mail.test/c/*"], "action": "base64_redirect", "param": "t" },
+                { "include": ["*://l.facebook.com/l.php*"], "action": "redirect", "param": "u" }
+            ]"#,
+        )
+        .unwrap();
+
+        let inner = "https://l.facebook.com/l.php?u=https%3A%2F%2Fexample.com%2F";
+        let outer = format!("https://track.mail.test/c/1?t={}", BASE64_STANDARD.encode(inner));
+        assert_eq!(rules.debounce(&outer).unwrap().as_str(), "https://example.com/");
+
+        let script = format!("https://track.mail.test/c/1?t={}", BASE64_STANDARD.encode("javascript:alert(1)"));
+        assert!(rules.debounce(&script).is_none());
+    }
+
+    #[test]
+    fn test_bounce_only_sites_are_cleared_after_grace_period() {
+        let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000);
+        let mut tracker = BounceTracker::new();
+        tracker.record_hop("tracker.test", start);
+        tracker.record_hop("mycompany.test", start);
+        tracker.record_interaction("mycompany.test");
+
+        assert!(tracker.take_sites_to_clear(start + Duration::from_secs(60)).is_empty());
+
+        let later = start + BOUNCE_GRACE_PERIOD;
+        assert_eq!(tracker.take_sites_to_clear(later), vec!["tracker.test".to_string()]);
+        assert!(tracker.take_sites_to_clear(later).is_empty());
+    }
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("*://l.facebook.com/l.php*", "https://l.facebook.com/l.php?u=x"));
+        assert!(!glob_match("*://l.facebook.com/l.php*", "https://l.facebook.com.evil.test/l.php"));
+        assert!(glob_match("https://a.test/", "https://a.test/"));
+        assert!(!glob_match("https://a.test/", "https://a.test/x"));
+
+        // Host wildcards match subdomains only, never a path, query or credentials
+        let safelinks = "*://*.safelinks.protection.outlook.com/*";
+        assert!(glob_match(safelinks, "https://eur01.safelinks.protection.outlook.com/?url=x"));
+        assert!(!glob_match(safelinks, "https://evil.test/a.safelinks.protection.outlook.com/"));
+        assert!(!glob_match(safelinks, "https://evil.test/?a.safelinks.protection.outlook.com/"));
+        assert!(!glob_match(safelinks, "https://a.safelinks.protection.outlook.com@evil.test/"));
+    }
+}
@@ -0,0 +1,189 @@
+//! Request header hygiene.
+//!
+//! Applied to every outgoing request after the network filters allowed it: advertises Global
+//! Privacy Control, trims cross-site `Referer`s to the origin, and removes headers that
+//! identify the browser installation or its exact build.
+
+use super::ShieldLevel;
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+/// Headers that identify the installation or the browser build rather than the request.
+const IDENTIFYING_HEADERS: &[&str] = &["x-client-data", "x-browser-validation", "x-browser-channel", "x-browser-year"];
+
+/// User-Agent client hints that are always sent by Chromium-based browsers.
+const LOW_ENTROPY_HINTS: &[&str] = &["sec-ch-ua", "sec-ch-ua-mobile", "sec-ch-ua-platform"];
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum ClientHints {
+    /// Send whatever the engine produced
+    Full,
+    /// Only the low-entropy hints (brand, mobile, platform)
+    LowEntropy,
+    /// Only brand and mobile
+    Minimal,
+}
+
+/// Which header rewrites are applied to outgoing requests.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub struct HeaderPolicy {
+    pub send_gpc: bool,
+    pub drop_dnt: bool,
+    pub trim_cross_site_referer: bool,
+    pub strip_identifying_headers: bool,
+    pub client_hints: ClientHints,
+}
+
+impl HeaderPolicy {
+    pub fn for_level(level: ShieldLevel) -> Self {
+        match level {
+            ShieldLevel::Off => Self {
+                send_gpc: false,
+                drop_dnt: false,
+                trim_cross_site_referer: false,
+                strip_identifying_headers: false,
+                client_hints: ClientHints::Full,
+            },
+            ShieldLevel::Standard => Self {
+                send_gpc: true,
+                drop_dnt: false,
+                trim_cross_site_referer: true,
+                strip_identifying_headers: true,
+                client_hints: ClientHints::LowEntropy,
+            },
+            // DNT is ignored by servers and only adds a fingerprinting bit
+            ShieldLevel::Strict => Self {
+                send_gpc: true,
+                drop_dnt: true,
+                trim_cross_site_referer: true,
+                strip_identifying_headers: true,
+                client_hints: ClientHints::Minimal,
+            },
+        }
+    }
+
+    /// Rewrites the headers of a request to `url`. Header names are matched case-insensitively.
+    pub fn apply(&self, url: &str, headers: &mut Vec<(String, String)>) {
+        headers.retain(|(name, _)| {
+            let name = name.to_ascii_lowercase();
+            if self.drop_dnt && name == "dnt" {
+                return false;
+            }
+            if self.strip_identifying_headers && IDENTIFYING_HEADERS.contains(&name.as_str()) {
+                return false;
+            }
+            if name.starts_with("sec-ch-ua") {
+                return match self.client_hints {
+                    ClientHints::Full => true,
+                    ClientHints::LowEntropy => LOW_ENTROPY_HINTS.contains(&name.as_str()),
+                    ClientHints::Minimal => name == "sec-ch-ua" || name == "sec-ch-ua-mobile",
+                };
+            }
+            true
+        });
+
+        if self.trim_cross_site_referer {
+            for (name, value) in headers.iter_mut() {
+                if name.eq_ignore_ascii_case("referer")
+                    && let Some(trimmed) = trim_referer(url, value)
+                {
+                    *value = trimmed;
+                }
+            }
+        }
+
+        if self.send_gpc {
+            headers.retain(|(name, _)| !name.eq_ignore_ascii_case("sec-gpc"));
+            headers.push(("Sec-GPC".to_string(), "1".to_string()));
+        }
+    }
+}
+
+impl Default for HeaderPolicy {
+    fn default() -> Self {
+        Self::for_level(ShieldLevel::default())
+    }
+}
+
+/// The referrer origin (with a trailing slash), if `referer` is cross-site to `url`.
+fn trim_referer(url: &str, referer: &str) -> Option<String> {
+    let referer_url = Url::parse(referer).ok()?;
+    if super::site_of(url).is_some() && super::site_of(url) == super::site_of(referer) {
+        return None;
+    }
+    let origin = referer_url.origin();
+    origin.is_tuple().then(|| format!("{}/", origin.ascii_serialization()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs.iter().map(|(n, v)| (n.to_string(), v.to_string())).collect()
+    }
+
+    fn get<'a>(headers: &'a [(String, String)], name: &str) -> Option<&'a str> {
+        headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    fn synthetic_request() -> Vec<(String, String)> {
+        headers(&[
+            ("User-Agent", "Mozilla/5.0 Wave"),
+            ("Referer", "https://news.example.com/articles/42?session=abc"),
+            ("DNT", "1"),
+            ("X-Client-Data", "CIa2yQEIpLbJAQ=="),
+            ("sec-ch-ua", "\"Wave\";v=\"1\""),
+            ("sec-ch-ua-mobile", "?0"),
+            ("sec-ch-ua-platform", "\"Linux\""),
+            ("sec-ch-ua-full-version-list", "\"Wave\";v=\"1.0.3.7\""),
+            ("sec-ch-ua-arch", "\"x86\""),
+        ])
+    }
+
+    #[test]
+    fn test_standard_policy() {
+        let mut request = synthetic_request();
+        HeaderPolicy::for_level(ShieldLevel::Standard).apply("https://cdn.tracker.test/pixel.gif", &mut request);
+
+        assert_eq!(get(&request, "Sec-GPC"), Some("1"));
+        assert_eq!(get(&request, "DNT"), Some("1"));
+        assert_eq!(get(&request, "Referer"), Some("https://news.example.com/"));
+        assert_eq!(get(&request, "X-Client-Data"), None);
+        assert_eq!(get(&request, "sec-ch-ua-platform"), Some("\"Linux\""));
+        assert_eq!(get(&request, "sec-ch-ua-full-version-list"), None);
+        assert_eq!(get(&request, "sec-ch-ua-arch"), None);
+        assert_eq!(get(&request, "User-Agent"), Some("Mozilla/5.0 Wave"));
+    }
+
+    #[test]
+    fn test_same_site_referer_is_kept() {
+        let mut request = synthetic_request();
+        HeaderPolicy::for_level(ShieldLevel::Standard).apply("https://static.example.com/app.js", &mut request);
+        assert_eq!(get(&request, "Referer"), Some("https://news.example.com/articles/42?session=abc"));
+    }
+
+    #[test]
+    fn test_strict_policy() {
+        let mut request = synthetic_request();
+        request.push(("Sec-GPC".to_string(), "0".to_string()));
+        HeaderPolicy::for_level(ShieldLevel::Strict).apply("https://cdn.tracker.test/pixel.gif", &mut request);
+
+        assert_eq!(get(&request, "DNT"), None);
+        assert_eq!(get(&request, "sec-ch-ua-platform"), None);
+        assert_eq!(get(&request, "sec-ch-ua-mobile"), Some("?0"));
+        let gpc: Vec<_> = request.iter().filter(|(n, _)| n.eq_ignore_ascii_case("sec-gpc")).collect();
+        assert_eq!(gpc.len(), 1);
+        assert_eq!(gpc[0].1, "1");
+    }
+
+    #[test]
+    fn test_off_policy_leaves_request_untouched() {
+        let mut request = synthetic_request();
+        HeaderPolicy::for_level(ShieldLevel::Off).apply("https://cdn.tracker.test/pixel.gif", &mut request);
+        assert_eq!(request, synthetic_request());
+    }
+}
@@ -0,0 +1,260 @@
+//! Element picker.
+//!
+//! Turns an element the user pointed at into candidate cosmetic selectors, from the broadest
+//! (`.ad-slot`) to the most specific (`main > div.feed > div.ad-slot:nth-of-type(3)`). The engine
+//! reports hovered elements from its hit tests; the shell lets the user pick one, adjust the
+//! specificity while the selector is previewed, and saves it as a `##` rule for the site.
+
+use serde::{Deserialize, Serialize};
+
+/// One element of the picked element's ancestor chain, as reported by the engine.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ElementNode {
+    pub tag: String,
+    pub id: Option<String>,
+    pub classes: Vec<String>,
+    /// 1-based position among siblings with the same tag
+    pub nth_of_type: usize,
+    /// Number of siblings (including itself) with the same tag
+    pub of_type_count: usize,
+}
+
+/// An element under the pointer: the element first, then its ancestors up to `<body>`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct PickedElement {
+    pub path: Vec<ElementNode>,
+    /// Border box in page coordinates: x, y, width, height
+    pub rect: [f32; 4],
+}
+
+impl PickedElement {
+    /// Candidate selectors, broadest first, without duplicates.
+    pub fn selector_candidates(&self) -> Vec<String> {
+        let Some(target) = self.path.first() else {
+            return Vec::new();
+        };
+
+        let mut candidates = Vec::new();
+        let mut push = |selector: String| {
+            if !candidates.contains(&selector) {
+                candidates.push(selector);
+            }
+        };
+        push(compound(target, Detail::Distinctive));
+        push(compound(target, Detail::Full));
+        let mut chain = compound(target, Detail::Positional);
+        push(chain.clone());
+
+        for ancestor in self.path.iter().skip(1).take_while(|node| node.tag != "body" && node.tag != "html") {
+            chain = format!("{} > {}", compound(ancestor, Detail::Full), chain);
+            push(chain.clone());
+            // An ancestor with an ID pins the selector down; going further only makes it brittle
+            if ancestor.id.as_deref().is_some_and(is_identifier) {
+                break;
+            }
+        }
+        candidates
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Detail {
+    /// The single most distinctive part: the ID, the first class or the tag
+    Distinctive,
+    /// Tag with ID or all (stable) classes
+    Full,
+    /// Like `Full`, plus `:nth-of-type` when the element has same-tag siblings
+    Positional,
+}
+
+fn compound(node: &ElementNode, detail: Detail) -> String {
+    let id = node.id.as_deref().filter(|id| !id.is_empty()).map(|id| {
+        if is_identifier(id) {
+            format!("#{}", id)
+        } else {
+            format!("[id=\"{}\"]", id.replace('\\', "\\\\").replace('"', "\\\""))
+        }
+    });
+    let classes: Vec<&str> = node
+        .classes
+        .iter()
+        .map(String::as_str)
+        .filter(|class| is_identifier(class) && !is_generated(class))
+        .collect();
+
+    match detail {
+        Detail::Distinctive => id
+            .or_else(|| classes.first().map(|class| format!(".{}", class)))
+            .unwrap_or_else(|| node.tag.clone()),
+        Detail::Full | Detail::Positional => {
+            let mut selector = node.tag.clone();
+            match id {
+                Some(id) => selector.push_str(&id),
+                None => classes.iter().for_each(|class| {
+                    selector.push('.');
+                    selector.push_str(class);
+                }),
+            }
+            if matches!(detail, Detail::Positional) && node.of_type_count > 1 {
+                selector.push_str(&format!(":nth-of-type({})", node.nth_of_type));
+            }
+            selector
+        }
+    }
+}
+
+/// Whether `name` can be used after `#` or `.` without escaping.
+fn is_identifier(name: &str) -> bool {
+    let start = name.strip_prefix('-').unwrap_or(name);
+    start.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && start.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+/// Class names produced by CSS-in-JS tooling change with every deploy and make useless rules.
+fn is_generated(class: &str) -> bool {
+    const PREFIXES: [&str; 4] = ["css-", "sc-", "jsx-", "emotion-"];
+    PREFIXES.iter().any(|prefix| class.starts_with(prefix)) || class.chars().filter(char::is_ascii_digit).count() >= 4
+}
+
+/// Picker state for one page: the hovered element, and once clicked, the chosen one.
+#[derive(Debug, Clone, Default)]
+pub struct ElementPicker {
+    hovered: Option<PickedElement>,
+    picked: Option<PickedElement>,
+    candidates: Vec<String>,
+    specificity: usize,
+}
+
+impl ElementPicker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The element under the pointer, reported by the engine's hit test. Ignored once picked.
+    pub fn hover(&mut self, element: Option<PickedElement>) {
+        if self.picked.is_none() {
+            self.hovered = element;
+        }
+    }
+
+    /// The element to highlight: the picked one, or the one under the pointer.
+    pub fn highlighted(&self) -> Option<&PickedElement> {
+        self.picked.as_ref().or(self.hovered.as_ref())
+    }
+
+    /// Picks the hovered element, starting at the broadest selector.
+    pub fn pick(&mut self) -> bool {
+        let Some(element) = self.hovered.take() else {
+            return false;
+        };
+        self.candidates = element.selector_candidates();
+        self.specificity = 0;
+        self.picked = Some(element);
+        true
+    }
+
+    /// Back to hovering, e.g. when the user picked the wrong element.
+    pub fn unpick(&mut self) {
+        self.picked = None;
+        self.candidates.clear();
+        self.specificity = 0;
+    }
+
+    pub fn is_picked(&self) -> bool {
+        self.picked.is_some()
+    }
+
+    pub fn candidates(&self) -> &[String] {
+        &self.candidates
+    }
+
+    pub fn specificity(&self) -> usize {
+        self.specificity
+    }
+
+    /// Selects a candidate; 0 is the broadest. Clamped to the available candidates.
+    pub fn set_specificity(&mut self, specificity: usize) {
+        self.specificity = specificity.min(self.candidates.len().saturating_sub(1));
+    }
+
+    /// The selector currently chosen, which the engine previews by hiding what it matches.
+    pub fn selector(&self) -> Option<&str> {
+        self.candidates.get(self.specificity).map(String::as_str)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(tag: &str, id: Option<&str>, classes: &[&str], nth_of_type: usize, of_type_count: usize) -> ElementNode {
+        ElementNode {
+            tag: tag.to_string(),
+            id: id.map(str::to_string),
+            classes: classes.iter().map(|c| c.to_string()).collect(),
+            nth_of_type,
+            of_type_count,
+        }
+    }
+
+    fn ad_slot() -> PickedElement {
+        PickedElement {
+            path: vec![
+                node("div", None, &["ad-slot", "css-1q2w3e4", "sponsored"], 3, 5),
+                node("section", None, &["feed"], 1, 1),
+                node("main", Some("content"), &[], 1, 1),
+                node("body", None, &[], 1, 1),
+            ],
+            rect: [0.0, 120.0, 300.0, 250.0],
+        }
+    }
+
+    #[test]
+    fn test_candidates_go_from_broad_to_specific() {
+        assert_eq!(
+            ad_slot().selector_candidates(),
+            vec![
+                ".ad-slot",
+                "div.ad-slot.sponsored",
+                "div.ad-slot.sponsored:nth-of-type(3)",
+                "section.feed > div.ad-slot.sponsored:nth-of-type(3)",
+                "main#content > section.feed > div.ad-slot.sponsored:nth-of-type(3)",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_awkward_ids_and_bare_tags() {
+        let element = PickedElement {
+            path: vec![node("iframe", Some("1:ad\"frame"), &[], 1, 1), node("body", None, &[], 1, 1)],
+            rect: [0.0; 4],
+        };
+        assert_eq!(element.selector_candidates(), vec!["[id=\"1:ad\\\"frame\"]", "iframe[id=\"1:ad\\\"frame\"]"]);
+
+        let element = PickedElement {
+            path: vec![node("aside", None, &[], 2, 2)],
+            rect: [0.0; 4],
+        };
+        assert_eq!(element.selector_candidates(), vec!["aside", "aside:nth-of-type(2)"]);
+    }
+
+    #[test]
+    fn test_picker_flow() {
+        let mut picker = ElementPicker::new();
+        assert!(!picker.pick());
+
+        picker.hover(Some(ad_slot()));
+        assert!(picker.pick());
+        assert_eq!(picker.selector(), Some(".ad-slot"));
+
+        // Hovering other elements doesn't move the highlight off the picked one
+        picker.hover(None);
+        assert_eq!(picker.highlighted(), Some(&ad_slot()));
+
+        picker.set_specificity(99);
+        assert_eq!(picker.selector(), Some("main#content > section.feed > div.ad-slot.sponsored:nth-of-type(3)"));
+
+        picker.unpick();
+        assert_eq!(picker.selector(), None);
+    }
+}
@@ -0,0 +1,255 @@
+//! Bundled redirect and scriptlet resources for the adblock engine.
+//!
+//! Filter lists written for uBlock Origin rely on `$redirect=` resources (stub scripts,
+//! transparent images) and `##+js()` scriptlets. Without them, redirect rules fail closed and
+//! scriptlet rules are silently dropped, which breaks sites. The names and aliases below match
+//! the ones used by uBlock Origin so existing lists work unmodified.
+
+use adblock::resources::{MimeType, Resource, ResourceType as AdblockResourceType};
+use base64::{Engine as _, prelude::BASE64_STANDARD};
+
+enum Content {
+    Text(&'static str),
+    Base64(&'static str),
+}
+
+enum Kind {
+    Mime(&'static str),
+    Scriptlet, // Template with {{1}}, {{2}}... placeholders
+}
+
+struct Bundled {
+    name: &'static str,
+    aliases: &'static [&'static str],
+    kind: Kind,
+    content: Content,
+}
+
+const BUNDLED: &[Bundled] = &[
+    // --- Redirect resources ---
+    Bundled {
+        name: "1x1.gif",
+        aliases: &["1x1-transparent.gif", "abp-resource:1x1-transparent-gif"],
+        kind: Kind::Mime("image/gif"),
+        content: Content::Base64("R0lGODlhAQABAIAAAAAAAP///yH5BAEAAAAALAAAAAABAAEAAAIBRAA7"),
+    },
+    Bundled {
+        name: "2x2.png",
+        aliases: &["2x2-transparent.png", "abp-resource:2x2-transparent-png"],
+        kind: Kind::Mime("image/png"),
+        content: Content::Base64(
+            "iVBORw0KGgoAAAANSUhEUgAAAAIAAAACCAYAAABytg0kAAAAC0lEQVR42mNgQAcAABIAAeRVjecAAAAASUVORK5CYII=",
+        ),
+    },
+    Bundled {
+        name: "noop.js",
+        aliases: &["noopjs", "abp-resource:blank-js"],
+        kind: Kind::Mime("application/javascript"),
+        content: Content::Text("(function() {\n    'use strict';\n})();\n"),
+    },
+    Bundled {
+        name: "noop.css",
+        aliases: &["noopcss", "abp-resource:blank-css"],
+        kind: Kind::Mime("text/css"),
+        content: Content::Text(""),
+    },
+    Bundled {
+        name: "noop.html",
+        aliases: &["noopframe", "abp-resource:blank-html"],
+        kind: Kind::Mime("text/html"),
+        content: Content::Text("<!DOCTYPE html>\n<html><head></head><body></body></html>\n"),
+    },
+    Bundled {
+        name: "noop.json",
+        aliases: &["noopjson"],
+        kind: Kind::Mime("application/json"),
+        content: Content::Text("{}"),
+    },
+    Bundled {
+        name: "noop.txt",
+        aliases: &["nooptext", "abp-resource:blank-text"],
+        kind: Kind::Mime("text/plain"),
+        content: Content::Text(""),
+    },
+    Bundled {
+        name: "empty",
+        aliases: &[],
+        kind: Kind::Mime("text/plain"),
+        content: Content::Text(""),
+    },
+    // --- Scriptlets ---
+    Bundled {
+        name: "abort-on-property-read.js",
+        aliases: &["aopr.js"],
+        kind: Kind::Scriptlet,
+        content: Content::Text(
+            r#"(function() {
+    const chain = '{{1}}';
+    const magic = String.fromCharCode(Date.now() % 26 + 97) + Math.floor(Math.random() * 982451653 + 982451653).toString(36);
+    const abort = function() { throw new ReferenceError(magic); };
+    const makeProxy = function(owner, chain) {
+        const pos = chain.indexOf('.');
+        if ( pos === -1 ) {
+            const desc = Object.getOwnPropertyDescriptor(owner, chain);
+            if ( !desc || desc.get !== abort ) {
+                Object.defineProperty(owner, chain, { get: abort, set: function() {} });
+            }
+            return;
+        }
+        const prop = chain.slice(0, pos);
+        let v = owner[prop];
+        chain = chain.slice(pos + 1);
+        if ( v ) { makeProxy(v, chain); return; }
+        const desc = Object.getOwnPropertyDescriptor(owner, prop);
+        if ( desc && desc.set !== undefined ) { return; }
+        Object.defineProperty(owner, prop, {
+            get: function() { return v; },
+            set: function(a) { v = a; if ( a instanceof Object ) { makeProxy(a, chain); } }
+        });
+    };
+    makeProxy(window, chain);
+    const oe = window.onerror;
+    window.onerror = function(msg) {
+        if ( typeof msg === 'string' && msg.indexOf(magic) !== -1 ) { return true; }
+        if ( oe instanceof Function ) { return oe.apply(this, arguments); }
+    }.bind();
+})();"#,
+        ),
+    },
+    Bundled {
+        name: "abort-on-property-write.js",
+        aliases: &["aopw.js"],
+        kind: Kind::Scriptlet,
+        content: Content::Text(
+            r#"(function() {
+    const chain = '{{1}}';
+    let prop = chain;
+    let owner = window;
+    for (;;) {
+        const pos = prop.indexOf('.');
+        if ( pos === -1 ) { break; }
+        owner = owner[prop.slice(0, pos)];
+        if ( owner instanceof Object === false ) { return; }
+        prop = prop.slice(pos + 1);
+    }
+    delete owner[prop];
+    Object.defineProperty(owner, prop, {
+        set: function() { throw new ReferenceError(chain); }
+    });
+})();"#,
+        ),
+    },
+    Bundled {
+        name: "set-constant.js",
+        aliases: &["set.js"],
+        kind: Kind::Scriptlet,
+        content: Content::Text(
+            r#"(function() {
+    const chain = '{{1}}';
+    const raw = '{{2}}';
+    const values = {
+        'undefined': undefined, 'false': false, 'true': true, 'null': null,
+        'noopFunc': function() {}, 'trueFunc': function() { return true; },
+        'falseFunc': function() { return false; }, '': '',
+    };
+    let cValue;
+    if ( Object.prototype.hasOwnProperty.call(values, raw) ) {
+        cValue = values[raw];
+    } else if ( /^\d+$/.test(raw) ) {
+        cValue = parseFloat(raw);
+        if ( Math.abs(cValue) > 0x7FFF ) { return; }
+    } else {
+        return;
+    }
+    let owner = window;
+    let prop = chain;
+    for (;;) {
+        const pos = prop.indexOf('.');
+        if ( pos === -1 ) { break; }
+        owner = owner[prop.slice(0, pos)];
+        if ( owner instanceof Object === false ) { return; }
+        prop = prop.slice(pos + 1);
+    }
+    try {
+        Object.defineProperty(owner, prop, {
+            configurable: false,
+            get: function() { return cValue; },
+            set: function() {}
+        });
+    } catch(ex) {
+    }
+})();"#,
+        ),
+    },
+    Bundled {
+        name: "no-setTimeout-if.js",
+        aliases: &["nostif.js", "prevent-setTimeout.js"],
+        kind: Kind::Scriptlet,
+        content: Content::Text(
+            r#"(function() {
+    const needle = '{{1}}';
+    const delay = parseInt('{{2}}', 10);
+    const re = needle === '' || /^\{\{\d\}\}$/.test(needle) ? null : new RegExp(needle.replace(/[.*+?^${}()|[\]\\]/g, '\\$&'));
+    window.setTimeout = new Proxy(window.setTimeout, {
+        apply: function(target, thisArg, args) {
+            const matchesNeedle = re === null || re.test(String(args[0]));
+            const matchesDelay = isNaN(delay) || args[1] === delay;
+            if ( matchesNeedle && matchesDelay ) {
+                args[0] = function() {};
+            }
+            return target.apply(thisArg, args);
+        }
+    });
+})();"#,
+        ),
+    },
+    Bundled {
+        name: "remove-attr.js",
+        aliases: &["ra.js"],
+        kind: Kind::Scriptlet,
+        content: Content::Text(
+            r#"(function() {
+    const attrs = '{{1}}'.split(/\s*\|\s*/).filter(a => a !== '');
+    if ( attrs.length === 0 ) { return; }
+    const selectorArg = '{{2}}';
+    const selector = selectorArg === '' || /^\{\{\d\}\}$/.test(selectorArg)
+        ? attrs.map(a => `[${a}]`).join(',')
+        : selectorArg;
+    const rmattr = function() {
+        try {
+            for ( const node of document.querySelectorAll(selector) ) {
+                for ( const attr of attrs ) { node.removeAttribute(attr); }
+            }
+        } catch(ex) {
+        }
+    };
+    if ( document.readyState === 'loading' ) {
+        document.addEventListener('DOMContentLoaded', rmattr, { once: true });
+    } else {
+        rmattr();
+    }
+})();"#,
+        ),
+    },
+];
+
+/// The resource library shipped with Wave, ready to be handed to the adblock engine.
+pub fn bundled_resources() -> Vec<Resource> {
+    BUNDLED
+        .iter()
+        .map(|bundled| Resource {
+            name: bundled.name.to_string(),
+            aliases: bundled.aliases.iter().map(|a| a.to_string()).collect(),
+            kind: match bundled.kind {
+                Kind::Mime(mime) => AdblockResourceType::Mime(MimeType::from(mime)),
+                Kind::Scriptlet => AdblockResourceType::Template,
+            },
+            content: match bundled.content {
+                Content::Text(text) => BASE64_STANDARD.encode(text),
+                Content::Base64(encoded) => encoded.to_string(),
+            },
+            dependencies: Vec::new(),
+            permission: Default::default(),
+        })
+        .collect()
+}
@@ -0,0 +1,471 @@
+//! Local phishing and malware protection.
+//!
+//! Threat lists are stored as SHA-256 hash prefixes and kept up to date with responses in the
+//! Safe Browsing Update API (v4) format, so lookups never leave the machine. A URL is checked
+//! by hashing its host-suffix / path-prefix expressions and looking each hash up in every list.
+//!
+//! Without a full-hash round trip, a match on a short prefix cannot be confirmed, so it is
+//! reported as a hit. Lists meant for offline use should ship full 32-byte hashes.
+//!
+//! The lists are kept in the profile's `safe_browsing.json`. Updates are fetched only if the
+//! profile's `safe_browsing_source.json` names an Update API endpoint.
+
+use base64::{Engine as _, prelude::BASE64_STANDARD};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use serde_json::json;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::net::IpAddr;
+use std::path::Path;
+use std::time::Duration;
+use url::Url;
+
+const MIN_PREFIX_SIZE: usize = 4;
+const MAX_PREFIX_SIZE: usize = 32;
+
+/// Version of the saved lists written by this build.
+pub const SCHEMA_VERSION: u64 = 1;
+pub(crate) const FILE_NAME: &str = "safe_browsing.json";
+const SOURCE_FILE_NAME: &str = "safe_browsing_source.json";
+
+/// How often the lists are brought up to date.
+pub const UPDATE_INTERVAL: Duration = Duration::from_secs(30 * 60);
+
+/// Why a site is considered dangerous.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ThreatType {
+    Malware,
+    SocialEngineering, // Phishing and deceptive sites
+    UnwantedSoftware,
+}
+
+impl fmt::Display for ThreatType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Malware => "This site may install malware on your device",
+            Self::SocialEngineering => "This site may trick you into revealing passwords or other personal information",
+            Self::UnwantedSoftware => "This site may try to install unwanted software",
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum UpdateError {
+    Malformed(String),
+    InvalidPrefixSize(usize),
+    RemovalOutOfRange(usize),
+    /// The list no longer matches the server's; a full update is needed
+    ChecksumMismatch(ThreatType),
+}
+
+impl fmt::Display for UpdateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Malformed(reason) => write!(f, "malformed update: {}", reason),
+            Self::InvalidPrefixSize(size) => write!(f, "invalid hash prefix size {}", size),
+            Self::RemovalOutOfRange(index) => write!(f, "removal index {} out of range", index),
+            Self::ChecksumMismatch(threat) => write!(f, "checksum mismatch for {:?} list", threat),
+        }
+    }
+}
+
+impl std::error::Error for UpdateError {}
+
+// --- Update API wire format (fetchThreatListUpdates response) ---
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct UpdateResponse {
+    #[serde(default)]
+    list_update_responses: Vec<ListUpdate>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+enum ResponseType {
+    FullUpdate,
+    PartialUpdate,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ListUpdate {
+    threat_type: ThreatType,
+    response_type: ResponseType,
+    #[serde(default)]
+    additions: Vec<ThreatEntrySet>,
+    #[serde(default)]
+    removals: Vec<ThreatEntrySet>,
+    #[serde(default)]
+    new_client_state: String,
+    checksum: Option<Checksum>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ThreatEntrySet {
+    raw_hashes: Option<RawHashes>,
+    raw_indices: Option<RawIndices>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawHashes {
+    prefix_size: usize,
+    raw_hashes: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct RawIndices {
+    indices: Vec<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Checksum {
+    sha256: String,
+}
+
+/// One threat list: a lexicographically sorted set of hash prefixes.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct ThreatList {
+    #[serde(with = "base64_prefixes")]
+    prefixes: BTreeSet<Vec<u8>>,
+    client_state: String,
+}
+
+/// Saves prefixes as base64 strings rather than arrays of numbers, which take four times the room.
+mod base64_prefixes {
+    use super::*;
+    use serde::{Deserializer, Serializer, de::Error};
+
+    pub fn serialize<S: Serializer>(prefixes: &BTreeSet<Vec<u8>>, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(prefixes.iter().map(|prefix| BASE64_STANDARD.encode(prefix)))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<BTreeSet<Vec<u8>>, D::Error> {
+        Vec::<String>::deserialize(deserializer)?
+            .iter()
+            .map(|prefix| BASE64_STANDARD.decode(prefix).map_err(D::Error::custom))
+            .collect()
+    }
+}
+
+impl ThreatList {
+    fn apply(&mut self, update: ListUpdate) -> Result<(), UpdateError> {
+        let mut prefixes: Vec<Vec<u8>> = match update.response_type {
+            ResponseType::FullUpdate => Vec::new(),
+            ResponseType::PartialUpdate => self.prefixes.iter().cloned().collect(),
+        };
+
+        // Removal indices refer to the sorted list *before* additions
+        let mut removed: Vec<usize> = update
+            .removals
+            .iter()
+            .filter_map(|set| set.raw_indices.as_ref())
+            .flat_map(|raw| raw.indices.iter().copied())
+            .collect();
+        removed.sort_unstable();
+        removed.dedup();
+        for index in removed.into_iter().rev() {
+            if index >= prefixes.len() {
+                return Err(UpdateError::RemovalOutOfRange(index));
+            }
+            prefixes.remove(index);
+        }
+
+        let mut updated: BTreeSet<Vec<u8>> = prefixes.into_iter().collect();
+        for raw in update.additions.iter().filter_map(|set| set.raw_hashes.as_ref()) {
+            if !(MIN_PREFIX_SIZE..=MAX_PREFIX_SIZE).contains(&raw.prefix_size) {
+                return Err(UpdateError::InvalidPrefixSize(raw.prefix_size));
+            }
+            let bytes = BASE64_STANDARD
+                .decode(&raw.raw_hashes)
+                .map_err(|e| UpdateError::Malformed(e.to_string()))?;
+            if bytes.len() % raw.prefix_size != 0 {
+                return Err(UpdateError::Malformed("raw hashes are not a multiple of the prefix size".into()));
+            }
+            updated.extend(bytes.chunks(raw.prefix_size).map(|c| c.to_vec()));
+        }
+
+        if let Some(checksum) = update.checksum {
+            let expected = BASE64_STANDARD
+                .decode(&checksum.sha256)
+                .map_err(|e| UpdateError::Malformed(e.to_string()))?;
+            let mut hasher = Sha256::new();
+            updated.iter().for_each(|prefix| hasher.update(prefix));
+            if hasher.finalize().as_slice() != expected.as_slice() {
+                return Err(UpdateError::ChecksumMismatch(update.threat_type));
+            }
+        }
+
+        self.prefixes = updated;
+        self.client_state = update.new_client_state;
+        Ok(())
+    }
+
+    fn contains(&self, hash: &[u8]) -> bool {
+        (MIN_PREFIX_SIZE..=MAX_PREFIX_SIZE).any(|size| self.prefixes.contains(&hash[..size]))
+    }
+}
+
+/// The local hash-prefix database, one list per threat type.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SafeBrowsingDb {
+    lists: BTreeMap<ThreatType, ThreatList>,
+}
+
+impl SafeBrowsingDb {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Applies a `fetchThreatListUpdates` response, all of it or none: on error every list
+    /// keeps its previous contents, and the failed one should be re-fetched with a full update.
+    pub fn apply_update(&mut self, json: &str) -> Result<(), UpdateError> {
+        let response: UpdateResponse = serde_json::from_str(json).map_err(|e| UpdateError::Malformed(e.to_string()))?;
+        let mut lists = self.lists.clone();
+        for update in response.list_update_responses {
+            lists.entry(update.threat_type).or_default().apply(update)?;
+        }
+        self.lists = lists;
+        Ok(())
+    }
+
+    /// The `fetchThreatListUpdates` request body asking for what changed in every list since
+    /// its client state.
+    pub fn update_request(&self) -> String {
+        let requests: Vec<_> = [ThreatType::Malware, ThreatType::SocialEngineering, ThreatType::UnwantedSoftware]
+            .into_iter()
+            .map(|threat_type| {
+                json!({
+                    "threatType": threat_type,
+                    "platformType": "ANY_PLATFORM",
+                    "threatEntryType": "URL",
+                    "state": self.client_state(threat_type).unwrap_or_default(),
+                    "constraints": { "supportedCompressions": ["RAW"] },
+                })
+            })
+            .collect();
+        json!({
+            "client": { "clientId": "wave", "clientVersion": env!("CARGO_PKG_VERSION") },
+            "listUpdateRequests": requests,
+        })
+        .to_string()
+    }
+
+    /// The opaque state to send with the next update request for `threat_type`.
+    pub fn client_state(&self, threat_type: ThreatType) -> Option<&str> {
+        self.lists.get(&threat_type).map(|list| list.client_state.as_str())
+    }
+
+    /// The first threat list `url` appears on, if any.
+    pub fn check_url(&self, url: &str) -> Option<ThreatType> {
+        let hashes: Vec<[u8; 32]> = url_expressions(url)
+            .iter()
+            .map(|expression| Sha256::digest(expression.as_bytes()).into())
+            .collect();
+
+        self.lists
+            .iter()
+            .find(|(_, list)| hashes.iter().any(|hash| list.contains(hash)))
+            .map(|(threat_type, _)| *threat_type)
+    }
+}
+
+/// The `threatListUpdates:fetch` endpoint (with its API key) in a profile directory's
+/// `safe_browsing_source.json`, e.g. `{ "update_url": "https://...?key=..." }`.
+pub fn update_url(profile_dir: &Path) -> Option<String> {
+    #[derive(Deserialize)]
+    struct Source {
+        update_url: String,
+    }
+
+    let path = profile_dir.join(SOURCE_FILE_NAME);
+    let data = match fs::read_to_string(&path) {
+        Ok(data) => data,
+        Err(e) => {
+            if e.kind() != io::ErrorKind::NotFound {
+                log::warn!("Could not read {}: {}", path.display(), e);
+            }
+            return None;
+        }
+    };
+    match serde_json::from_str::<Source>(&data) {
+        Ok(source) => Some(source.update_url),
+        Err(e) => {
+            log::warn!("Ignoring {}: {}", path.display(), e);
+            None
+        }
+    }
+}
+
+/// The host-suffix / path-prefix expressions Safe Browsing hashes for a URL, e.g. for
+/// `http://a.b.c/1/2.html?param=1`: `a.b.c/1/2.html?param=1`, `a.b.c/1/2.html`, `a.b.c/`,
+/// `a.b.c/1/`, `b.c/1/2.html?param=1`, ...
+pub fn url_expressions(url: &str) -> Vec<String> {
+    let Ok(parsed) = Url::parse(url.trim()) else {
+        return Vec::new();
+    };
+    let Some(host) = parsed.host_str() else {
+        return Vec::new();
+    };
+    let host = host.trim_matches('.').to_ascii_lowercase();
+
+    // Host suffixes: the exact host plus up to four suffixes built from the last five components
+    let mut hosts = vec![host.clone()];
+    if host.parse::<IpAddr>().is_err() && !host.starts_with('[') {
+        let components: Vec<&str> = host.split('.').filter(|c| !c.is_empty()).collect();
+        let start = components.len().saturating_sub(5).max(1);
+        for i in start..components.len().saturating_sub(1) {
+            let suffix = components[i..].join(".");
+            if !hosts.contains(&suffix) {
+                hosts.push(suffix);
+            }
+        }
+    }
+
+    // Path prefixes: exact path with and without query, then up to four leading directories
+    let path = parsed.path();
+    let mut paths = Vec::new();
+    if let Some(query) = parsed.query() {
+        paths.push(format!("{}?{}", path, query));
+    }
+    paths.push(path.to_string());
+    let mut prefix = String::from("/");
+    if !paths.contains(&prefix) {
+        paths.push(prefix.clone());
+    }
+    let directories = &path[..path.rfind('/').unwrap_or(0)];
+    for component in directories.split('/').filter(|c| !c.is_empty()).take(3) {
+        prefix.push_str(component);
+        prefix.push('/');
+        if !paths.contains(&prefix) {
+            paths.push(prefix.clone());
+        }
+    }
+
+    hosts
+        .iter()
+        .flat_map(|host| paths.iter().map(move |path| format!("{}{}", host, path)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn prefix(expression: &str, size: usize) -> Vec<u8> {
+        Sha256::digest(expression.as_bytes())[..size].to_vec()
+    }
+
+    fn full_update(threat: &str, prefixes: &[Vec<u8>], size: usize) -> String {
+        let mut sorted = prefixes.to_vec();
+        sorted.sort();
+        let checksum = BASE64_STANDARD.encode(Sha256::digest(sorted.concat()));
+        format!(
+            r#"{{ "listUpdateResponses": [{{
+                "threatType": "{}",
+                "responseType": "FULL_UPDATE",
+                "additions": [{{ "compressionType": "RAW", "rawHashes": {{ "prefixSize": {}, "rawHashes": "{}" }} }}],
+                "newClientState": "state-1",
+                "checksum": {{ "sha256": "{}" }}
+            }}] }}"#,
+            threat,
+            size,
+            BASE64_STANDARD.encode(prefixes.concat()),
+            checksum
+        )
+    }
+
+    #[test]
+    fn test_url_expressions() {
+        let expressions = url_expressions("http://a.b.c.d.e.f.g/1.html");
+        assert_eq!(
+            expressions,
+            vec![
+                "a.b.c.d.e.f.g/1.html",
+                "a.b.c.d.e.f.g/",
+                "c.d.e.f.g/1.html",
+                "c.d.e.f.g/",
+                "d.e.f.g/1.html",
+                "d.e.f.g/",
+                "e.f.g/1.html",
+                "e.f.g/",
+                "f.g/1.html",
+                "f.g/",
+            ]
+        );
+
+        let expressions = url_expressions("http://1.2.3.4/1/2.html?param=1");
+        assert_eq!(expressions, vec!["1.2.3.4/1/2.html?param=1", "1.2.3.4/1/2.html", "1.2.3.4/", "1.2.3.4/1/"]);
+    }
+
+    #[test]
+    fn test_full_and_partial_updates() {
+        let mut db = SafeBrowsingDb::new();
+        let phishing = prefix("login-paypa1.test/", 4);
+        let other = prefix("unrelated.test/", 4);
+        db.apply_update(&full_update("SOCIAL_ENGINEERING", &[phishing.clone(), other.clone()], 4)).unwrap();
+
+        assert_eq!(db.client_state(ThreatType::SocialEngineering), Some("state-1"));
+        assert_eq!(db.check_url("https://login-paypa1.test/signin?next=/"), Some(ThreatType::SocialEngineering));
+        assert_eq!(db.check_url("https://www.login-paypa1.test/"), Some(ThreatType::SocialEngineering));
+        assert_eq!(db.check_url("https://example.com/"), None);
+
+        // Remove the phishing prefix by its index in the sorted list
+        let mut sorted = [phishing.clone(), other.clone()];
+        sorted.sort();
+        let index = sorted.iter().position(|p| *p == phishing).unwrap();
+        let partial = format!(
+            r#"{{ "listUpdateResponses": [{{
+                "threatType": "SOCIAL_ENGINEERING",
+                "responseType": "PARTIAL_UPDATE",
+                "removals": [{{ "compressionType": "RAW", "rawIndices": {{ "indices": [{}] }} }}],
+                "newClientState": "state-2",
+                "checksum": {{ "sha256": "{}" }}
+            }}] }}"#,
+            index,
+            BASE64_STANDARD.encode(Sha256::digest(&other))
+        );
+        db.apply_update(&partial).unwrap();
+        assert_eq!(db.check_url("https://login-paypa1.test/signin"), None);
+        assert_eq!(db.client_state(ThreatType::SocialEngineering), Some("state-2"));
+    }
+
+    #[test]
+    fn test_checksum_mismatch_keeps_previous_list() {
+        let mut db = SafeBrowsingDb::new();
+        let malware = prefix("malware.test/", 32);
+        db.apply_update(&full_update("MALWARE", &[malware], 32)).unwrap();
+
+        let corrupt = full_update("MALWARE", &[prefix("other.test/", 32)], 32).replace("state-1", "state-2");
+        let corrupt = corrupt.replace(r#""sha256": ""#, r#""sha256": "AAAA"#);
+        assert_eq!(db.apply_update(&corrupt), Err(UpdateError::ChecksumMismatch(ThreatType::Malware)));
+        assert_eq!(db.check_url("http://malware.test/download.exe"), Some(ThreatType::Malware));
+        assert_eq!(db.client_state(ThreatType::Malware), Some("state-1"));
+
+        // Lists before the one that fails aren't updated either
+        let good = full_update("SOCIAL_ENGINEERING", &[prefix("phish.test/", 32)], 32);
+        let both = format!("{}, {}", good.trim_end().trim_end_matches("] }"), corrupt.trim_start().trim_start_matches(r#"{ "listUpdateResponses": ["#));
+        assert_eq!(db.apply_update(&both), Err(UpdateError::ChecksumMismatch(ThreatType::Malware)));
+        assert_eq!(db.check_url("http://phish.test/"), None);
+        assert_eq!(db.client_state(ThreatType::SocialEngineering), None);
+    }
+
+    #[test]
+    fn test_update_request_and_saved_lists() {
+        let mut db = SafeBrowsingDb::new();
+        db.apply_update(&full_update("MALWARE", &[prefix("malware.test/", 4)], 4)).unwrap();
+        let request: serde_json::Value = serde_json::from_str(&db.update_request()).unwrap();
+        let states: Vec<_> = request["listUpdateRequests"].as_array().unwrap().iter().map(|list| (list["threatType"].as_str().unwrap(), list["state"].as_str().unwrap())).collect();
+        assert_eq!(states, vec![("MALWARE", "state-1"), ("SOCIAL_ENGINEERING", ""), ("UNWANTED_SOFTWARE", "")]);
+
+        let saved: SafeBrowsingDb = serde_json::from_str(&serde_json::to_string(&db).unwrap()).unwrap();
+        assert_eq!(saved.check_url("http://malware.test/"), Some(ThreatType::Malware));
+        assert_eq!(saved.client_state(ThreatType::Malware), Some("state-1"));
+    }
+}
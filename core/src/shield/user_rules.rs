@@ -0,0 +1,403 @@
+//! User rules file format.
+//!
+//! A thin layer over ABP/uBlock filter syntax for rules people write by hand:
+//!
+//! ```text
+//! ! Comments start with `!`, or with `#` followed by a space
+//! # Rules before any section apply everywhere
+//! ||ads.example.com^
+//!
+//! include "work.rules"          # other rule files, relative to this one
+//!
+//! [news.example.com, news.example.org]
+//! ##.newsletter-popup            # becomes news.example.com,news.example.org##.newsletter-popup
+//! ||cdn.tracker.test^            # becomes ||cdn.tracker.test^$domain=news.example.com|news.example.org
+//!
+//! [*]                            # back to global rules
+//! ```
+//!
+//! Compiling never fails as a whole: rejected lines are reported as [`Diagnostic`]s and the
+//! remaining rules are still loaded.
+
+use adblock::lists::{FilterParseError, ParseOptions, parse_filter};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Includes nested deeper than this are rejected (and catch cycles the stack check misses).
+const MAX_INCLUDE_DEPTH: usize = 8;
+
+/// Markers that start the selector part of a cosmetic rule.
+const COSMETIC_MARKERS: [&str; 5] = ["#@#", "#?#", "#$#", "#@?#", "##"];
+
+/// A problem found while compiling, pointing at the offending spot (1-based line and column).
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub file: String,
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}: {}", self.file, self.line, self.column, self.message)
+    }
+}
+
+/// Rules ready for [`super::WaveShield::load_user_rules`], and everything that was rejected.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CompiledRules {
+    pub filters: Vec<String>,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl CompiledRules {
+    pub fn has_errors(&self) -> bool {
+        !self.diagnostics.is_empty()
+    }
+}
+
+/// Compiles a rules file from disk, resolving includes relative to the including file.
+pub fn compile_file(path: &Path) -> CompiledRules {
+    let mut compiled = CompiledRules::default();
+    let name = path.display().to_string();
+    match std::fs::read_to_string(path) {
+        Ok(source) => {
+            let mut compiler = Compiler {
+                loader: &|p: &Path| std::fs::read_to_string(p),
+                stack: vec![path.to_path_buf()],
+                out: &mut compiled,
+            };
+            compiler.compile(&source, path);
+        }
+        Err(e) => compiled.diagnostics.push(Diagnostic {
+            file: name,
+            line: 0,
+            column: 0,
+            message: format!("cannot read file: {}", e),
+        }),
+    }
+    compiled
+}
+
+/// Compiles rules from memory (e.g. the editor). `origin` names the source in diagnostics and
+/// anchors relative includes, which are read through `loader`.
+pub fn compile(source: &str, origin: &Path, loader: &dyn Fn(&Path) -> io::Result<String>) -> CompiledRules {
+    let mut compiled = CompiledRules::default();
+    let mut compiler = Compiler {
+        loader,
+        stack: vec![origin.to_path_buf()],
+        out: &mut compiled,
+    };
+    compiler.compile(source, origin);
+    compiled
+}
+
+/// Adds `rule` to the `[site]` section of `source`, creating the section at the end if there is
+/// none. Used by the element picker to save rules without disturbing the user's layout.
+pub fn add_site_rule(source: &str, site: &str, rule: &str) -> String {
+    let mut lines: Vec<&str> = source.lines().collect();
+    let header = lines
+        .iter()
+        .position(|line| parse_section(line.trim()).is_ok_and(|sites| sites == [site.to_ascii_lowercase()]));
+
+    match header {
+        Some(header) => {
+            // After the section's last rule, keeping any blank lines before the next section
+            let end = lines[header + 1..]
+                .iter()
+                .position(|line| line.trim_start().starts_with('['))
+                .map_or(lines.len(), |offset| header + 1 + offset);
+            let insert_at = (header + 1..end)
+                .rev()
+                .find(|&index| !lines[index].trim().is_empty())
+                .map_or(header + 1, |index| index + 1);
+            lines.insert(insert_at, rule);
+        }
+        None => {
+            let section = format!("[{}]", site);
+            if lines.last().is_some_and(|line| !line.trim().is_empty()) {
+                lines.push("");
+            }
+            let mut out = lines.join("\n");
+            if !out.is_empty() {
+                out.push('\n');
+            }
+            return format!("{}{}\n{}\n", out, section, rule);
+        }
+    }
+    lines.join("\n") + "\n"
+}
+
+struct Compiler<'a> {
+    loader: &'a dyn Fn(&Path) -> io::Result<String>,
+    stack: Vec<PathBuf>,
+    out: &'a mut CompiledRules,
+}
+
+impl Compiler<'_> {
+    fn compile(&mut self, source: &str, path: &Path) {
+        let file = path.display().to_string();
+        // Sites of the current section; empty means global
+        let mut sites: Vec<String> = Vec::new();
+
+        for (index, raw_line) in source.lines().enumerate() {
+            let line = index + 1;
+            let trimmed = raw_line.trim();
+            let column = raw_line.len() - raw_line.trim_start().len() + 1;
+            let error = |column: usize, message: String| Diagnostic {
+                file: file.clone(),
+                line,
+                column,
+                message,
+            };
+
+            if trimmed.is_empty() || is_comment(trimmed) {
+                continue;
+            }
+
+            if trimmed.starts_with('[') {
+                match parse_section(trimmed) {
+                    Ok(section) => sites = section,
+                    Err((offset, message)) => {
+                        self.out.diagnostics.push(error(column + offset, message));
+                        // Don't leak the previous section's scope into the following rules
+                        sites.clear();
+                    }
+                }
+                continue;
+            }
+
+            if let Some(rest) = trimmed.strip_prefix("include")
+                && (rest.is_empty() || rest.starts_with(char::is_whitespace))
+            {
+                let arg_column = column + "include".len() + (rest.len() - rest.trim_start().len());
+                if let Err(message) = self.include(rest.trim(), path) {
+                    self.out.diagnostics.push(error(arg_column, message));
+                }
+                continue;
+            }
+
+            match scope_rule(trimmed, &sites) {
+                Ok(rule) => match parse_filter(&rule, false, ParseOptions::default()) {
+                    Ok(_) => self.out.filters.push(rule),
+                    Err(e) => self.out.diagnostics.push(error(column + error_offset(trimmed, &e), describe(&e))),
+                },
+                Err((offset, message)) => self.out.diagnostics.push(error(column + offset, message)),
+            }
+        }
+    }
+
+    fn include(&mut self, argument: &str, from: &Path) -> Result<(), String> {
+        let target = argument.trim_matches('"');
+        if target.is_empty() {
+            return Err("include needs a file path".to_string());
+        }
+        let path = from.parent().unwrap_or(Path::new("")).join(target);
+
+        if self.stack.contains(&path) {
+            return Err(format!("'{}' includes itself", target));
+        }
+        if self.stack.len() > MAX_INCLUDE_DEPTH {
+            return Err(format!("includes are nested more than {} levels deep", MAX_INCLUDE_DEPTH));
+        }
+
+        let source = (self.loader)(&path).map_err(|e| format!("cannot include '{}': {}", target, e))?;
+        self.stack.push(path.clone());
+        self.compile(&source, &path);
+        self.stack.pop();
+        Ok(())
+    }
+}
+
+fn is_comment(line: &str) -> bool {
+    if line.starts_with('!') {
+        return true;
+    }
+    // `#` alone or followed by a space is a comment; `##`, `#@#`... start cosmetic rules
+    line == "#" || line.starts_with("# ") || line.starts_with("#\t")
+}
+
+/// Parses `[site, site]` or `[*]`. Errors carry the 0-based offset of the problem.
+fn parse_section(line: &str) -> Result<Vec<String>, (usize, String)> {
+    let Some(inner) = line.strip_prefix('[').and_then(|l| l.split(" #").next()).map(str::trim_end) else {
+        return Err((0, "expected a section header".to_string()));
+    };
+    let Some(inner) = inner.strip_suffix(']') else {
+        return Err((line.len(), "missing ']' at the end of the section header".to_string()));
+    };
+
+    if inner.trim() == "*" {
+        return Ok(Vec::new());
+    }
+
+    let mut sites = Vec::new();
+    let mut offset = 1;
+    for part in inner.split(',') {
+        let site = part.trim();
+        let site_offset = offset + (part.len() - part.trim_start().len());
+        let valid = !site.is_empty()
+            && site
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-' || c == '*' || c == '~')
+            && !site.starts_with('.')
+            && !site.ends_with('.');
+        if !valid {
+            return Err((site_offset, format!("'{}' is not a valid site name", site)));
+        }
+        sites.push(site.to_ascii_lowercase());
+        offset += part.len() + 1;
+    }
+    Ok(sites)
+}
+
+/// Restricts a rule to the section's sites. Errors carry the 0-based offset of the problem.
+fn scope_rule(rule: &str, sites: &[String]) -> Result<String, (usize, String)> {
+    if sites.is_empty() {
+        return Ok(rule.to_string());
+    }
+
+    if let Some(marker) = cosmetic_marker(rule) {
+        if marker > 0 {
+            return Err((0, "rule names its own sites; move it out of the [site] section".to_string()));
+        }
+        return Ok(format!("{}{}", sites.join(","), rule));
+    }
+
+    // Network rule: options follow the last `$`, unless the rule is a /regex/
+    let options_start = if rule.ends_with('/') { None } else { rule.rfind('$') };
+    match options_start {
+        Some(dollar) => {
+            if let Some(domain) = rule[dollar..].find("domain=") {
+                return Err((dollar + domain, "rule has its own domain= option; move it out of the [site] section".to_string()));
+            }
+            Ok(format!("{},domain={}", rule, sites.join("|")))
+        }
+        None => Ok(format!("{}$domain={}", rule, sites.join("|"))),
+    }
+}
+
+fn cosmetic_marker(rule: &str) -> Option<usize> {
+    COSMETIC_MARKERS.iter().filter_map(|marker| rule.find(marker)).min()
+}
+
+/// Where in the rule the adblock parser's complaint most likely points.
+fn error_offset(rule: &str, error: &FilterParseError) -> usize {
+    match error {
+        FilterParseError::Network(_) => rule.rfind('$').unwrap_or(0),
+        FilterParseError::Cosmetic(_) => cosmetic_marker(rule).unwrap_or(0),
+        _ => 0,
+    }
+}
+
+fn describe(error: &FilterParseError) -> String {
+    match error {
+        FilterParseError::Network(e) => format!("invalid network rule: {}", e),
+        FilterParseError::Cosmetic(e) => format!("invalid cosmetic rule: {}", e),
+        FilterParseError::Unsupported => "unsupported rule syntax".to_string(),
+        FilterParseError::Empty => "empty rule".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn compile_with(files: &[(&str, &str)], main: &str) -> CompiledRules {
+        let files: HashMap<PathBuf, String> = files.iter().map(|(p, s)| (PathBuf::from(p), s.to_string())).collect();
+        compile(main, Path::new("rules/user.rules"), &|path: &Path| {
+            files
+                .get(path)
+                .cloned()
+                .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no such file"))
+        })
+    }
+
+    #[test]
+    fn test_sections_and_comments() {
+        let compiled = compile_with(
+            &[],
+            "! Global rules\n\
+             # also a comment\n\
+             ||ads.example.com^\n\
+             \n\
+             [news.example.com, News.Example.org]\n\
+             ##.newsletter-popup\n\
+             ||cdn.tracker.test^\n\
+             @@||comments.test^$script\n\
+             \n\
+             [*]\n\
+             ###global-banner\n",
+        );
+
+        assert_eq!(compiled.diagnostics, vec![]);
+        assert_eq!(
+            compiled.filters,
+            vec![
+                "||ads.example.com^",
+                "news.example.com,news.example.org##.newsletter-popup",
+                "||cdn.tracker.test^$domain=news.example.com|news.example.org",
+                "@@||comments.test^$script,domain=news.example.com|news.example.org",
+                "###global-banner",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_includes() {
+        let compiled = compile_with(
+            &[
+                ("rules/work.rules", "||intranet-tracker.test^\ninclude \"shared/common.rules\"\n"),
+                ("rules/shared/common.rules", "||common.test^\n"),
+                ("rules/loop.rules", "include loop.rules\n"),
+            ],
+            "include \"work.rules\"\ninclude loop.rules\ninclude missing.rules\n",
+        );
+
+        assert_eq!(compiled.filters, vec!["||intranet-tracker.test^", "||common.test^"]);
+        assert_eq!(compiled.diagnostics.len(), 2);
+        assert_eq!(compiled.diagnostics[0].file, "rules/loop.rules");
+        assert!(compiled.diagnostics[0].message.contains("includes itself"));
+        assert_eq!((compiled.diagnostics[1].line, compiled.diagnostics[1].column), (3, 9));
+        assert!(compiled.diagnostics[1].message.contains("cannot include 'missing.rules'"));
+    }
+
+    #[test]
+    fn test_add_site_rule() {
+        let source = "||ads.example.com^\n\n[news.example.com]\n##.popup\n\n[*]\n###banner\n";
+        assert_eq!(
+            add_site_rule(source, "news.example.com", "##.ad-slot"),
+            "||ads.example.com^\n\n[news.example.com]\n##.popup\n##.ad-slot\n\n[*]\n###banner\n"
+        );
+        assert_eq!(
+            add_site_rule(source, "shop.test", "##.ad-slot"),
+            format!("{}\n[shop.test]\n##.ad-slot\n", source)
+        );
+        assert_eq!(add_site_rule("", "shop.test", "##.ad-slot"), "[shop.test]\n##.ad-slot\n");
+    }
+
+    #[test]
+    fn test_diagnostics_point_at_the_problem() {
+        let compiled = compile_with(
+            &[],
+            "||good.test^\n\
+             ||bad.test^$nonsense-option\n\
+             [news.example.com, bad site]\n\
+             ##.still-scoped-to-nothing\n\
+             [news.example.com\n\
+             [example.com]\n\
+             example.org##.ad\n\
+             ||x.test^$domain=y.test\n",
+        );
+
+        assert_eq!(compiled.filters, vec!["||good.test^", "##.still-scoped-to-nothing"]);
+        let positions: Vec<(usize, usize)> = compiled.diagnostics.iter().map(|d| (d.line, d.column)).collect();
+        assert_eq!(positions, vec![(2, 12), (3, 20), (5, 18), (7, 1), (8, 11)]);
+        assert!(compiled.diagnostics[0].message.contains("unrecognised option"));
+        assert!(compiled.diagnostics[1].message.contains("'bad site'"));
+        assert!(compiled.diagnostics[0].to_string().starts_with("rules/user.rules:2:12:"));
+    }
+}
@@ -1,27 +1,69 @@
 use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fmt;
+use std::time::{SystemTime, UNIX_EPOCH};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+pub mod essentials;
+pub mod identity;
+pub mod journal;
+pub mod layout;
+pub mod lifecycle;
+pub mod panel;
+pub mod recently_closed;
+pub mod store;
+pub mod template;
+
+pub use layout::{Axis, Layout, LayoutError, Placement, SplitChild};
+pub use essentials::Essentials;
+pub use identity::SpaceIdentity;
+pub use lifecycle::{LifecycleManager, PanelState};
+pub use panel::{HistoryEntry, Panel};
+pub use recently_closed::{ClosedItem, ClosedPanel, RecentlyClosed};
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct Space {
     pub id: String,
     pub name: String,
     pub panels: Vec<Panel>,
     pub layout: Layout,
+    /// Panel the space shows in the engine; always one placed in the layout
+    #[serde(default)]
+    pub focused_panel: Option<String>,
+    /// Icon and colors; the browser theme's when unset
+    #[serde(default)]
+    pub identity: SpaceIdentity,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct Panel {
-    pub id: String,
-    pub title: String,
-    pub url: String,
-    // Future: History stack, zoom level, scroll position
+#[derive(Debug, Clone, PartialEq)]
+pub enum SpaceError {
+    /// The layout doesn't fit the panels: it references a missing panel, places one twice, or
+    /// has a malformed split
+    Layout(LayoutError),
+    /// A panel the layout never shows
+    OrphanedPanel(String),
+    /// Two panels share an ID
+    DuplicatePanelId(String),
+    /// The focused panel isn't in the layout
+    UnknownFocus(String),
+}
+
+impl fmt::Display for SpaceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SpaceError::Layout(e) => write!(f, "{}", e),
+            SpaceError::OrphanedPanel(id) => write!(f, "panel {} is not in the layout", id),
+            SpaceError::DuplicatePanelId(id) => write!(f, "more than one panel has ID {}", id),
+            SpaceError::UnknownFocus(id) => write!(f, "focused panel {} is not in the layout", id),
+        }
+    }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub enum Layout {
-    Single, // One panel full width
-    SplitVertical(String, String), // Two panels left/right (IDs)
-    SplitHorizontal(String, String), // Two panels top/bottom (IDs)
-    Grid(Vec<String>), // N panels in a grid
+impl std::error::Error for SpaceError {}
+
+impl From<LayoutError> for SpaceError {
+    fn from(e: LayoutError) -> Self {
+        SpaceError::Layout(e)
+    }
 }
 
 impl Space {
@@ -30,16 +72,245 @@ impl Space {
             id: uuid::Uuid::new_v4().to_string(),
             name: name.to_string(),
             panels: Vec::new(),
-            layout: Layout::Single,
+            layout: Layout::Empty,
+            focused_panel: None,
+            identity: SpaceIdentity::default(),
         }
     }
 
-    pub fn add_panel(&mut self, url: &str) {
-        let panel = Panel {
-            id: uuid::Uuid::new_v4().to_string(),
-            title: "New Panel".to_string(),
-            url: url.to_string(),
-        };
+    pub fn panel(&self, id: &str) -> Option<&Panel> {
+        self.panels.iter().find(|panel| panel.id == id)
+    }
+
+    pub fn panel_mut(&mut self, id: &str) -> Option<&mut Panel> {
+        self.panels.iter_mut().find(|panel| panel.id == id)
+    }
+
+    /// The focused panel, falling back to the first one in the layout.
+    pub fn focused(&self) -> Option<&Panel> {
+        match &self.focused_panel {
+            Some(id) => self.panel(id),
+            None => self.layout.panel_ids().first().and_then(|id| self.panel(id)),
+        }
+    }
+
+    pub fn focused_mut(&mut self) -> Option<&mut Panel> {
+        let id = self.focused()?.id.clone();
+        self.panel_mut(&id)
+    }
+
+    /// Opens `url` in a new panel at the right edge of the layout. Returns the panel's ID.
+    pub fn add_panel(&mut self, url: &str) -> String {
+        let panel = Panel::new(url);
+        self.layout.push(&panel.id, Axis::Row);
+        let id = panel.id.clone();
+        self.panels.push(panel);
+        id
+    }
+
+    /// Opens `url` in a new panel next to `target`, splitting `target`'s space along `axis`.
+    /// Returns the new panel's ID, or `None` if `target` isn't in the layout.
+    pub fn split_panel(&mut self, target: &str, url: &str, axis: Axis) -> Option<String> {
+        let panel = Panel::new(url);
+        if !self.layout.split(target, &panel.id, axis) {
+            return None;
+        }
+        let id = panel.id.clone();
+        self.panels.push(panel);
+        Some(id)
+    }
+
+    /// Closes a panel, taking it out of the layout. Focus passes to the first remaining panel.
+    pub fn remove_panel(&mut self, id: &str) -> Option<Panel> {
+        let index = self.panels.iter().position(|panel| panel.id == id)?;
+        self.layout.close(id);
+        if self.focused_panel.as_deref() == Some(id) {
+            self.focused_panel = self.layout.panel_ids().first().map(|id| id.to_string());
+        }
+        Some(self.panels.remove(index))
+    }
+
+    /// Closes a panel, remembering where it sat so [`Space::reopen_panel`] can put it back.
+    pub fn close_panel(&mut self, id: &str) -> Option<ClosedPanel> {
+        let placement = self.layout.placement(id);
+        let panel = self.remove_panel(id)?;
+        Some(ClosedPanel {
+            space_id: self.id.clone(),
+            panel,
+            placement,
+            closed_at: unix_now(),
+        })
+    }
+
+    /// Closes every panel that isn't pinned, most recently used last in the returned list.
+    pub fn close_all(&mut self) -> Vec<ClosedPanel> {
+        let mut ids: Vec<_> = self.panels.iter().filter(|panel| !panel.pinned).map(|panel| (panel.last_active, panel.id.clone())).collect();
+        ids.sort();
+        ids.into_iter().filter_map(|(_, id)| self.close_panel(&id)).collect()
+    }
+
+    /// Puts a closed panel back where it sat, or at the right edge of the layout if its
+    /// neighbours are gone too. Returns false if the space already has a panel with its ID.
+    pub fn reopen_panel(&mut self, closed: ClosedPanel) -> bool {
+        let panel = closed.panel;
+        if self.panel(&panel.id).is_some() {
+            return false;
+        }
+        let restored = closed.placement.is_some_and(|placement| self.layout.restore(&panel.id, &placement));
+        if !restored {
+            self.layout.push(&panel.id, Axis::Row);
+        }
+        self.panels.push(panel);
+        true
+    }
+
+    /// Adds a panel from elsewhere, like the essentials, at the right edge of the layout.
+    /// Returns false if the space already has a panel with its ID.
+    pub fn insert_panel(&mut self, panel: Panel) -> bool {
+        if self.panel(&panel.id).is_some() {
+            return false;
+        }
+        self.layout.push(&panel.id, Axis::Row);
         self.panels.push(panel);
+        true
+    }
+
+    /// Moves a panel next to `target`, splitting `target`'s space along `axis`.
+    /// Returns false if either panel isn't in the layout or they are the same panel.
+    pub fn move_panel(&mut self, id: &str, target: &str, axis: Axis) -> bool {
+        if id == target || !self.layout.contains(id) || !self.layout.contains(target) {
+            return false;
+        }
+        self.layout.close(id);
+        self.layout.split(target, id, axis)
+    }
+
+    /// Focuses a panel of the layout. Returns false if the layout doesn't show it.
+    pub fn focus_panel(&mut self, id: &str) -> bool {
+        if !self.layout.contains(id) {
+            return false;
+        }
+        self.focused_panel = Some(id.to_string());
+        if let Some(panel) = self.panel_mut(id) {
+            panel.touch();
+        }
+        true
+    }
+
+    /// Checks that the layout shows every panel exactly once and nothing else.
+    pub fn validate(&self) -> Result<(), SpaceError> {
+        let mut ids = HashSet::new();
+        for panel in &self.panels {
+            if !ids.insert(panel.id.as_str()) {
+                return Err(SpaceError::DuplicatePanelId(panel.id.clone()));
+            }
+        }
+        self.layout.validate(ids.iter().copied())?;
+        if let Some(panel) = self.panels.iter().find(|panel| !self.layout.contains(&panel.id)) {
+            return Err(SpaceError::OrphanedPanel(panel.id.clone()));
+        }
+        match &self.focused_panel {
+            Some(id) if !self.layout.contains(id) => Err(SpaceError::UnknownFocus(id.clone())),
+            _ => Ok(()),
+        }
+    }
+
+    /// Makes the space valid again, keeping as much as possible: the first of several panels
+    /// sharing an ID wins, dangling layout references are dropped, and orphaned panels are
+    /// placed at the right edge of the layout.
+    pub fn repair(&mut self) {
+        let mut seen = HashSet::new();
+        self.panels.retain(|panel| seen.insert(panel.id.clone()));
+
+        let known: HashSet<&str> = self.panels.iter().map(|panel| panel.id.as_str()).collect();
+        self.layout.repair(&known);
+        for panel in &self.panels {
+            if !self.layout.contains(&panel.id) {
+                self.layout.push(&panel.id, Axis::Row);
+            }
+        }
+
+        if self.focused_panel.as_ref().is_some_and(|id| !self.layout.contains(id)) {
+            self.focused_panel = None;
+        }
+    }
+}
+
+/// Seconds since the Unix epoch.
+pub(crate) fn unix_now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn space_with(urls: &[&str]) -> (Space, Vec<String>) {
+        let mut space = Space::new("Test");
+        let ids = urls.iter().map(|url| space.add_panel(url)).collect();
+        (space, ids)
+    }
+
+    #[test]
+    fn test_panel_operations_keep_layout_in_sync() {
+        let (mut space, ids) = space_with(&["https://a.test", "https://b.test", "https://c.test"]);
+        assert_eq!(space.layout.panel_ids(), ids.iter().map(String::as_str).collect::<Vec<_>>());
+        assert_eq!(space.validate(), Ok(()));
+
+        assert!(space.move_panel(&ids[0], &ids[2], Axis::Column));
+        assert_eq!(space.layout.panel_ids(), vec![ids[1].as_str(), ids[2].as_str(), ids[0].as_str()]);
+        assert!(!space.move_panel(&ids[0], &ids[0], Axis::Row));
+        assert!(!space.move_panel(&ids[0], "missing", Axis::Row));
+        assert_eq!(space.validate(), Ok(()));
+
+        assert!(space.focus_panel(&ids[2]));
+        assert!(!space.focus_panel("missing"));
+        assert_eq!(space.remove_panel(&ids[2]).map(|panel| panel.url), Some("https://c.test/".to_string()));
+        assert_eq!(space.focused_panel.as_deref(), Some(ids[1].as_str()));
+        assert_eq!(space.remove_panel(&ids[2]), None);
+        assert_eq!(space.validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_validate_reports_dangling_and_orphaned_panels() {
+        let (mut space, ids) = space_with(&["https://a.test", "https://b.test"]);
+
+        space.panels.remove(1);
+        assert_eq!(space.validate(), Err(SpaceError::Layout(LayoutError::UnknownPanel(ids[1].clone()))));
+
+        space.layout = Layout::Panel(ids[0].clone());
+        space.panels.push(Panel::new("https://c.test"));
+        let orphan = space.panels[1].id.clone();
+        assert_eq!(space.validate(), Err(SpaceError::OrphanedPanel(orphan)));
+    }
+
+    #[test]
+    fn test_repair() {
+        let (mut space, ids) = space_with(&["https://a.test", "https://b.test"]);
+        let orphan = Panel::new("https://c.test");
+        space.panels.push(orphan.clone());
+        space.panels.push(orphan.clone());
+        space.panels.remove(0);
+        space.focused_panel = Some(ids[0].clone());
+
+        space.repair();
+        assert_eq!(space.validate(), Ok(()));
+        assert_eq!(space.layout.panel_ids(), vec![ids[1].as_str(), orphan.id.as_str()]);
+        assert_eq!(space.panels.len(), 2);
+        assert_eq!(space.focused_panel, None);
+        assert_eq!(space.focused().map(|panel| panel.id.as_str()), Some(ids[1].as_str()));
+    }
+
+    #[test]
+    fn test_close_all_keeps_pinned_panels() {
+        let (mut space, ids) = space_with(&["https://mail.test", "https://a.test", "https://b.test"]);
+        space.panel_mut(&ids[0]).unwrap().pinned = true;
+        space.focus_panel(&ids[2]);
+
+        let closed = space.close_all();
+        assert_eq!(closed.len(), 2);
+        assert_eq!(space.layout, Layout::Panel(ids[0].clone()));
+        assert_eq!(space.focused().map(|panel| panel.id.as_str()), Some(ids[0].as_str()));
+        assert_eq!(space.validate(), Ok(()));
     }
 }
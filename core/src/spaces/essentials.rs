@@ -0,0 +1,79 @@
+//! Essentials: panels that belong to no space and are at hand in all of them.
+//!
+//! Essentials are always pinned, so they keep to their origin like pinned panels do, and
+//! "close all" in a space never touches them.
+
+use super::Panel;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(transparent)]
+pub struct Essentials {
+    panels: Vec<Panel>,
+}
+
+impl Essentials {
+    /// Opens `url` as a new essential. Returns its ID.
+    pub fn add(&mut self, url: &str) -> String {
+        self.adopt(Panel::new(url))
+    }
+
+    /// Makes a panel taken out of a space an essential. Returns its ID.
+    pub fn adopt(&mut self, mut panel: Panel) -> String {
+        panel.pinned = true;
+        let id = panel.id.clone();
+        self.panels.retain(|existing| existing.id != id);
+        self.panels.push(panel);
+        id
+    }
+
+    pub fn remove(&mut self, id: &str) -> Option<Panel> {
+        let index = self.panels.iter().position(|panel| panel.id == id)?;
+        Some(self.panels.remove(index))
+    }
+
+    pub fn get(&self, id: &str) -> Option<&Panel> {
+        self.panels.iter().find(|panel| panel.id == id)
+    }
+
+    pub fn get_mut(&mut self, id: &str) -> Option<&mut Panel> {
+        self.panels.iter_mut().find(|panel| panel.id == id)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Panel> {
+        self.panels.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.panels.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.panels.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::spaces::Space;
+
+    #[test]
+    fn test_panels_move_between_a_space_and_the_essentials() {
+        let mut space = Space::new("Work");
+        let mail = space.add_panel("https://mail.test");
+        space.add_panel("https://a.test");
+
+        let mut essentials = Essentials::default();
+        let chat = essentials.add("https://chat.test");
+        essentials.adopt(space.remove_panel(&mail).unwrap());
+        assert_eq!(essentials.iter().map(|panel| panel.id.as_str()).collect::<Vec<_>>(), vec![chat.as_str(), mail.as_str()]);
+        assert!(essentials.iter().all(|panel| panel.pinned));
+        assert!(!essentials.get(&mail).unwrap().allows_navigation("https://a.test"));
+        assert_eq!(space.validate(), Ok(()));
+
+        assert!(space.insert_panel(essentials.remove(&chat).unwrap()));
+        assert_eq!(space.layout.panel_ids().last(), Some(&chat.as_str()));
+        assert_eq!(essentials.len(), 1);
+    }
+}
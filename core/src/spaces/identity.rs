@@ -0,0 +1,61 @@
+//! How a space looks: an icon and colors that set it apart from the others while it is active.
+
+use serde::{Deserialize, Serialize};
+use wave_common::theme::{AccentColor, CatppuccinFlavor, Theme};
+
+/// Characters an icon may have; enough for an emoji sequence or a couple of letters.
+pub const MAX_ICON_CHARS: usize = 8;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SpaceIdentity {
+    /// An emoji or short text shown in place of the folder icon
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub icon: Option<String>,
+    /// Overrides the browser theme's accent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub accent: Option<AccentColor>,
+    /// Overrides the browser theme's flavor
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub flavor: Option<CatppuccinFlavor>,
+}
+
+impl SpaceIdentity {
+    /// Sets the icon from user input: trimmed, cut to [`MAX_ICON_CHARS`], blank clears it.
+    pub fn set_icon(&mut self, input: &str) {
+        let icon: String = input.trim().chars().take(MAX_ICON_CHARS).collect();
+        self.icon = (!icon.is_empty()).then_some(icon);
+    }
+
+    /// `base` with the space's overrides applied.
+    pub fn theme(&self, base: &Theme) -> Theme {
+        Theme {
+            flavor: self.flavor.unwrap_or(base.flavor),
+            accent: self.accent.unwrap_or(base.accent),
+            ..base.clone()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::spaces::Space;
+
+    #[test]
+    fn test_overrides() {
+        let base = Theme::default_wave();
+        let mut identity = SpaceIdentity::default();
+        identity.set_icon("  🚀  ");
+        assert_eq!(identity.icon.as_deref(), Some("🚀"));
+        identity.set_icon(" ");
+        assert_eq!(identity.icon, None);
+
+        identity.accent = Some(AccentColor::Peach);
+        let theme = identity.theme(&base);
+        assert_eq!((theme.flavor, theme.accent), (base.flavor, AccentColor::Peach));
+
+        // Spaces saved before identities existed
+        let space: Space = serde_json::from_str(r#"{ "id": "s", "name": "Old", "panels": [], "layout": "Empty" }"#).unwrap();
+        assert_eq!(space.identity, SpaceIdentity::default());
+    }
+}
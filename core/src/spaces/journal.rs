@@ -0,0 +1,556 @@
+//! Session journal: an append-only log of changes to the spaces, for crash recovery.
+//!
+//! `session.journal` holds one JSON record per line. It starts with a snapshot of the whole
+//! state; every [`SessionJournal::record`] appends only what changed since. A clean exit ends
+//! the log with a shutdown record, so a journal that doesn't end with one was left by a run
+//! that died, and replaying it gives back the state at the time. Once the log grows long it is
+//! compacted into a fresh snapshot, written atomically like the spaces file.
+//!
+//! The journal of a run that died is kept as `session.journal.previous` until the user
+//! answers the offer to restore it, so quitting or crashing again first doesn't lose it.
+//!
+//! The snapshot carries the spaces schema version. Later records embed panels and spaces
+//! without one, so they can't be migrated like the spaces file is: a journal written at another
+//! version is discarded rather than offered.
+//!
+//! Records aren't synced to disk one by one: they survive the process dying, not power loss.
+
+use super::recently_closed::MAX_RECENTLY_CLOSED;
+use super::store::{SCHEMA_VERSION, SpacesState, StoreError};
+use super::{ClosedItem, ClosedPanel, Essentials, Layout, Panel, Placement, Space, unix_now};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+const FILE_NAME: &str = "session.journal";
+const PREVIOUS_FILE_NAME: &str = "session.journal.previous";
+
+/// Records appended before the log is compacted into a snapshot.
+pub const COMPACT_AFTER: usize = 500;
+
+/// What a run that didn't shut down cleanly left behind.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PreviousSession {
+    pub state: SpacesState,
+    /// Panels closed shortly before, most recent last
+    pub recently_closed: Vec<ClosedPanel>,
+}
+
+impl PreviousSession {
+    /// The state to continue from. With `reopen_closed`, the recently closed panels go back
+    /// where they were (into the active space, if theirs is gone).
+    pub fn restore(self, reopen_closed: bool) -> SpacesState {
+        let mut state = self.state;
+        if reopen_closed {
+            for closed in self.recently_closed {
+                ClosedItem::Panel(closed).restore(&mut state.spaces, state.active_space);
+            }
+        }
+        state
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "record", rename_all = "snake_case")]
+enum Record {
+    /// Everything at once; every journal starts with one
+    Snapshot { version: u64, state: SpacesState, recently_closed: Vec<ClosedPanel> },
+    /// A new space, or one whose own settings changed
+    SpaceSaved { index: usize, space: Space },
+    SpaceRemoved { space_id: String },
+    /// A new panel, or one that navigated, scrolled, ...
+    PanelSaved { space_id: String, panel: Panel },
+    PanelClosed {
+        space_id: String,
+        panel_id: String,
+        #[serde(default)]
+        placement: Option<Placement>,
+        closed_at: u64,
+    },
+    /// A panel left its space without being closed, to become an essential
+    PanelMadeEssential { space_id: String, panel_id: String },
+    LayoutChanged { space_id: String, layout: Layout, focused_panel: Option<String> },
+    ActiveSpace { index: usize },
+    EssentialsChanged { essentials: Essentials },
+    /// The browser exited normally
+    Shutdown,
+}
+
+pub struct SessionJournal {
+    path: PathBuf,
+    // The crashed run's journal, kept until the user restores it or starts fresh
+    previous_path: PathBuf,
+    file: File,
+    // What the log describes so far; new records are relative to it
+    state: SpacesState,
+    recently_closed: Vec<ClosedPanel>,
+    records: usize,
+}
+
+impl SessionJournal {
+    /// Starts the journal of this run in a profile directory, from `state`. If the previous
+    /// run didn't shut down cleanly and its journal holds anything `state` doesn't, that is
+    /// returned for the shell to offer restoring; it stays on disk, and is offered again by
+    /// the next run, until [`SessionJournal::discard_previous`].
+    pub fn open(profile_dir: &Path, state: &SpacesState) -> Result<(Self, Option<PreviousSession>), StoreError> {
+        fs::create_dir_all(profile_dir)?;
+        let path = profile_dir.join(FILE_NAME);
+        let previous_path = profile_dir.join(PREVIOUS_FILE_NAME);
+        let worth_offering = |previous: &PreviousSession| previous.state != *state || !previous.recently_closed.is_empty();
+
+        // A session still waiting for an answer comes first
+        let mut previous = read_journal(&previous_path)?.filter(worth_offering);
+        if previous.is_none() {
+            previous = read_journal(&path)?.filter(worth_offering);
+            match &previous {
+                Some(_) => fs::rename(&path, &previous_path)?,
+                None => remove_if_present(&previous_path)?,
+            }
+        }
+
+        let journal = Self {
+            file: write_snapshot(&path, state, &[])?,
+            path,
+            previous_path,
+            state: state.clone(),
+            recently_closed: Vec::new(),
+            records: 0,
+        };
+        Ok((journal, previous))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Forgets the session [`SessionJournal::open`] offered, once the user restored it or
+    /// chose to start fresh.
+    pub fn discard_previous(&self) -> Result<(), StoreError> {
+        remove_if_present(&self.previous_path)
+    }
+
+    /// Appends what changed between the last recorded state and `state`.
+    pub fn record(&mut self, state: &SpacesState) -> Result<(), StoreError> {
+        if *state == self.state {
+            return Ok(());
+        }
+        let mut records = diff(&self.state, state);
+        let mut replayed = self.state.clone();
+        let mut recently_closed = self.recently_closed.clone();
+        for record in &records {
+            apply(&mut replayed, &mut recently_closed, record.clone());
+        }
+        // Changes the records can't express, like reordered spaces, take a snapshot
+        if replayed != *state {
+            recently_closed = self.recently_closed.clone();
+            records = vec![Record::Snapshot {
+                version: SCHEMA_VERSION,
+                state: state.clone(),
+                recently_closed: recently_closed.clone(),
+            }];
+        }
+
+        for record in &records {
+            self.append(record)?;
+        }
+        self.state = state.clone();
+        self.recently_closed = recently_closed;
+        if self.records > COMPACT_AFTER {
+            self.compact()?;
+        }
+        Ok(())
+    }
+
+    /// Marks the session as ended cleanly; the next run won't offer to restore it.
+    pub fn shutdown(&mut self) -> Result<(), StoreError> {
+        self.append(&Record::Shutdown)?;
+        self.file.sync_all()?;
+        Ok(())
+    }
+
+    fn append(&mut self, record: &Record) -> Result<(), StoreError> {
+        let mut line = serde_json::to_string(record)?;
+        line.push('\n');
+        self.file.write_all(line.as_bytes())?;
+        self.records += 1;
+        Ok(())
+    }
+
+    /// Replaces the log with a snapshot of the current state.
+    fn compact(&mut self) -> Result<(), StoreError> {
+        self.file = write_snapshot(&self.path, &self.state, &self.recently_closed)?;
+        self.records = 0;
+        Ok(())
+    }
+}
+
+/// The session the journal at `path` describes; `None` if there is none or it ended cleanly.
+fn read_journal(path: &Path) -> Result<Option<PreviousSession>, StoreError> {
+    match fs::read_to_string(path) {
+        Ok(data) => Ok(replay(&data)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn remove_if_present(path: &Path) -> Result<(), StoreError> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+/// Atomically replaces the journal at `path` with a single snapshot; returns it open for appending.
+fn write_snapshot(path: &Path, state: &SpacesState, recently_closed: &[ClosedPanel]) -> Result<File, StoreError> {
+    let snapshot = Record::Snapshot {
+        version: SCHEMA_VERSION,
+        state: state.clone(),
+        recently_closed: recently_closed.to_vec(),
+    };
+    let tmp = path.with_extension("journal.tmp");
+    {
+        let mut file = File::create(&tmp)?;
+        file.write_all(format!("{}\n", serde_json::to_string(&snapshot)?).as_bytes())?;
+        file.sync_all()?;
+    }
+    fs::rename(&tmp, path)?;
+    Ok(OpenOptions::new().append(true).open(path)?)
+}
+
+/// Rebuilds the state a journal describes; `None` if it ended cleanly, has no snapshot, or was
+/// written at another schema version.
+fn replay(data: &str) -> Option<PreviousSession> {
+    let mut lines = data.lines();
+    let snapshot: serde_json::Value = serde_json::from_str(lines.next()?).ok()?;
+    // Journals from before the snapshot carried a version count as version 0
+    let version = snapshot["version"].as_u64().unwrap_or(0);
+    if version != SCHEMA_VERSION {
+        log::warn!("Discarding a session journal of schema version {} (expected {})", version, SCHEMA_VERSION);
+        return None;
+    }
+    let Ok(Record::Snapshot { mut state, mut recently_closed, .. }) = serde_json::from_value(snapshot) else {
+        return None;
+    };
+    let records = lines.map(serde_json::from_str::<Record>);
+    // A crash can tear the last line; everything before it still counts
+    for record in records.map_while(Result::ok) {
+        if let Record::Shutdown = record {
+            return None;
+        }
+        apply(&mut state, &mut recently_closed, record);
+    }
+    Some(PreviousSession { state, recently_closed })
+}
+
+fn apply(state: &mut SpacesState, recently_closed: &mut Vec<ClosedPanel>, record: Record) {
+    match record {
+        Record::Snapshot { state: snapshot, recently_closed: closed, .. } => {
+            *state = snapshot;
+            *recently_closed = closed;
+        }
+        Record::SpaceSaved { index, space } => {
+            state.spaces.retain(|existing| existing.id != space.id);
+            state.spaces.insert(index.min(state.spaces.len()), space);
+        }
+        Record::SpaceRemoved { space_id } => state.spaces.retain(|space| space.id != space_id),
+        Record::PanelSaved { space_id, panel } => {
+            if let Some(space) = space_mut(state, &space_id) {
+                match space.panel_mut(&panel.id) {
+                    Some(existing) => *existing = panel,
+                    None => space.panels.push(panel),
+                }
+            }
+        }
+        Record::PanelClosed { space_id, panel_id, placement, closed_at } => {
+            if let Some(space) = space_mut(state, &space_id)
+                && let Some(index) = space.panels.iter().position(|panel| panel.id == panel_id)
+            {
+                let panel = space.panels.remove(index);
+                recently_closed.push(ClosedPanel { space_id, panel, placement, closed_at });
+                if recently_closed.len() > MAX_RECENTLY_CLOSED {
+                    recently_closed.remove(0);
+                }
+            }
+        }
+        Record::PanelMadeEssential { space_id, panel_id } => {
+            if let Some(space) = space_mut(state, &space_id) {
+                space.panels.retain(|panel| panel.id != panel_id);
+            }
+        }
+        Record::LayoutChanged { space_id, layout, focused_panel } => {
+            if let Some(space) = space_mut(state, &space_id) {
+                space.layout = layout;
+                space.focused_panel = focused_panel;
+            }
+        }
+        Record::ActiveSpace { index } => state.active_space = index,
+        Record::EssentialsChanged { essentials } => state.essentials = essentials,
+        Record::Shutdown => {}
+    }
+}
+
+fn space_mut<'a>(state: &'a mut SpacesState, id: &str) -> Option<&'a mut Space> {
+    state.spaces.iter_mut().find(|space| space.id == id)
+}
+
+/// Records that turn `old` into `new`, as far as they can express it.
+fn diff(old: &SpacesState, new: &SpacesState) -> Vec<Record> {
+    let mut records = Vec::new();
+    for space in &old.spaces {
+        if !new.spaces.iter().any(|new_space| new_space.id == space.id) {
+            records.push(Record::SpaceRemoved { space_id: space.id.clone() });
+        }
+    }
+
+    for (index, space) in new.spaces.iter().enumerate() {
+        let Some(old_space) = old.spaces.iter().find(|old_space| old_space.id == space.id) else {
+            records.push(Record::SpaceSaved { index, space: space.clone() });
+            continue;
+        };
+        if settings_of(old_space) != settings_of(space) {
+            records.push(Record::SpaceSaved { index, space: space.clone() });
+            continue;
+        }
+
+        let space_id = space.id.clone();
+        for panel in &old_space.panels {
+            if space.panel(&panel.id).is_some() {
+                continue;
+            }
+            if new.essentials.get(&panel.id).is_some() && old.essentials.get(&panel.id).is_none() {
+                records.push(Record::PanelMadeEssential {
+                    space_id: space_id.clone(),
+                    panel_id: panel.id.clone(),
+                });
+            } else {
+                records.push(Record::PanelClosed {
+                    space_id: space_id.clone(),
+                    panel_id: panel.id.clone(),
+                    placement: old_space.layout.placement(&panel.id),
+                    closed_at: unix_now(),
+                });
+            }
+        }
+        for panel in &space.panels {
+            if old_space.panel(&panel.id) != Some(panel) {
+                records.push(Record::PanelSaved { space_id: space_id.clone(), panel: panel.clone() });
+            }
+        }
+        if old_space.layout != space.layout || old_space.focused_panel != space.focused_panel {
+            records.push(Record::LayoutChanged {
+                space_id,
+                layout: space.layout.clone(),
+                focused_panel: space.focused_panel.clone(),
+            });
+        }
+    }
+
+    if old.active_space != new.active_space {
+        records.push(Record::ActiveSpace { index: new.active_space });
+    }
+    if old.essentials != new.essentials {
+        records.push(Record::EssentialsChanged { essentials: new.essentials.clone() });
+    }
+    records
+}
+
+/// A space without its panels and their arrangement.
+fn settings_of(space: &Space) -> Space {
+    Space {
+        panels: Vec::new(),
+        layout: Layout::Empty,
+        focused_panel: None,
+        ..space.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::spaces::Axis;
+
+    fn temp_profile() -> PathBuf {
+        std::env::temp_dir().join(format!("wave-journal-{}", uuid::Uuid::new_v4()))
+    }
+
+    fn journal_lines(dir: &Path) -> usize {
+        fs::read_to_string(dir.join(FILE_NAME)).unwrap().lines().count()
+    }
+
+    #[test]
+    fn test_crash_is_recovered_with_closed_panels() {
+        let dir = temp_profile();
+        let mut state = SpacesState::first_run();
+        let (mut journal, previous) = SessionJournal::open(&dir, &state).unwrap();
+        assert_eq!(previous, None);
+
+        let space = &mut state.spaces[0];
+        let home = space.panels[0].id.clone();
+        let docs = space.split_panel(&home, "https://docs.test", Axis::Row).unwrap();
+        space.focus_panel(&docs);
+        journal.record(&state).unwrap();
+        state.spaces[0].panel_mut(&docs).unwrap().record_navigation("https://docs.test/guide");
+        journal.record(&state).unwrap();
+        let closed = state.spaces[0].remove_panel(&home).unwrap();
+        journal.record(&state).unwrap();
+        state.essentials.add("https://mail.test");
+        journal.record(&state).unwrap();
+        // Nothing changed, nothing written
+        let lines = journal_lines(&dir);
+        journal.record(&state).unwrap();
+        assert_eq!(journal_lines(&dir), lines);
+        drop(journal);
+
+        let (_journal, previous) = SessionJournal::open(&dir, &SpacesState::first_run()).unwrap();
+        let previous = previous.unwrap();
+        assert_eq!(previous.state, state);
+        assert_eq!(previous.recently_closed.iter().map(|closed| &closed.panel).collect::<Vec<_>>(), vec![&closed]);
+
+        let restored = previous.restore(true);
+        assert_eq!(restored.spaces[0].layout.panel_ids(), vec![home.as_str(), docs.as_str()]);
+        assert_eq!(restored.spaces[0].validate(), Ok(()));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_crashed_session_is_kept_until_answered() {
+        let dir = temp_profile();
+        let mut state = SpacesState::first_run();
+        let fresh = state.clone();
+        let (mut journal, _) = SessionJournal::open(&dir, &state).unwrap();
+        state.spaces.push(Space::new("Work"));
+        journal.record(&state).unwrap();
+        drop(journal);
+
+        // Quitting without answering, even cleanly, offers it again
+        let (mut journal, previous) = SessionJournal::open(&dir, &fresh).unwrap();
+        assert_eq!(previous.map(|previous| previous.state), Some(state.clone()));
+        journal.shutdown().unwrap();
+        drop(journal);
+
+        let (journal, previous) = SessionJournal::open(&dir, &fresh).unwrap();
+        assert_eq!(previous.map(|previous| previous.state), Some(state));
+        journal.discard_previous().unwrap();
+        drop(journal);
+
+        let (_journal, previous) = SessionJournal::open(&dir, &fresh).unwrap();
+        assert_eq!(previous, None);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_clean_shutdown_is_not_offered() {
+        let dir = temp_profile();
+        let mut state = SpacesState::first_run();
+        let (mut journal, _) = SessionJournal::open(&dir, &state).unwrap();
+        state.spaces.push(Space::new("Work"));
+        state.active_space = 1;
+        journal.record(&state).unwrap();
+        journal.shutdown().unwrap();
+
+        let (_journal, previous) = SessionJournal::open(&dir, &state).unwrap();
+        assert_eq!(previous, None);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_torn_record_and_reordering() {
+        let dir = temp_profile();
+        let mut state = SpacesState::first_run();
+        state.spaces.push(Space::new("Work"));
+        let (mut journal, _) = SessionJournal::open(&dir, &state).unwrap();
+
+        // Reordering spaces has no record of its own and falls back to a snapshot
+        state.spaces.swap(0, 1);
+        journal.record(&state).unwrap();
+        let mut file = OpenOptions::new().append(true).open(journal.path()).unwrap();
+        file.write_all(br#"{"record":"active_space","ind"#).unwrap();
+        drop(journal);
+
+        let (_journal, previous) = SessionJournal::open(&dir, &SpacesState::first_run()).unwrap();
+        assert_eq!(previous.map(|previous| previous.state), Some(state));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_journal_from_another_version_is_discarded() {
+        let dir = temp_profile();
+        fs::create_dir_all(&dir).unwrap();
+        let mut state = SpacesState::first_run();
+        state.spaces.push(Space::new("Work"));
+        let snapshot = serde_json::to_value(Record::Snapshot {
+            version: SCHEMA_VERSION,
+            state: state.clone(),
+            recently_closed: Vec::new(),
+        })
+        .unwrap();
+
+        for version in [None, Some(SCHEMA_VERSION - 1), Some(SCHEMA_VERSION + 1)] {
+            let mut snapshot = snapshot.clone();
+            match version {
+                Some(version) => snapshot["version"] = serde_json::json!(version),
+                None => drop(snapshot.as_object_mut().unwrap().remove("version")),
+            }
+            fs::write(dir.join(FILE_NAME), format!("{}\n", snapshot)).unwrap();
+            let (_journal, previous) = SessionJournal::open(&dir, &SpacesState::first_run()).unwrap();
+            assert_eq!(previous, None);
+            assert!(!dir.join(PREVIOUS_FILE_NAME).exists());
+        }
+
+        fs::write(dir.join(FILE_NAME), format!("{}\n", snapshot)).unwrap();
+        let (_journal, previous) = SessionJournal::open(&dir, &SpacesState::first_run()).unwrap();
+        assert_eq!(previous.map(|previous| previous.state), Some(state));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_panel_made_essential_is_not_closed() {
+        let dir = temp_profile();
+        let mut state = SpacesState::first_run();
+        let (mut journal, _) = SessionJournal::open(&dir, &state).unwrap();
+        let space = &mut state.spaces[0];
+        let home = space.panels[0].id.clone();
+        let mail = space.split_panel(&home, "https://mail.test", Axis::Row).unwrap();
+        journal.record(&state).unwrap();
+        let panel = state.spaces[0].remove_panel(&mail).unwrap();
+        state.essentials.adopt(panel);
+        journal.record(&state).unwrap();
+        let log = fs::read_to_string(journal.path()).unwrap();
+        assert!(log.contains("panel_made_essential") && !log.contains("panel_closed"));
+        drop(journal);
+
+        let (_journal, previous) = SessionJournal::open(&dir, &SpacesState::first_run()).unwrap();
+        let previous = previous.unwrap();
+        assert_eq!(previous.state, state);
+        assert!(previous.recently_closed.is_empty());
+        let restored = previous.restore(true);
+        assert_eq!(restored.spaces[0].panels.len(), 1);
+        assert_eq!(restored.essentials.len(), 1);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_compaction() {
+        let dir = temp_profile();
+        let mut state = SpacesState::first_run();
+        let (mut journal, _) = SessionJournal::open(&dir, &state).unwrap();
+        for step in 1..=COMPACT_AFTER + 1 {
+            state.spaces[0].panels[0].scroll_offset = [0.0, step as f32];
+            journal.record(&state).unwrap();
+        }
+        assert_eq!(journal_lines(&dir), 1);
+        drop(journal);
+
+        let (_journal, previous) = SessionJournal::open(&dir, &SpacesState::first_run()).unwrap();
+        assert_eq!(previous.map(|previous| previous.state), Some(state));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,537 @@
+//! Panel layout of a space.
+//!
+//! A tree of splits: every split lays its children out along one axis, each child taking a
+//! share (`ratio`) of the split's extent. Leaves reference panels by ID. Operations keep the
+//! tree normalized: no single-child or empty splits, no split nested in a split of the same
+//! axis, and ratios that add up to 1.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fmt;
+
+/// Smallest share a divider can be dragged down to.
+pub const MIN_RATIO: f32 = 0.1;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum Axis {
+    /// Children side by side, left to right
+    Row,
+    /// Children stacked, top to bottom
+    Column,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub enum Layout {
+    /// No panel placed yet
+    #[default]
+    Empty,
+    Panel(String),
+    Split { axis: Axis, children: Vec<SplitChild> },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SplitChild {
+    pub ratio: f32,
+    pub node: Layout,
+}
+
+/// Where a panel sat, so it can be put back after being closed.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Placement {
+    /// Panels of the neighbouring node it sat beside
+    pub beside: Vec<String>,
+    pub axis: Axis,
+    /// Whether the panel came before its neighbour along `axis`
+    pub before: bool,
+    /// The panel's share of its split
+    pub ratio: f32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LayoutError {
+    /// The layout references a panel the space doesn't have
+    UnknownPanel(String),
+    /// A panel appears in more than one place
+    DuplicatePanel(String),
+    /// The split at this child-index path has fewer than two children or bad ratios
+    InvalidSplit(Vec<usize>),
+}
+
+impl fmt::Display for LayoutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LayoutError::UnknownPanel(id) => write!(f, "layout references unknown panel {}", id),
+            LayoutError::DuplicatePanel(id) => write!(f, "panel {} appears more than once in the layout", id),
+            LayoutError::InvalidSplit(path) => write!(f, "invalid split at {:?}", path),
+        }
+    }
+}
+
+impl std::error::Error for LayoutError {}
+
+impl Layout {
+    /// Panel IDs in reading order (left to right, top to bottom).
+    pub fn panel_ids(&self) -> Vec<&str> {
+        let mut ids = Vec::new();
+        self.collect_ids(&mut ids);
+        ids
+    }
+
+    fn collect_ids<'a>(&'a self, ids: &mut Vec<&'a str>) {
+        match self {
+            Layout::Empty => {}
+            Layout::Panel(id) => ids.push(id),
+            Layout::Split { children, .. } => children.iter().for_each(|child| child.node.collect_ids(ids)),
+        }
+    }
+
+    pub fn contains(&self, panel_id: &str) -> bool {
+        match self {
+            Layout::Empty => false,
+            Layout::Panel(id) => id == panel_id,
+            Layout::Split { children, .. } => children.iter().any(|child| child.node.contains(panel_id)),
+        }
+    }
+
+    /// Replaces every panel ID with what `rename` gives for it.
+    pub fn rename_panels(&mut self, rename: &mut impl FnMut(&str) -> String) {
+        match self {
+            Layout::Empty => {}
+            Layout::Panel(id) => *id = rename(id),
+            Layout::Split { children, .. } => children.iter_mut().for_each(|child| child.node.rename_panels(rename)),
+        }
+    }
+
+    /// Child-index path from the root to a panel.
+    fn path_to(&self, panel_id: &str) -> Option<Vec<usize>> {
+        match self {
+            Layout::Panel(id) if id == panel_id => Some(Vec::new()),
+            Layout::Split { children, .. } => children.iter().enumerate().find_map(|(index, child)| {
+                let mut path = child.node.path_to(panel_id)?;
+                path.insert(0, index);
+                Some(path)
+            }),
+            _ => None,
+        }
+    }
+
+    /// Where a panel sits; `None` if it isn't in the layout or is the only panel.
+    pub fn placement(&self, panel_id: &str) -> Option<Placement> {
+        let path = self.path_to(panel_id)?;
+        let (&index, parent_path) = path.split_last()?;
+        let Some(Layout::Split { axis, children }) = self.node_at(parent_path) else {
+            return None;
+        };
+        let before = index + 1 < children.len();
+        let neighbour = if before { &children[index + 1] } else { &children[index - 1] };
+        Some(Placement {
+            beside: neighbour.node.panel_ids().into_iter().map(str::to_string).collect(),
+            axis: *axis,
+            before,
+            ratio: children[index].ratio,
+        })
+    }
+
+    /// Puts `panel_id` back beside the smallest node holding what is left of its neighbour.
+    /// Returns false if none of the neighbour's panels are in the layout any more.
+    pub fn restore(&mut self, panel_id: &str, placement: &Placement) -> bool {
+        let paths: Vec<Vec<usize>> = placement.beside.iter().filter_map(|id| self.path_to(id)).collect();
+        let Some(first) = paths.first() else {
+            return false;
+        };
+        let common = paths
+            .iter()
+            .map(|path| first.iter().zip(path).take_while(|(a, b)| a == b).count())
+            .min()
+            .unwrap_or_default();
+        let target = first[..common].to_vec();
+        let ratio = placement.ratio.clamp(MIN_RATIO, 1.0 - MIN_RATIO);
+        let node = Layout::Panel(panel_id.to_string());
+
+        if let Some((&index, parent_path)) = target.split_last()
+            && let Some(Layout::Split { axis, children }) = self.node_at_mut(parent_path)
+            && *axis == placement.axis
+        {
+            // The neighbour is still in a split along the same axis: rejoin it as a sibling
+            for child in children.iter_mut() {
+                child.ratio *= 1.0 - ratio;
+            }
+            let at = if placement.before { index } else { index + 1 };
+            children.insert(at, SplitChild { ratio, node });
+        } else {
+            let Some(neighbour) = self.node_at_mut(&target) else {
+                return false;
+            };
+            let rest = SplitChild { ratio: 1.0 - ratio, node: std::mem::take(neighbour) };
+            let restored = SplitChild { ratio, node };
+            *neighbour = Layout::Split {
+                axis: placement.axis,
+                children: if placement.before { vec![restored, rest] } else { vec![rest, restored] },
+            };
+        }
+        self.normalize();
+        true
+    }
+
+    /// Places `new_panel` next to `target` (after it along `axis`), sharing `target`'s space.
+    /// Returns false if `target` isn't in the layout.
+    pub fn split(&mut self, target: &str, new_panel: &str, axis: Axis) -> bool {
+        match self {
+            Layout::Panel(id) if id == target => {
+                *self = Layout::Split {
+                    axis,
+                    children: vec![
+                        SplitChild { ratio: 0.5, node: Layout::Panel(id.clone()) },
+                        SplitChild { ratio: 0.5, node: Layout::Panel(new_panel.to_string()) },
+                    ],
+                };
+                true
+            }
+            Layout::Split { axis: split_axis, children } => {
+                // Splitting along the parent's axis adds a sibling instead of nesting
+                if *split_axis == axis
+                    && let Some(index) = children.iter().position(|child| matches!(&child.node, Layout::Panel(id) if id == target))
+                {
+                    let ratio = children[index].ratio / 2.0;
+                    children[index].ratio = ratio;
+                    children.insert(index + 1, SplitChild { ratio, node: Layout::Panel(new_panel.to_string()) });
+                    return true;
+                }
+                children.iter_mut().any(|child| child.node.split(target, new_panel, axis))
+            }
+            _ => false,
+        }
+    }
+
+    /// Places `panel_id` at the end of the root along `axis`, taking an equal share of it.
+    pub fn push(&mut self, panel_id: &str, axis: Axis) {
+        let node = Layout::Panel(panel_id.to_string());
+        match self {
+            Layout::Empty => *self = node,
+            Layout::Split { axis: root_axis, children } if *root_axis == axis => {
+                let ratio = 1.0 / (children.len() + 1) as f32;
+                for child in children.iter_mut() {
+                    child.ratio *= 1.0 - ratio;
+                }
+                children.push(SplitChild { ratio, node });
+            }
+            _ => {
+                let root = std::mem::take(self);
+                *self = Layout::Split {
+                    axis,
+                    children: vec![SplitChild { ratio: 0.5, node: root }, SplitChild { ratio: 0.5, node }],
+                };
+            }
+        }
+    }
+
+    /// Removes a panel; its siblings grow proportionally to fill the space.
+    /// Returns false if the panel isn't in the layout.
+    pub fn close(&mut self, panel_id: &str) -> bool {
+        let closed = self.remove(panel_id);
+        if closed {
+            self.normalize();
+        }
+        closed
+    }
+
+    fn remove(&mut self, panel_id: &str) -> bool {
+        match self {
+            Layout::Panel(id) if id == panel_id => {
+                *self = Layout::Empty;
+                true
+            }
+            Layout::Split { children, .. } => children.iter_mut().any(|child| child.node.remove(panel_id)),
+            _ => false,
+        }
+    }
+
+    /// Gives every child of every split an equal share.
+    pub fn rebalance(&mut self) {
+        if let Layout::Split { children, .. } = self {
+            let ratio = 1.0 / children.len() as f32;
+            for child in children {
+                child.ratio = ratio;
+                child.node.rebalance();
+            }
+        }
+    }
+
+    /// The node at a path of child indices from the root.
+    pub fn node_at(&self, path: &[usize]) -> Option<&Layout> {
+        match (path.split_first(), self) {
+            (None, node) => Some(node),
+            (Some((&index, rest)), Layout::Split { children, .. }) => children.get(index)?.node.node_at(rest),
+            _ => None,
+        }
+    }
+
+    fn node_at_mut(&mut self, path: &[usize]) -> Option<&mut Layout> {
+        match (path.split_first(), self) {
+            (None, node) => Some(node),
+            (Some((&index, rest)), Layout::Split { children, .. }) => children.get_mut(index)?.node.node_at_mut(rest),
+            _ => None,
+        }
+    }
+
+    /// Moves the divider after child `divider` of the split at `path` by `delta` (a fraction of
+    /// the split's extent). Neither neighbour shrinks below [`MIN_RATIO`].
+    pub fn drag_divider(&mut self, path: &[usize], divider: usize, delta: f32) -> bool {
+        let Some(Layout::Split { children, .. }) = self.node_at_mut(path) else {
+            return false;
+        };
+        if divider + 1 >= children.len() {
+            return false;
+        }
+        let pair = children[divider].ratio + children[divider + 1].ratio;
+        let min = MIN_RATIO.min(pair / 2.0);
+        let before = (children[divider].ratio + delta).clamp(min, pair - min);
+        children[divider].ratio = before;
+        children[divider + 1].ratio = pair - before;
+        true
+    }
+
+    /// Checks that every referenced panel is in `panel_ids`, none is placed twice, and every
+    /// split is well formed.
+    pub fn validate<'a>(&self, panel_ids: impl IntoIterator<Item = &'a str>) -> Result<(), LayoutError> {
+        let known: HashSet<&str> = panel_ids.into_iter().collect();
+        let mut seen = HashSet::new();
+        self.validate_node(&known, &mut seen, &mut Vec::new())
+    }
+
+    fn validate_node<'a>(&'a self, known: &HashSet<&str>, seen: &mut HashSet<&'a str>, path: &mut Vec<usize>) -> Result<(), LayoutError> {
+        match self {
+            Layout::Empty => Ok(()),
+            Layout::Panel(id) => {
+                if !known.contains(id.as_str()) {
+                    return Err(LayoutError::UnknownPanel(id.clone()));
+                }
+                if !seen.insert(id) {
+                    return Err(LayoutError::DuplicatePanel(id.clone()));
+                }
+                Ok(())
+            }
+            Layout::Split { children, .. } => {
+                let total: f32 = children.iter().map(|child| child.ratio).sum();
+                let ratios_ok = children.iter().all(|child| child.ratio.is_finite() && child.ratio > 0.0);
+                if children.len() < 2 || !ratios_ok || (total - 1.0).abs() > 1e-3 {
+                    return Err(LayoutError::InvalidSplit(path.clone()));
+                }
+                for (index, child) in children.iter().enumerate() {
+                    path.push(index);
+                    child.node.validate_node(known, seen, path)?;
+                    path.pop();
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Drops references to panels not in `known` and repeated references, replaces unusable
+    /// ratios, and restores the tree invariants.
+    pub fn repair(&mut self, known: &HashSet<&str>) {
+        self.drop_invalid_leaves(known, &mut HashSet::new());
+        self.normalize();
+    }
+
+    fn drop_invalid_leaves(&mut self, known: &HashSet<&str>, seen: &mut HashSet<String>) {
+        match self {
+            Layout::Empty => {}
+            Layout::Panel(id) => {
+                if !known.contains(id.as_str()) || !seen.insert(id.clone()) {
+                    *self = Layout::Empty;
+                }
+            }
+            Layout::Split { children, .. } => {
+                for child in children {
+                    if !child.ratio.is_finite() || child.ratio <= 0.0 {
+                        child.ratio = 1.0;
+                    }
+                    child.node.drop_invalid_leaves(known, seen);
+                }
+            }
+        }
+    }
+
+    /// Restores the tree invariants after an edit.
+    fn normalize(&mut self) {
+        let Layout::Split { axis, children } = self else {
+            return;
+        };
+        let axis = *axis;
+
+        let mut flattened = Vec::with_capacity(children.len());
+        for mut child in std::mem::take(children) {
+            child.node.normalize();
+            match child.node {
+                Layout::Empty => {}
+                // A same-axis split inside a split is just more siblings
+                Layout::Split { axis: child_axis, children: grandchildren } if child_axis == axis => {
+                    flattened.extend(grandchildren.into_iter().map(|grandchild| SplitChild {
+                        ratio: grandchild.ratio * child.ratio,
+                        node: grandchild.node,
+                    }));
+                }
+                _ => flattened.push(child),
+            }
+        }
+
+        let total: f32 = flattened.iter().map(|child| child.ratio).sum();
+        for child in &mut flattened {
+            child.ratio /= total;
+        }
+
+        *self = match flattened.len() {
+            0 => Layout::Empty,
+            1 => flattened.pop().map(|child| child.node).unwrap_or_default(),
+            _ => Layout::Split { axis, children: flattened },
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ratios(layout: &Layout) -> Vec<f32> {
+        match layout {
+            Layout::Split { children, .. } => children.iter().map(|child| child.ratio).collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_split_nests_and_adds_siblings() {
+        let mut layout = Layout::Panel("a".into());
+        assert!(layout.split("a", "b", Axis::Row));
+        assert!(layout.split("a", "c", Axis::Row));
+        assert!(layout.split("b", "d", Axis::Column));
+        assert!(!layout.split("missing", "e", Axis::Row));
+
+        assert_eq!(layout.panel_ids(), vec!["a", "c", "b", "d"]);
+        assert_eq!(ratios(&layout), vec![0.25, 0.25, 0.5]);
+        assert_eq!(layout.node_at(&[2]).map(ratios), Some(vec![0.5, 0.5]));
+        assert_eq!(layout.validate(["a", "b", "c", "d"]), Ok(()));
+    }
+
+    #[test]
+    fn test_close_collapses_and_flattens() {
+        let mut layout = Layout::Panel("a".into());
+        layout.split("a", "b", Axis::Row);
+        layout.split("b", "c", Axis::Column);
+        layout.split("c", "d", Axis::Row);
+
+        // Closing `b` leaves a row inside a row, which merges into the root
+        assert!(layout.close("b"));
+        assert_eq!(layout.panel_ids(), vec!["a", "c", "d"]);
+        assert_eq!(ratios(&layout), vec![0.5, 0.25, 0.25]);
+
+        assert!(layout.close("c"));
+        assert!(layout.close("d"));
+        assert_eq!(layout, Layout::Panel("a".into()));
+        assert!(layout.close("a"));
+        assert_eq!(layout, Layout::Empty);
+        assert!(!layout.close("a"));
+    }
+
+    #[test]
+    fn test_rebalance_and_drag() {
+        let mut layout = Layout::Panel("a".into());
+        layout.split("a", "b", Axis::Row);
+        layout.split("a", "c", Axis::Row);
+        layout.rebalance();
+        let third = 1.0 / 3.0;
+        assert_eq!(ratios(&layout), vec![third, third, third]);
+
+        assert!(layout.drag_divider(&[], 0, 0.5));
+        let expected = [2.0 * third - MIN_RATIO, MIN_RATIO, third];
+        assert!(ratios(&layout).iter().zip(expected).all(|(ratio, expected)| (ratio - expected).abs() < 1e-6));
+        assert!(!layout.drag_divider(&[], 2, 0.1));
+        assert!(!layout.drag_divider(&[0], 0, 0.1));
+    }
+
+    #[test]
+    fn test_push() {
+        let mut layout = Layout::Empty;
+        layout.push("a", Axis::Row);
+        assert_eq!(layout, Layout::Panel("a".into()));
+
+        layout.push("b", Axis::Row);
+        layout.push("c", Axis::Row);
+        assert_eq!(layout.panel_ids(), vec!["a", "b", "c"]);
+        let third = 1.0 / 3.0;
+        assert!(ratios(&layout).iter().all(|ratio| (ratio - third).abs() < 1e-6));
+
+        layout.push("d", Axis::Column);
+        assert_eq!(ratios(&layout), vec![0.5, 0.5]);
+        assert_eq!(layout.validate(["a", "b", "c", "d"]), Ok(()));
+    }
+
+    #[test]
+    fn test_placement_restores_closed_panel() {
+        let mut layout = Layout::Panel("a".into());
+        layout.split("a", "b", Axis::Row);
+        layout.split("b", "c", Axis::Column);
+        layout.split("a", "d", Axis::Row);
+        layout.drag_divider(&[], 0, 0.1);
+        let before = layout.clone();
+
+        // `d` sits between `a` and the `b`/`c` column
+        let placement = layout.placement("d").unwrap();
+        assert_eq!((placement.beside.as_slice(), placement.axis, placement.before), (&["b".to_string(), "c".to_string()][..], Axis::Row, true));
+        layout.close("d");
+        assert!(layout.restore("d", &placement));
+        assert_eq!(layout.panel_ids(), before.panel_ids());
+        assert!(ratios(&layout).iter().zip(ratios(&before)).all(|(ratio, expected)| (ratio - expected).abs() < 1e-6));
+
+        // `c` was the last of a pair; its column collapsed when it closed
+        let placement = layout.placement("c").unwrap();
+        layout.close("c");
+        assert!(layout.restore("c", &placement));
+        assert_eq!(layout.panel_ids(), before.panel_ids());
+        assert_eq!(layout.node_at(&[2]).map(ratios), Some(vec![0.5, 0.5]));
+
+        assert_eq!(Layout::Panel("a".into()).placement("a"), None);
+        let gone = Placement { beside: vec!["x".into()], axis: Axis::Row, before: false, ratio: 0.5 };
+        assert!(!layout.restore("e", &gone));
+    }
+
+    #[test]
+    fn test_repair() {
+        let mut layout = Layout::Split {
+            axis: Axis::Row,
+            children: vec![
+                SplitChild { ratio: f32::NAN, node: Layout::Panel("a".into()) },
+                SplitChild { ratio: 0.5, node: Layout::Panel("gone".into()) },
+                SplitChild {
+                    ratio: 0.5,
+                    node: Layout::Split {
+                        axis: Axis::Column,
+                        children: vec![SplitChild { ratio: 1.0, node: Layout::Panel("a".into()) }, SplitChild { ratio: 1.0, node: Layout::Panel("b".into()) }],
+                    },
+                },
+            ],
+        };
+        layout.repair(&HashSet::from(["a", "b"]));
+
+        assert_eq!(layout.panel_ids(), vec!["a", "b"]);
+        assert_eq!(ratios(&layout), vec![2.0 / 3.0, 1.0 / 3.0]);
+        assert_eq!(layout.validate(["a", "b"]), Ok(()));
+    }
+
+    #[test]
+    fn test_validate() {
+        let mut layout = Layout::Panel("a".into());
+        layout.split("a", "b", Axis::Column);
+        assert_eq!(layout.validate(["a"]), Err(LayoutError::UnknownPanel("b".into())));
+
+        layout.split("b", "a", Axis::Row);
+        assert_eq!(layout.validate(["a", "b"]), Err(LayoutError::DuplicatePanel("a".into())));
+
+        let lopsided = Layout::Split {
+            axis: Axis::Row,
+            children: vec![SplitChild { ratio: 0.9, node: Layout::Panel("a".into()) }, SplitChild { ratio: 0.9, node: Layout::Panel("b".into()) }],
+        };
+        assert_eq!(lopsided.validate(["a", "b"]), Err(LayoutError::InvalidSplit(vec![])));
+    }
+}
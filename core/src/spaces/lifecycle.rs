@@ -0,0 +1,260 @@
+//! Panel lifecycle: how much of a panel stays alive while nobody looks at it.
+//!
+//! Unused panels go active → frozen → discarded. A frozen panel keeps its page in memory but
+//! stops running it; a discarded one gives the memory back and is shown as a thumbnail of
+//! where it was until it is focused and reloaded. When live panels add up to more than the
+//! memory budget, the ones unused the longest are discarded early. The panel on screen and
+//! panels marked `never_sleep` stay active.
+//!
+//! The manager only tracks panels it has seen live, so panels restored from disk start out
+//! discarded.
+
+use super::{Essentials, Space, unix_now};
+use std::collections::{HashMap, HashSet};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum PanelState {
+    Active,
+    Frozen,
+    Discarded,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LifecyclePolicy {
+    /// Seconds out of sight before a panel is frozen
+    pub freeze_after: u64,
+    /// Seconds out of sight before a panel is discarded
+    pub discard_after: u64,
+    /// Bytes the live panels may use together
+    pub memory_budget: u64,
+}
+
+impl Default for LifecyclePolicy {
+    fn default() -> Self {
+        Self {
+            freeze_after: 5 * 60,
+            discard_after: 30 * 60,
+            memory_budget: 2 << 30,
+        }
+    }
+}
+
+/// A small screenshot, RGBA, top row first.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Thumbnail {
+    pub width: usize,
+    pub height: usize,
+    pub rgba: Vec<u8>,
+}
+
+/// A panel moving to another state, for the engine to carry out.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Transition {
+    pub panel_id: String,
+    pub from: PanelState,
+    pub to: PanelState,
+}
+
+#[derive(Debug)]
+struct Tracked {
+    state: PanelState,
+    /// Bytes the engine last reported
+    memory: u64,
+    /// When the panel went out of sight; `None` while it is on screen
+    hidden_since: Option<u64>,
+    thumbnail: Option<Thumbnail>,
+}
+
+#[derive(Debug, Default)]
+pub struct LifecycleManager {
+    policy: LifecyclePolicy,
+    panels: HashMap<String, Tracked>,
+}
+
+impl LifecycleManager {
+    pub fn new(policy: LifecyclePolicy) -> Self {
+        Self {
+            policy,
+            panels: HashMap::new(),
+        }
+    }
+
+    pub fn policy(&self) -> &LifecyclePolicy {
+        &self.policy
+    }
+
+    pub fn state(&self, panel_id: &str) -> PanelState {
+        self.panels.get(panel_id).map_or(PanelState::Discarded, |tracked| tracked.state)
+    }
+
+    /// What a discarded panel showed last.
+    pub fn thumbnail(&self, panel_id: &str) -> Option<&Thumbnail> {
+        self.panels.get(panel_id)?.thumbnail.as_ref()
+    }
+
+    /// Keeps a screenshot of a panel about to go out of sight.
+    pub fn set_thumbnail(&mut self, panel_id: &str, thumbnail: Thumbnail) {
+        if let Some(tracked) = self.panels.get_mut(panel_id) {
+            tracked.thumbnail = Some(thumbnail);
+        }
+    }
+
+    /// The engine's measurement of a live panel's memory use.
+    pub fn report_memory(&mut self, panel_id: &str, bytes: u64) {
+        if let Some(tracked) = self.panels.get_mut(panel_id)
+            && tracked.state != PanelState::Discarded
+        {
+            tracked.memory = bytes;
+        }
+    }
+
+    /// Brings a panel on screen. Returns the state it was in: a discarded panel has to be
+    /// loaded again, a frozen one resumed.
+    pub fn activate(&mut self, panel_id: &str) -> PanelState {
+        let previous = self.state(panel_id);
+        let tracked = self.panels.entry(panel_id.to_string()).or_insert(Tracked {
+            state: PanelState::Active,
+            memory: 0,
+            hidden_since: None,
+            thumbnail: None,
+        });
+        tracked.state = PanelState::Active;
+        tracked.hidden_since = None;
+        previous
+    }
+
+    /// Moves panels along by how long they've been out of sight and the memory budget.
+    /// `on_screen` is the panel the engine shows.
+    pub fn tick(&mut self, spaces: &[Space], essentials: &Essentials, on_screen: Option<&str>) -> Vec<Transition> {
+        self.tick_at(spaces, essentials, on_screen, unix_now())
+    }
+
+    fn tick_at(&mut self, spaces: &[Space], essentials: &Essentials, on_screen: Option<&str>, now: u64) -> Vec<Transition> {
+        let panels: Vec<_> = spaces.iter().flat_map(|space| &space.panels).chain(essentials.iter()).collect();
+        let open: HashSet<&str> = panels.iter().map(|panel| panel.id.as_str()).collect();
+        self.panels.retain(|id, _| open.contains(id.as_str()));
+
+        let mut transitions = Vec::new();
+        for panel in &panels {
+            let Some(tracked) = self.panels.get_mut(&panel.id) else {
+                continue;
+            };
+            let target = if on_screen == Some(panel.id.as_str()) {
+                tracked.hidden_since = None;
+                PanelState::Active
+            } else {
+                let idle = now.saturating_sub(*tracked.hidden_since.get_or_insert(now));
+                if panel.never_sleep {
+                    PanelState::Active
+                } else if idle >= self.policy.discard_after {
+                    PanelState::Discarded
+                } else if idle >= self.policy.freeze_after {
+                    tracked.state.max(PanelState::Frozen)
+                } else {
+                    tracked.state
+                }
+            };
+            // Discarded panels only come back through `activate`
+            if target != tracked.state && tracked.state != PanelState::Discarded {
+                transitions.push(Transition { panel_id: panel.id.clone(), from: tracked.state, to: target });
+                set_state(tracked, target);
+            }
+        }
+
+        // Over budget: discard what has been out of sight the longest
+        let mut live: u64 = self.panels.values().filter(|tracked| tracked.state != PanelState::Discarded).map(|tracked| tracked.memory).sum();
+        let mut candidates: Vec<_> = panels
+            .iter()
+            .filter(|panel| !panel.never_sleep)
+            .filter_map(|panel| {
+                let tracked = self.panels.get(&panel.id)?;
+                let hidden_since = tracked.hidden_since.filter(|_| tracked.state != PanelState::Discarded)?;
+                Some((hidden_since, panel.id.clone()))
+            })
+            .collect();
+        candidates.sort();
+        for (_, id) in candidates {
+            if live <= self.policy.memory_budget {
+                break;
+            }
+            if let Some(tracked) = self.panels.get_mut(&id) {
+                live -= tracked.memory;
+                transitions.push(Transition { panel_id: id, from: tracked.state, to: PanelState::Discarded });
+                set_state(tracked, PanelState::Discarded);
+            }
+        }
+        transitions
+    }
+}
+
+fn set_state(tracked: &mut Tracked, state: PanelState) {
+    tracked.state = state;
+    if state == PanelState::Discarded {
+        tracked.memory = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MB: u64 = 1 << 20;
+
+    fn space_with(count: usize) -> (Space, Vec<String>) {
+        let mut space = Space::new("Test");
+        let ids = (0..count).map(|index| space.add_panel(&format!("https://{}.test", index))).collect();
+        (space, ids)
+    }
+
+    fn states(manager: &LifecycleManager, ids: &[String]) -> Vec<PanelState> {
+        ids.iter().map(|id| manager.state(id)).collect()
+    }
+
+    #[test]
+    fn test_panels_freeze_then_discard_over_time() {
+        let (mut space, ids) = space_with(3);
+        space.panel_mut(&ids[2]).unwrap().never_sleep = true;
+        let mut manager = LifecycleManager::default();
+        let policy = manager.policy().clone();
+        for id in &ids {
+            assert_eq!(manager.activate(id), PanelState::Discarded);
+        }
+
+        let spaces = [space];
+        manager.tick_at(&spaces, &Essentials::default(), Some(&ids[0]), 1000);
+        manager.tick_at(&spaces, &Essentials::default(), Some(&ids[0]), 1000 + policy.freeze_after);
+        assert_eq!(states(&manager, &ids), vec![PanelState::Active, PanelState::Frozen, PanelState::Active]);
+
+        let transitions = manager.tick_at(&spaces, &Essentials::default(), Some(&ids[0]), 1000 + policy.discard_after);
+        assert_eq!(transitions, vec![Transition { panel_id: ids[1].clone(), from: PanelState::Frozen, to: PanelState::Discarded }]);
+        assert_eq!(manager.activate(&ids[1]), PanelState::Discarded);
+        assert_eq!(manager.state(&ids[1]), PanelState::Active);
+    }
+
+    #[test]
+    fn test_memory_budget_discards_least_recently_seen() {
+        let (space, ids) = space_with(4);
+        let mut manager = LifecycleManager::new(LifecyclePolicy { memory_budget: 250 * MB, ..LifecyclePolicy::default() });
+        let spaces = [space];
+        for (second, id) in ids.iter().enumerate() {
+            manager.activate(id);
+            manager.report_memory(id, 100 * MB);
+            manager.tick_at(&spaces, &Essentials::default(), Some(id), second as u64);
+        }
+
+        // Panels 0 and 1 went out of sight first
+        assert_eq!(states(&manager, &ids), vec![PanelState::Discarded, PanelState::Discarded, PanelState::Active, PanelState::Active]);
+        manager.set_thumbnail(&ids[0], Thumbnail { width: 1, height: 1, rgba: vec![0; 4] });
+        assert!(manager.thumbnail(&ids[0]).is_some());
+    }
+
+    #[test]
+    fn test_closed_panels_are_forgotten() {
+        let (mut space, ids) = space_with(2);
+        let mut manager = LifecycleManager::default();
+        manager.activate(&ids[1]);
+        space.remove_panel(&ids[1]);
+        manager.tick_at(&[space], &Essentials::default(), None, 0);
+        assert!(manager.panels.is_empty());
+    }
+}
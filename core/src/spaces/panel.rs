@@ -0,0 +1,239 @@
+//! A panel and where it was: its back/forward history, scroll position and zoom.
+//!
+//! `url` and `title` always mirror the current history entry. The shell feeds the engine's
+//! notifications into the focused panel, and replays a panel's URL, zoom and scroll offset
+//! into the engine when the panel is focused again.
+//!
+//! A pinned panel stays on the origin it shows: navigations elsewhere belong in a new panel.
+
+use super::unix_now;
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+/// Entries kept per panel; older ones fall off the back of the history.
+pub const MAX_HISTORY: usize = 50;
+
+pub const DEFAULT_ZOOM: f32 = 1.0;
+
+const DEFAULT_TITLE: &str = "New Panel";
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Panel {
+    pub id: String,
+    pub title: String,
+    pub url: String,
+    /// Back/forward list, oldest first
+    pub history: Vec<HistoryEntry>,
+    pub history_index: usize,
+    /// Scroll position of the current page, in CSS pixels
+    pub scroll_offset: [f32; 2],
+    pub zoom: f32,
+    /// URL of the current page's icon
+    pub favicon: Option<String>,
+    /// Unix time (seconds) the panel was last focused
+    pub last_active: u64,
+    /// Kept by "close all" and held to its origin
+    pub pinned: bool,
+    /// Exempt from freezing and discarding
+    #[serde(default)]
+    pub never_sleep: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct HistoryEntry {
+    pub url: String,
+    pub title: String,
+    /// Where the page was scrolled when the user navigated away from it
+    pub scroll_offset: [f32; 2],
+}
+
+impl Panel {
+    pub(super) fn new(url: &str) -> Self {
+        let url = normalize_url(url);
+        Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            title: DEFAULT_TITLE.to_string(),
+            url: url.to_string(),
+            history: vec![HistoryEntry {
+                url: url.to_string(),
+                title: DEFAULT_TITLE.to_string(),
+                scroll_offset: [0.0; 2],
+            }],
+            history_index: 0,
+            scroll_offset: [0.0; 2],
+            zoom: DEFAULT_ZOOM,
+            favicon: None,
+            last_active: unix_now(),
+            pinned: false,
+            never_sleep: false,
+        }
+    }
+
+    /// A navigation the engine committed. Drops the forward history, unless `url` is the
+    /// current entry (as after [`Panel::go_back`] and [`Panel::go_forward`]).
+    pub fn record_navigation(&mut self, url: &str) {
+        let url = normalize_url(url);
+        if self.history.get(self.history_index).is_some_and(|entry| entry.url == url) {
+            return;
+        }
+        self.save_scroll_offset();
+        self.history.truncate(self.history_index + 1);
+        self.history.push(HistoryEntry {
+            url: url.clone(),
+            title: url,
+            scroll_offset: [0.0; 2],
+        });
+        if self.history.len() > MAX_HISTORY {
+            self.history.drain(..self.history.len() - MAX_HISTORY);
+        }
+        self.history_index = self.history.len() - 1;
+        self.favicon = None;
+        self.show_current_entry();
+    }
+
+    pub fn set_title(&mut self, title: &str) {
+        self.title = title.to_string();
+        if let Some(entry) = self.history.get_mut(self.history_index) {
+            entry.title = title.to_string();
+        }
+    }
+
+    pub fn can_go_back(&self) -> bool {
+        self.history_index > 0
+    }
+
+    pub fn can_go_forward(&self) -> bool {
+        self.history_index + 1 < self.history.len()
+    }
+
+    /// Steps back in the history; returns the URL to load.
+    pub fn go_back(&mut self) -> Option<&str> {
+        if !self.can_go_back() {
+            return None;
+        }
+        self.save_scroll_offset();
+        self.history_index -= 1;
+        self.show_current_entry();
+        Some(&self.url)
+    }
+
+    /// Steps forward in the history; returns the URL to load.
+    pub fn go_forward(&mut self) -> Option<&str> {
+        if !self.can_go_forward() {
+            return None;
+        }
+        self.save_scroll_offset();
+        self.history_index += 1;
+        self.show_current_entry();
+        Some(&self.url)
+    }
+
+    /// Whether a navigation to `url` may happen in this panel. Pinned panels only go
+    /// elsewhere on the origin they show; pages without one, like `about:home`, don't hold them.
+    pub fn allows_navigation(&self, url: &str) -> bool {
+        if !self.pinned {
+            return true;
+        }
+        let Some(origin) = Url::parse(&self.url).ok().map(|current| current.origin()).filter(|origin| origin.is_tuple()) else {
+            return true;
+        };
+        Url::parse(url).is_ok_and(|target| target.origin() == origin)
+    }
+
+    /// The origin a navigation is held to, if any; see [`Panel::allows_navigation`].
+    pub fn locked_origin(&self) -> Option<String> {
+        let origin = Url::parse(&self.url).ok()?.origin();
+        (self.pinned && origin.is_tuple()).then(|| origin.ascii_serialization())
+    }
+
+    /// Marks the panel as just used.
+    pub fn touch(&mut self) {
+        self.last_active = unix_now();
+    }
+
+    fn save_scroll_offset(&mut self) {
+        if let Some(entry) = self.history.get_mut(self.history_index) {
+            entry.scroll_offset = self.scroll_offset;
+        }
+    }
+
+    fn show_current_entry(&mut self) {
+        if let Some(entry) = self.history.get(self.history_index) {
+            self.url = entry.url.clone();
+            self.title = entry.title.clone();
+            self.scroll_offset = entry.scroll_offset;
+        }
+    }
+}
+
+/// `url` the way the engine reports it (`https://a.test` is `https://a.test/`), so a typed URL
+/// and the page it loaded compare equal. Left as is if it doesn't parse.
+pub(super) fn normalize_url(url: &str) -> String {
+    Url::parse(url).map_or_else(|_| url.to_string(), |parsed| parsed.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_history_and_scroll_restore() {
+        let mut panel = Panel::new("https://a.test/");
+        panel.set_title("A");
+        panel.scroll_offset = [0.0, 300.0];
+        panel.record_navigation("https://b.test/");
+        assert_eq!((panel.url.as_str(), panel.title.as_str()), ("https://b.test/", "https://b.test/"));
+        assert_eq!(panel.scroll_offset, [0.0, 0.0]);
+
+        assert_eq!(panel.go_back(), Some("https://a.test/"));
+        assert_eq!((panel.title.as_str(), panel.scroll_offset), ("A", [0.0, 300.0]));
+        // The engine then reports the page it loaded; that's not a new entry
+        panel.record_navigation("https://a.test/");
+        assert!(panel.can_go_forward());
+        assert_eq!(panel.go_forward(), Some("https://b.test/"));
+        assert_eq!(panel.go_forward(), None);
+
+        panel.go_back();
+        panel.record_navigation("https://c.test/");
+        assert!(!panel.can_go_forward());
+        assert_eq!(panel.history.iter().map(|entry| entry.url.as_str()).collect::<Vec<_>>(), vec!["https://a.test/", "https://c.test/"]);
+    }
+
+    #[test]
+    fn test_typed_and_reported_urls_are_one_entry() {
+        let mut panel = Panel::new("https://a.test");
+        assert_eq!(panel.url, "https://a.test/");
+        panel.record_navigation("https://a.test/");
+        panel.record_navigation("HTTPS://A.test");
+        assert_eq!(panel.history.len(), 1);
+        assert!(!panel.can_go_back());
+    }
+
+    #[test]
+    fn test_history_is_bounded() {
+        let mut panel = Panel::new("https://a.test/");
+        for page in 0..MAX_HISTORY + 5 {
+            panel.record_navigation(&format!("https://a.test/{}", page));
+        }
+        assert_eq!(panel.history.len(), MAX_HISTORY);
+        assert_eq!(panel.history_index, MAX_HISTORY - 1);
+        assert_eq!(panel.url, format!("https://a.test/{}", MAX_HISTORY + 4));
+    }
+
+    #[test]
+    fn test_pinned_panels_keep_their_origin() {
+        let mut panel = Panel::new("https://mail.test/inbox");
+        assert!(panel.allows_navigation("https://elsewhere.test/"));
+        assert_eq!(panel.locked_origin(), None);
+
+        panel.pinned = true;
+        assert_eq!(panel.locked_origin().as_deref(), Some("https://mail.test"));
+        assert!(panel.allows_navigation("https://mail.test/sent"));
+        assert!(!panel.allows_navigation("http://mail.test/sent"));
+        assert!(!panel.allows_navigation("https://elsewhere.test/"));
+        assert!(!panel.allows_navigation("not a url"));
+
+        panel.record_navigation("about:home");
+        assert!(panel.allows_navigation("https://elsewhere.test/"));
+    }
+}
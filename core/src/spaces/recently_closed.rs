@@ -0,0 +1,139 @@
+//! Recently closed panels and spaces, kept so a close can be undone.
+
+use super::{Panel, Placement, Space, unix_now};
+use serde::{Deserialize, Serialize};
+
+/// Closed items remembered, most recent last.
+pub const MAX_RECENTLY_CLOSED: usize = 25;
+
+/// A panel as it was when closed, and where it sat.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ClosedPanel {
+    pub space_id: String,
+    pub panel: Panel,
+    /// `None` if it was the space's only panel
+    #[serde(default)]
+    pub placement: Option<Placement>,
+    /// Unix time (seconds)
+    pub closed_at: u64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ClosedItem {
+    Panel(ClosedPanel),
+    /// A whole space and its position in the space list
+    Space { index: usize, space: Space, closed_at: u64 },
+}
+
+impl ClosedItem {
+    /// A space just taken out of the space list at `index`.
+    pub fn closed_space(index: usize, space: Space) -> Self {
+        ClosedItem::Space { index, space, closed_at: unix_now() }
+    }
+
+    pub fn title(&self) -> &str {
+        match self {
+            ClosedItem::Panel(closed) => &closed.panel.title,
+            ClosedItem::Space { space, .. } => &space.name,
+        }
+    }
+
+    /// Puts the item back: a panel where it sat in its space (or in `fallback_space`, if its
+    /// space is gone too), a space at its old position. Returns the index of the space that
+    /// received it, or `None` if something with the same ID is already open.
+    pub fn restore(self, spaces: &mut Vec<Space>, fallback_space: usize) -> Option<usize> {
+        match self {
+            ClosedItem::Panel(closed) => {
+                let index = spaces.iter().position(|space| space.id == closed.space_id).unwrap_or(fallback_space);
+                spaces.get_mut(index)?.reopen_panel(closed).then_some(index)
+            }
+            ClosedItem::Space { index, space, .. } => {
+                if spaces.iter().any(|open| open.id == space.id) {
+                    return None;
+                }
+                let index = index.min(spaces.len());
+                spaces.insert(index, space);
+                Some(index)
+            }
+        }
+    }
+}
+
+/// Bounded stack of closed items; the oldest fall off once it is full.
+#[derive(Debug, Clone, Default)]
+pub struct RecentlyClosed {
+    items: Vec<ClosedItem>,
+}
+
+impl RecentlyClosed {
+    pub fn push(&mut self, item: ClosedItem) {
+        self.items.push(item);
+        if self.items.len() > MAX_RECENTLY_CLOSED {
+            self.items.remove(0);
+        }
+    }
+
+    /// The most recently closed item.
+    pub fn pop(&mut self) -> Option<ClosedItem> {
+        self.items.pop()
+    }
+
+    /// Takes out the item at `index` of [`RecentlyClosed::iter`].
+    pub fn take(&mut self, index: usize) -> Option<ClosedItem> {
+        let position = self.items.len().checked_sub(index + 1)?;
+        Some(self.items.remove(position))
+    }
+
+    /// Most recent first.
+    pub fn iter(&self) -> impl Iterator<Item = &ClosedItem> {
+        self.items.iter().rev()
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::spaces::Axis;
+
+    #[test]
+    fn test_undo_close_panel_and_space() {
+        let mut work = Space::new("Work");
+        let a = work.add_panel("https://a.test");
+        let b = work.split_panel(&a, "https://b.test", Axis::Column).unwrap();
+        work.panel_mut(&b).unwrap().record_navigation("https://b.test/page");
+        let before = work.clone();
+        let mut spaces = vec![Space::new("Personal"), work];
+
+        let mut closed = RecentlyClosed::default();
+        closed.push(ClosedItem::Panel(spaces[1].close_panel(&b).unwrap()));
+        closed.push(ClosedItem::closed_space(0, spaces.remove(0)));
+        assert_eq!(closed.iter().map(ClosedItem::title).collect::<Vec<_>>(), vec!["Personal", "https://b.test/page"]);
+
+        // Undo in reverse order
+        assert_eq!(closed.pop().and_then(|item| item.restore(&mut spaces, 0)), Some(0));
+        assert_eq!(spaces[0].name, "Personal");
+        assert_eq!(closed.take(0).and_then(|item| item.restore(&mut spaces, 0)), Some(1));
+        assert_eq!(spaces[1].layout, before.layout);
+        assert_eq!(spaces[1].panel(&b), before.panel(&b));
+        assert!(closed.is_empty());
+    }
+
+    #[test]
+    fn test_is_bounded() {
+        let mut closed = RecentlyClosed::default();
+        for index in 0..MAX_RECENTLY_CLOSED + 3 {
+            closed.push(ClosedItem::Space { index, space: Space::new(&index.to_string()), closed_at: 0 });
+        }
+        assert_eq!(closed.len(), MAX_RECENTLY_CLOSED);
+        assert_eq!(closed.iter().last().map(ClosedItem::title), Some("3"));
+        assert_eq!(closed.take(MAX_RECENTLY_CLOSED), None);
+    }
+}
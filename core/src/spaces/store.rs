@@ -0,0 +1,379 @@
+//! On-disk storage for spaces.
+//!
+//! Everything lives in one `spaces.json` in the profile directory. Saves go to a temporary file
+//! that is flushed and renamed over the old one, so a crash mid-write leaves the previous state
+//! intact. The file carries a schema version; older versions are migrated step by step on load.
+
+use super::{Essentials, Space};
+use super::panel::normalize_url;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Version written by this build. Bump it and add a step to [`migrate`] when the format changes.
+pub const SCHEMA_VERSION: u64 = 4;
+
+const FILE_NAME: &str = "spaces.json";
+
+/// The spaces of a profile, which one is shown, and the essentials shared by all of them.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SpacesState {
+    pub spaces: Vec<Space>,
+    pub active_space: usize,
+    pub essentials: Essentials,
+}
+
+impl SpacesState {
+    /// What a new profile starts with.
+    pub fn first_run() -> Self {
+        let mut personal = Space::new("Personal");
+        personal.add_panel("about:home");
+        Self {
+            spaces: vec![personal],
+            active_space: 0,
+            essentials: Essentials::default(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum StoreError {
+    Io(io::Error),
+    Parse(serde_json::Error),
+    /// Written by a newer build; left alone rather than downgraded
+    UnsupportedVersion(u64),
+}
+
+impl fmt::Display for StoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StoreError::Io(e) => write!(f, "I/O error: {}", e),
+            StoreError::Parse(e) => write!(f, "malformed spaces file: {}", e),
+            StoreError::UnsupportedVersion(version) => {
+                write!(f, "spaces file has schema version {}, newer than the supported {}", version, SCHEMA_VERSION)
+            }
+        }
+    }
+}
+
+impl std::error::Error for StoreError {}
+
+impl From<io::Error> for StoreError {
+    fn from(e: io::Error) -> Self {
+        StoreError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for StoreError {
+    fn from(e: serde_json::Error) -> Self {
+        StoreError::Parse(e)
+    }
+}
+
+pub struct SpacesStore {
+    path: PathBuf,
+}
+
+impl SpacesStore {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// The store inside a profile directory.
+    pub fn in_profile(profile_dir: &Path) -> Self {
+        Self::new(profile_dir.join(FILE_NAME))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The saved state, or `None` for a profile that never saved any.
+    pub fn load(&self) -> Result<Option<SpacesState>, StoreError> {
+        let data = match fs::read_to_string(&self.path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let value = migrate(serde_json::from_str(&data)?)?;
+        let mut state: SpacesState = serde_json::from_value(value)?;
+        if state.spaces.is_empty() {
+            return Ok(None);
+        }
+        state.active_space = state.active_space.min(state.spaces.len() - 1);
+        // A hand-edited or half-migrated file shouldn't leave panels the shell can't reach
+        for space in &mut state.spaces {
+            if let Err(e) = space.validate() {
+                log::warn!("Repairing space {}: {}", space.name, e);
+                space.repair();
+            }
+        }
+        Ok(Some(state))
+    }
+
+    /// Loads the saved state, falling back to [`SpacesState::first_run`]. A file that can't be
+    /// read is moved aside (not deleted) so the next save doesn't destroy it.
+    pub fn load_or_default(&self) -> SpacesState {
+        match self.load() {
+            Ok(Some(state)) => state,
+            Ok(None) => SpacesState::first_run(),
+            Err(e) => {
+                log::warn!("Could not load {}: {}", self.path.display(), e);
+                let stamp = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default();
+                let backup = self.path.with_extension(format!("json.unreadable-{}", stamp));
+                if let Err(e) = fs::rename(&self.path, &backup) {
+                    log::warn!("Could not move {} aside: {}", self.path.display(), e);
+                }
+                SpacesState::first_run()
+            }
+        }
+    }
+
+    /// Writes `state` atomically: to a temporary file next to the store, then renamed over it.
+    pub fn save(&self, state: &SpacesState) -> Result<(), StoreError> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        let mut document = serde_json::to_value(state)?;
+        document["version"] = json!(SCHEMA_VERSION);
+        let tmp = self.path.with_extension("json.tmp");
+        {
+            let mut file = fs::File::create(&tmp)?;
+            file.write_all(serde_json::to_string_pretty(&document)?.as_bytes())?;
+            file.sync_all()?;
+        }
+        fs::rename(&tmp, &self.path)?;
+        Ok(())
+    }
+}
+
+/// Brings a document of any known schema version up to [`SCHEMA_VERSION`].
+fn migrate(mut document: Value) -> Result<Value, StoreError> {
+    loop {
+        let version = match &document {
+            // Version 0: the bare list of spaces the shell used to dump
+            Value::Array(_) => 0,
+            other => other.get("version").and_then(Value::as_u64).unwrap_or(0),
+        };
+        document = match version {
+            0 => {
+                let spaces = if document.is_array() { document } else { document["spaces"].take() };
+                json!({ "version": 1, "spaces": spaces, "active_space": 0 })
+            }
+            1 => {
+                if let Some(spaces) = document["spaces"].as_array_mut() {
+                    for space in spaces {
+                        space["layout"] = layout_from_v1(space);
+                    }
+                }
+                document["version"] = json!(2);
+                document
+            }
+            2 => {
+                // Panels gain navigation state; their page becomes a one-entry history, with the
+                // URL as the engine will report it
+                for space in document["spaces"].as_array_mut().into_iter().flatten() {
+                    for panel in space["panels"].as_array_mut().into_iter().flatten() {
+                        if let Some(url) = panel["url"].as_str() {
+                            panel["url"] = json!(normalize_url(url));
+                        }
+                        let entry = json!({ "url": panel["url"], "title": panel["title"], "scroll_offset": [0.0, 0.0] });
+                        panel["history"] = json!([entry]);
+                        panel["history_index"] = json!(0);
+                        panel["scroll_offset"] = json!([0.0, 0.0]);
+                        panel["zoom"] = json!(1.0);
+                        panel["last_active"] = json!(0);
+                        panel["pinned"] = json!(false);
+                    }
+                }
+                document["version"] = json!(3);
+                document
+            }
+            3 => {
+                document["essentials"] = json!([]);
+                document["version"] = json!(4);
+                document
+            }
+            SCHEMA_VERSION => return Ok(document),
+            newer => return Err(StoreError::UnsupportedVersion(newer)),
+        };
+    }
+}
+
+/// Version 1 layouts were `Single`, a two-panel split, or a flat grid; version 2 is a tree.
+fn layout_from_v1(space: &Value) -> Value {
+    let leaf = |id: &Value| json!({ "Panel": id });
+    let split = |axis: &str, nodes: Vec<Value>| {
+        let ratio = 1.0 / nodes.len() as f64;
+        let children: Vec<Value> = nodes.into_iter().map(|node| json!({ "ratio": ratio, "node": node })).collect();
+        json!({ "Split": { "axis": axis, "children": children } })
+    };
+
+    let layout = &space["layout"];
+    if let Some(pair) = layout["SplitVertical"].as_array() {
+        return split("Row", pair.iter().map(leaf).collect());
+    }
+    if let Some(pair) = layout["SplitHorizontal"].as_array() {
+        return split("Column", pair.iter().map(leaf).collect());
+    }
+    if let Some(ids) = layout["Grid"].as_array().filter(|ids| !ids.is_empty()) {
+        // Rows of ceil(sqrt(n)) panels, stacked
+        let columns = (ids.len() as f64).sqrt().ceil() as usize;
+        let rows: Vec<Value> = ids
+            .chunks(columns)
+            .map(|row| if row.len() == 1 { leaf(&row[0]) } else { split("Row", row.iter().map(leaf).collect()) })
+            .collect();
+        return if rows.len() == 1 { rows[0].clone() } else { split("Column", rows) };
+    }
+    // `Single` showed the first panel
+    match space["panels"].get(0).map(|panel| &panel["id"]) {
+        Some(id) => leaf(id),
+        None => json!("Empty"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::spaces::{Axis, Layout};
+
+    fn temp_profile() -> PathBuf {
+        std::env::temp_dir().join(format!("wave-spaces-{}", uuid::Uuid::new_v4()))
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let dir = temp_profile();
+        let store = SpacesStore::in_profile(&dir);
+        assert_eq!(store.load().unwrap(), None);
+
+        let mut state = SpacesState::first_run();
+        let mut work = Space::new("Work");
+        work.add_panel("https://github.com");
+        state.spaces.push(work);
+        state.active_space = 1;
+        state.essentials.add("https://mail.test");
+
+        store.save(&state).unwrap();
+        store.save(&state).unwrap();
+        assert_eq!(store.load().unwrap(), Some(state));
+        assert!(!dir.join("spaces.json.tmp").exists());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_migrates_bare_space_list() {
+        let dir = temp_profile();
+        fs::create_dir_all(&dir).unwrap();
+        // What the shell wrote before the file had a version
+        let v0 = r#"[
+            { "id": "s1", "name": "Work", "layout": { "SplitVertical": ["a", "b"] }, "panels": [
+                { "id": "a", "title": "Mail", "url": "https://mail.test" },
+                { "id": "b", "title": "Docs", "url": "https://docs.test/guide" }
+            ] },
+            { "id": "s2", "name": "Personal", "layout": "Single", "panels": [
+                { "id": "c", "title": "News", "url": "https://news.test/" }
+            ] }
+        ]"#;
+        fs::write(dir.join("spaces.json"), v0).unwrap();
+
+        let state = SpacesStore::in_profile(&dir).load().unwrap().unwrap();
+        assert_eq!(state.spaces.iter().map(|space| space.name.as_str()).collect::<Vec<_>>(), vec!["Work", "Personal"]);
+        assert_eq!(state.spaces[0].layout.panel_ids(), vec!["a", "b"]);
+        assert_eq!(state.spaces[0].panel("a").unwrap().url, "https://mail.test/");
+        assert_eq!(state.spaces[1].panel("c").unwrap().title, "News");
+        assert!(state.spaces.iter().all(|space| space.validate() == Ok(())));
+        assert_eq!(state.active_space, 0);
+        assert!(state.essentials.is_empty());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_migrates_v1_layouts() {
+        let dir = temp_profile();
+        fs::create_dir_all(&dir).unwrap();
+        let v1 = json!({
+            "version": 1,
+            "active_space": 1,
+            "spaces": [
+                { "id": "s1", "name": "Work", "layout": { "SplitVertical": ["a", "b"] }, "panels": [
+                    { "id": "a", "title": "A", "url": "https://a.test" },
+                    { "id": "b", "title": "B", "url": "https://b.test" },
+                ] },
+                { "id": "s2", "name": "Grid", "layout": { "Grid": ["c", "d", "e"] }, "panels": [
+                    { "id": "c", "title": "C", "url": "https://c.test" },
+                    { "id": "d", "title": "D", "url": "https://d.test" },
+                    { "id": "e", "title": "E", "url": "https://e.test" },
+                ] },
+                { "id": "s3", "name": "Single", "layout": "Single", "panels": [
+                    { "id": "f", "title": "F", "url": "https://f.test" },
+                ] },
+            ],
+        });
+        fs::write(dir.join("spaces.json"), v1.to_string()).unwrap();
+
+        let state = SpacesStore::in_profile(&dir).load().unwrap().unwrap();
+        assert_eq!(state.active_space, 1);
+        assert_eq!(state.spaces[0].layout.panel_ids(), vec!["a", "b"]);
+        assert!(matches!(state.spaces[0].layout, Layout::Split { axis: Axis::Row, .. }));
+        assert_eq!(state.spaces[1].layout.panel_ids(), vec!["c", "d", "e"]);
+        assert_eq!(state.spaces[1].layout.node_at(&[0]).map(|row| row.panel_ids()), Some(vec!["c", "d"]));
+        assert_eq!(state.spaces[2].layout, Layout::Panel("f".into()));
+        let panel = &state.spaces[2].panels[0];
+        assert_eq!((panel.history.len(), panel.history[0].url.as_str(), panel.zoom), (1, "https://f.test/", 1.0));
+        assert_eq!(panel.url, "https://f.test/");
+        for space in &state.spaces {
+            assert_eq!(space.layout.validate(space.panels.iter().map(|panel| panel.id.as_str())), Ok(()));
+        }
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_repairs_inconsistent_spaces() {
+        let dir = temp_profile();
+        fs::create_dir_all(&dir).unwrap();
+        let broken = json!({
+            "version": 2,
+            "active_space": 0,
+            "spaces": [
+                { "id": "s1", "name": "Work", "layout": { "Panel": "gone" }, "focused_panel": "gone", "panels": [
+                    { "id": "a", "title": "A", "url": "https://a.test" },
+                ] },
+            ],
+        });
+        fs::write(dir.join("spaces.json"), broken.to_string()).unwrap();
+
+        let state = SpacesStore::in_profile(&dir).load().unwrap().unwrap();
+        assert_eq!(state.spaces[0].layout, Layout::Panel("a".into()));
+        assert_eq!(state.spaces[0].focused_panel, None);
+        assert_eq!(state.spaces[0].validate(), Ok(()));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_unreadable_file_is_kept_aside() {
+        let dir = temp_profile();
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("spaces.json"), r#"{ "version": 99, "spaces": [] }"#).unwrap();
+        let store = SpacesStore::in_profile(&dir);
+
+        assert!(matches!(store.load(), Err(StoreError::UnsupportedVersion(99))));
+        let state = store.load_or_default();
+        assert_eq!(state.spaces.len(), 1);
+        assert_eq!(state.spaces[0].name, "Personal");
+        assert!(fs::read_dir(&dir).unwrap().any(|entry| {
+            entry.unwrap().file_name().to_string_lossy().starts_with("spaces.json.unreadable-")
+        }));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,315 @@
+//! Space files: a space's panels and layout, for sharing a setup or starting from one.
+//!
+//! A space file is JSON with a format marker and its own version, independent of the spaces
+//! file: it holds what a space is made of (names, URLs, layout, per-panel settings) but none
+//! of one person's browsing in it. Panel IDs inside a file only tie the layout to the panels
+//! and can be anything readable; importing gives the space and every panel fresh IDs.
+
+use super::{Axis, Layout, Panel, Space, SpaceIdentity};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Version written by this build.
+pub const TEMPLATE_VERSION: u64 = 1;
+
+/// Suggested file name suffix.
+pub const FILE_EXTENSION: &str = "wave-space.json";
+
+const FORMAT: &str = "wave-space";
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SpaceTemplate {
+    pub name: String,
+    pub panels: Vec<PanelTemplate>,
+    pub layout: Layout,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub focused_panel: Option<String>,
+    #[serde(default)]
+    pub identity: SpaceIdentity,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct PanelTemplate {
+    pub id: String,
+    pub url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(default = "default_zoom")]
+    pub zoom: f32,
+    #[serde(default)]
+    pub pinned: bool,
+    #[serde(default)]
+    pub never_sleep: bool,
+}
+
+fn default_zoom() -> f32 {
+    super::panel::DEFAULT_ZOOM
+}
+
+#[derive(Debug)]
+pub enum TemplateError {
+    Io(io::Error),
+    Parse(serde_json::Error),
+    /// Valid JSON, but not a space file
+    NotASpaceFile,
+    /// Written by a newer build
+    UnsupportedVersion(u64),
+}
+
+impl fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TemplateError::Io(e) => write!(f, "I/O error: {}", e),
+            TemplateError::Parse(e) => write!(f, "malformed space file: {}", e),
+            TemplateError::NotASpaceFile => write!(f, "not a Wave space file"),
+            TemplateError::UnsupportedVersion(version) => {
+                write!(f, "space file has version {}, newer than the supported {}", version, TEMPLATE_VERSION)
+            }
+        }
+    }
+}
+
+impl std::error::Error for TemplateError {}
+
+impl From<io::Error> for TemplateError {
+    fn from(e: io::Error) -> Self {
+        TemplateError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for TemplateError {
+    fn from(e: serde_json::Error) -> Self {
+        TemplateError::Parse(e)
+    }
+}
+
+/// What to do when an imported space has the name of one already open.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OnConflict {
+    /// Keep both; the import gets a numbered name
+    KeepBoth,
+    /// The import takes the open space's place
+    Replace,
+}
+
+impl SpaceTemplate {
+    /// The shareable part of a space.
+    pub fn from_space(space: &Space) -> Self {
+        Self {
+            name: space.name.clone(),
+            panels: space
+                .panels
+                .iter()
+                .map(|panel| PanelTemplate {
+                    id: panel.id.clone(),
+                    url: panel.url.clone(),
+                    title: Some(panel.title.clone()),
+                    zoom: panel.zoom,
+                    pinned: panel.pinned,
+                    never_sleep: panel.never_sleep,
+                })
+                .collect(),
+            layout: space.layout.clone(),
+            focused_panel: space.focused_panel.clone(),
+            identity: space.identity.clone(),
+        }
+    }
+
+    /// A new space built from the template, with fresh IDs. Layout references to panels the
+    /// file doesn't list are dropped, and panels it leaves out of the layout are added.
+    pub fn instantiate(&self) -> Space {
+        let mut space = Space::new(&self.name);
+        space.identity = self.identity.clone();
+        let mut ids = HashMap::new();
+        for template in &self.panels {
+            let mut panel = Panel::new(&template.url);
+            if let Some(title) = &template.title {
+                panel.set_title(title);
+            }
+            panel.zoom = template.zoom;
+            panel.pinned = template.pinned;
+            panel.never_sleep = template.never_sleep;
+            ids.entry(template.id.as_str()).or_insert_with(|| panel.id.clone());
+            space.panels.push(panel);
+        }
+
+        let rename = |id: &str| ids.get(id).cloned().unwrap_or_else(|| id.to_string());
+        space.layout = self.layout.clone();
+        space.layout.rename_panels(&mut |id| rename(id));
+        space.focused_panel = self.focused_panel.as_deref().map(rename);
+        space.repair();
+        space
+    }
+
+    pub fn to_json(&self) -> Result<String, TemplateError> {
+        let document = json!({ "format": FORMAT, "version": TEMPLATE_VERSION, "space": self });
+        Ok(serde_json::to_string_pretty(&document)?)
+    }
+
+    pub fn from_json(data: &str) -> Result<Self, TemplateError> {
+        let mut document: Value = serde_json::from_str(data)?;
+        if document["format"] != FORMAT {
+            return Err(TemplateError::NotASpaceFile);
+        }
+        match document["version"].as_u64() {
+            Some(TEMPLATE_VERSION) => Ok(serde_json::from_value(document["space"].take())?),
+            Some(newer) if newer > TEMPLATE_VERSION => Err(TemplateError::UnsupportedVersion(newer)),
+            _ => Err(TemplateError::NotASpaceFile),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), TemplateError> {
+        fs::write(path, self.to_json()?)?;
+        Ok(())
+    }
+
+    pub fn load(path: &Path) -> Result<Self, TemplateError> {
+        Self::from_json(&fs::read_to_string(path)?)
+    }
+}
+
+/// Adds an imported space to `spaces`. Returns its index and the space it replaced, if any.
+pub fn add_imported(spaces: &mut Vec<Space>, mut space: Space, on_conflict: OnConflict) -> (usize, Option<Space>) {
+    let Some(existing) = spaces.iter().position(|open| open.name == space.name) else {
+        spaces.push(space);
+        return (spaces.len() - 1, None);
+    };
+    match on_conflict {
+        OnConflict::Replace => (existing, Some(std::mem::replace(&mut spaces[existing], space))),
+        OnConflict::KeepBoth => {
+            let base = space.name.clone();
+            space.name = (2..)
+                .map(|number| format!("{} ({})", base, number))
+                .find(|name| !spaces.iter().any(|open| open.name == *name))
+                .unwrap_or(base);
+            spaces.push(space);
+            (spaces.len() - 1, None)
+        }
+    }
+}
+
+/// Templates that come with the browser.
+pub fn builtin() -> Vec<SpaceTemplate> {
+    let panel = |id: &str, url: &str| PanelTemplate {
+        id: id.to_string(),
+        url: url.to_string(),
+        title: None,
+        zoom: default_zoom(),
+        pinned: false,
+        never_sleep: false,
+    };
+    let template = |name: &str, panels: Vec<PanelTemplate>, splits: &[(&str, &str, Axis)]| {
+        let mut layout = Layout::Empty;
+        layout.push(&panels[0].id, Axis::Row);
+        for (target, new_panel, axis) in splits {
+            layout.split(target, new_panel, *axis);
+        }
+        SpaceTemplate {
+            name: name.to_string(),
+            focused_panel: Some(panels[0].id.clone()),
+            panels,
+            layout,
+            identity: SpaceIdentity::default(),
+        }
+    };
+
+    vec![
+        template(
+            "Research",
+            vec![panel("search", "https://duckduckgo.com"), panel("notes", "about:home")],
+            &[("search", "notes", Axis::Row)],
+        ),
+        template(
+            "Release Day",
+            vec![
+                panel("releases", "https://github.com"),
+                panel("ci", "https://github.com/features/actions"),
+                panel("changelog", "about:home"),
+            ],
+            &[("releases", "ci", Axis::Row), ("ci", "changelog", Axis::Column)],
+        ),
+        template(
+            "Reading",
+            vec![panel("article", "about:home")],
+            &[],
+        ),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_export_import_round_trip_with_fresh_ids() {
+        let mut space = Space::new("Release Day");
+        let board = space.add_panel("https://board.test");
+        let ci = space.split_panel(&board, "https://ci.test", Axis::Column).unwrap();
+        space.panel_mut(&ci).unwrap().never_sleep = true;
+        space.panel_mut(&ci).unwrap().record_navigation("https://ci.test/runs");
+
+        let json = SpaceTemplate::from_space(&space).to_json().unwrap();
+        let imported = SpaceTemplate::from_json(&json).unwrap().instantiate();
+        assert_ne!(imported.id, space.id);
+        assert!(imported.panels.iter().all(|panel| space.panel(&panel.id).is_none()));
+        assert_eq!(imported.panels.iter().map(|panel| panel.url.as_str()).collect::<Vec<_>>(), vec!["https://board.test/", "https://ci.test/runs"]);
+        // Only where the panel is, not how it got there
+        assert_eq!(imported.panels[1].history.len(), 1);
+        assert!(imported.panels[1].never_sleep);
+        assert!(matches!(imported.layout, Layout::Split { axis: Axis::Column, .. }));
+        assert_eq!(imported.validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_hand_written_file() {
+        let json = r#"{
+            "format": "wave-space",
+            "version": 1,
+            "space": {
+                "name": "Team",
+                "panels": [{ "id": "chat", "url": "https://chat.test" }, { "id": "mail", "url": "https://mail.test", "pinned": true }],
+                "layout": { "Panel": "chat" },
+                "focused_panel": "nowhere"
+            }
+        }"#;
+        let space = SpaceTemplate::from_json(json).unwrap().instantiate();
+        assert_eq!(space.layout.panel_ids().len(), 2);
+        assert!(space.panels[1].pinned);
+        assert_eq!(space.focused_panel, None);
+        assert_eq!(space.validate(), Ok(()));
+
+        assert!(matches!(SpaceTemplate::from_json(r#"{ "spaces": [] }"#), Err(TemplateError::NotASpaceFile)));
+        assert!(matches!(
+            SpaceTemplate::from_json(r#"{ "format": "wave-space", "version": 7, "space": {} }"#),
+            Err(TemplateError::UnsupportedVersion(7))
+        ));
+    }
+
+    #[test]
+    fn test_name_conflicts() {
+        let mut spaces = vec![Space::new("Work"), Space::new("Work (2)")];
+        let (index, replaced) = add_imported(&mut spaces, Space::new("Work"), OnConflict::KeepBoth);
+        assert_eq!((index, replaced, spaces[2].name.as_str()), (2, None, "Work (3)"));
+
+        let import = Space::new("Work");
+        let import_id = import.id.clone();
+        let (index, replaced) = add_imported(&mut spaces, import, OnConflict::Replace);
+        assert_eq!(index, 0);
+        assert!(replaced.is_some_and(|space| space.id != import_id));
+        assert_eq!(spaces[0].id, import_id);
+    }
+
+    #[test]
+    fn test_builtin_templates_are_valid() {
+        for template in builtin() {
+            let space = template.instantiate();
+            assert_eq!(space.panels.len(), template.panels.len());
+            assert_eq!(space.validate(), Ok(()), "{}", template.name);
+        }
+    }
+}
@@ -1,9 +1,88 @@
+use wave_core::omnibox::{Input, OmniboxAction, Provider, RELEVANCE_KEYWORD, Suggestion, SuggestionKind};
+
+/// A word that, typed first in the omnibox, hands the rest of the input to an extension
+/// (the WebExtensions `omnibox` API).
+#[derive(Debug, Clone, PartialEq)]
+pub struct OmniboxKeyword {
+    pub extension_id: String,
+    /// Lowercase
+    pub keyword: String,
+    pub description: String,
+}
+
 pub struct ExtensionManager {
     // Placeholder for extension management logic
+    omnibox_keywords: Vec<OmniboxKeyword>,
 }
 
 impl ExtensionManager {
     pub fn new() -> Self {
-        Self {}
+        Self { omnibox_keywords: Vec::new() }
+    }
+
+    /// Gives `keyword` to an extension. Returns `false` if another extension has it.
+    pub fn register_omnibox_keyword(&mut self, extension_id: &str, keyword: &str, description: &str) -> bool {
+        let keyword = keyword.trim().to_lowercase();
+        if keyword.is_empty() || self.omnibox_keywords.iter().any(|existing| existing.keyword == keyword && existing.extension_id != extension_id) {
+            return false;
+        }
+        self.omnibox_keywords.retain(|existing| existing.extension_id != extension_id);
+        self.omnibox_keywords.push(OmniboxKeyword {
+            extension_id: extension_id.to_string(),
+            keyword,
+            description: description.to_string(),
+        });
+        true
+    }
+
+    pub fn omnibox_keywords(&self) -> &[OmniboxKeyword] {
+        &self.omnibox_keywords
+    }
+
+    /// The user chose an extension's keyword suggestion (`omnibox.onInputEntered`).
+    pub fn omnibox_input_entered(&self, extension_id: &str, text: &str) {
+        log::info!("Extension {}: omnibox input {:?}", extension_id, text);
+        // runtime.dispatch(extension_id, OmniboxEvent::InputEntered(text))
+    }
+}
+
+impl Default for ExtensionManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Provider for ExtensionManager {
+    fn suggest(&self, input: &Input, out: &mut Vec<Suggestion>) {
+        let (first, rest) = input.text.split_once(char::is_whitespace).unwrap_or((&input.text, ""));
+        let first = first.to_lowercase();
+        let Some(registered) = self.omnibox_keywords.iter().find(|registered| registered.keyword == first) else {
+            return;
+        };
+        let text = rest.trim().to_string();
+        out.push(Suggestion {
+            kind: SuggestionKind::Extension,
+            title: if text.is_empty() { registered.keyword.clone() } else { text.clone() },
+            detail: registered.description.clone(),
+            action: OmniboxAction::Extension { extension_id: registered.extension_id.clone(), text },
+            relevance: RELEVANCE_KEYWORD,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wave_core::omnibox::suggest;
+
+    #[test]
+    fn test_omnibox_keywords() {
+        let mut extensions = ExtensionManager::new();
+        assert!(extensions.register_omnibox_keyword("notes@wave", "Note", "Add to notes"));
+        assert!(!extensions.register_omnibox_keyword("todo@wave", "note", "Add a todo"));
+
+        let ranked = suggest("note  buy milk", &[&extensions], 10);
+        assert_eq!(ranked[0].action, OmniboxAction::Extension { extension_id: "notes@wave".into(), text: "buy milk".into() });
+        assert!(suggest("notes app", &[&extensions], 10).is_empty());
     }
 }
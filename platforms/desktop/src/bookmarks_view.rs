@@ -0,0 +1,142 @@
+use eframe::egui;
+
+use wave_core::bookmarks::{Bookmark, BookmarkError, BookmarkNode, Bookmarks, Folder};
+
+/// Something the user did in the bookmarks tree; applied once drawing is done.
+pub enum BookmarkAction {
+    Open(String),
+    Edit(String),
+    Delete(String),
+}
+
+/// Draws the bookmarks as collapsible folders.
+pub fn show_tree(ui: &mut egui::Ui, bookmarks: &Bookmarks) -> Option<BookmarkAction> {
+    let mut action = None;
+    show_folder(ui, bookmarks.root(), &mut action);
+    action
+}
+
+fn show_folder(ui: &mut egui::Ui, folder: &Folder, action: &mut Option<BookmarkAction>) {
+    for child in &folder.children {
+        match child {
+            BookmarkNode::Folder(folder) => {
+                let response = egui::CollapsingHeader::new(format!("🗀 {}", folder.title))
+                    .id_source(&folder.id)
+                    .show(ui, |ui| show_folder(ui, folder, action));
+                response.header_response.context_menu(|ui| {
+                    if ui.button("Delete folder").clicked() {
+                        *action = Some(BookmarkAction::Delete(folder.id.clone()));
+                        ui.close_menu();
+                    }
+                });
+            }
+            BookmarkNode::Bookmark(bookmark) => {
+                let mut hover = bookmark.url.clone();
+                if let Some(keyword) = &bookmark.keyword {
+                    hover.push_str(&format!("\nKeyword: {}", keyword));
+                }
+                if !bookmark.tags.is_empty() {
+                    hover.push_str(&format!("\nTags: {}", bookmark.tags.join(", ")));
+                }
+                let response = ui.small_button(format!("☆ {}", bookmark.title)).on_hover_text(hover);
+                if response.clicked() {
+                    *action = Some(BookmarkAction::Open(bookmark.url.clone()));
+                }
+                response.context_menu(|ui| {
+                    if ui.button("Edit…").clicked() {
+                        *action = Some(BookmarkAction::Edit(bookmark.id.clone()));
+                        ui.close_menu();
+                    }
+                    if ui.button("Delete").clicked() {
+                        *action = Some(BookmarkAction::Delete(bookmark.id.clone()));
+                        ui.close_menu();
+                    }
+                });
+            }
+        }
+    }
+}
+
+/// How the editor window was left.
+pub enum EditorOutcome {
+    Open,
+    /// Saved or removed
+    Changed,
+    Cancelled,
+}
+
+/// Edits one bookmark; nothing changes until saved.
+pub struct BookmarkEditor {
+    id: String,
+    title: String,
+    url: String,
+    // Comma-separated
+    tags: String,
+    keyword: String,
+    error: Option<String>,
+}
+
+impl BookmarkEditor {
+    pub fn open(bookmark: &Bookmark) -> Self {
+        Self {
+            id: bookmark.id.clone(),
+            title: bookmark.title.clone(),
+            url: bookmark.url.clone(),
+            tags: bookmark.tags.join(", "),
+            keyword: bookmark.keyword.clone().unwrap_or_default(),
+            error: None,
+        }
+    }
+
+    pub fn show(&mut self, ctx: &egui::Context, bookmarks: &mut Bookmarks) -> EditorOutcome {
+        let mut outcome = EditorOutcome::Open;
+        egui::Window::new("Edit bookmark")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_TOP, [0.0, 60.0])
+            .show(ctx, |ui| {
+                egui::Grid::new("bookmark_editor").num_columns(2).show(ui, |ui| {
+                    ui.label("Name");
+                    ui.add(egui::TextEdit::singleline(&mut self.title).desired_width(320.0));
+                    ui.end_row();
+                    ui.label("URL");
+                    ui.add(egui::TextEdit::singleline(&mut self.url).desired_width(320.0));
+                    ui.end_row();
+                    ui.label("Tags");
+                    ui.add(egui::TextEdit::singleline(&mut self.tags).hint_text("work, reading").desired_width(320.0));
+                    ui.end_row();
+                    ui.label("Keyword");
+                    ui.add(egui::TextEdit::singleline(&mut self.keyword).hint_text("typed in the address bar").desired_width(120.0));
+                    ui.end_row();
+                });
+                ui.label(egui::RichText::new("A keyword opens the bookmark from the address bar; %s in its URL is replaced by what follows it.").weak());
+                if let Some(error) = &self.error {
+                    ui.colored_label(egui::Color32::from_rgb(243, 139, 168), error);
+                }
+                ui.horizontal(|ui| {
+                    if ui.button(" Save ").clicked() {
+                        match self.save(bookmarks) {
+                            Ok(()) => outcome = EditorOutcome::Changed,
+                            Err(e) => self.error = Some(e.to_string()),
+                        }
+                    }
+                    if ui.button(" Remove bookmark ").clicked() {
+                        bookmarks.remove(&self.id);
+                        outcome = EditorOutcome::Changed;
+                    }
+                    if ui.button(" Cancel ").clicked() {
+                        outcome = EditorOutcome::Cancelled;
+                    }
+                });
+            });
+        outcome
+    }
+
+    fn save(&self, bookmarks: &mut Bookmarks) -> Result<(), BookmarkError> {
+        // The keyword first: it is the one that can be refused
+        bookmarks.set_keyword(&self.id, Some(&self.keyword))?;
+        bookmarks.rename(&self.id, self.title.trim())?;
+        bookmarks.set_url(&self.id, self.url.trim())?;
+        bookmarks.set_tags(&self.id, &self.tags.split(',').collect::<Vec<_>>())
+    }
+}
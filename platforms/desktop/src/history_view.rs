@@ -0,0 +1,154 @@
+use eframe::egui;
+
+use wave_core::history::{self, History, HistoryError};
+
+pub const PAGE_URL: &str = "about:history";
+
+/// Visits listed when not searching.
+const RECENT_VISITS: usize = 300;
+const SEARCH_RESULTS: usize = 100;
+
+/// The `about:history` page: recent visits, search, and forgetting.
+#[derive(Default)]
+pub struct HistoryPage {
+    query: String,
+    // What the page lists for `query`; `None` once the query or the history changed
+    rows: Option<Vec<Row>>,
+    error: Option<String>,
+}
+
+/// A row of the page: a visit, or a search result with its visit count.
+struct Row {
+    url: String,
+    title: String,
+    time: u64,
+    visits: Option<u64>,
+}
+
+impl HistoryPage {
+    /// The history changed behind the page's back (a visit was recorded); it is listed again
+    /// the next time it is drawn.
+    pub fn invalidate(&mut self) {
+        self.rows = None;
+    }
+
+    /// Draws the page; returns a URL the user chose to open.
+    pub fn show(&mut self, ui: &mut egui::Ui, history: &mut History, now: u64) -> Option<String> {
+        let mut open = None;
+        ui.heading("History");
+        ui.add_space(10.0);
+
+        ui.horizontal(|ui| {
+            let search = ui.add(egui::TextEdit::singleline(&mut self.query).hint_text("Search titles and addresses").desired_width(320.0));
+            if search.changed() {
+                self.rows = None;
+            }
+            ui.separator();
+            ui.label("Forget:");
+            if ui.button(" Last hour ").clicked() {
+                self.report(history.delete_range(now.saturating_sub(3600), now + 1).map(drop));
+            }
+            if ui.button(" Last day ").clicked() {
+                self.report(history.delete_range(now.saturating_sub(86_400), now + 1).map(drop));
+            }
+            if ui.button(" Everything ").clicked() {
+                self.report(history.clear());
+            }
+        });
+        if let Some(error) = &self.error {
+            ui.colored_label(egui::Color32::from_rgb(243, 139, 168), error);
+        }
+        ui.add_space(10.0);
+
+        if self.rows.is_none() {
+            self.rows = Some(self.list(history));
+        }
+        let rows = self.rows.as_deref().unwrap_or_default();
+        if rows.is_empty() && self.error.is_none() {
+            ui.label(egui::RichText::new(if self.query.trim().is_empty() { "Nothing visited yet." } else { "No matches." }).weak());
+        }
+
+        let mut forget = None;
+        egui::ScrollArea::vertical().id_source("history_page").show(ui, |ui| {
+            for (index, row) in rows.iter().enumerate() {
+                ui.horizontal(|ui| {
+                    ui.label(egui::RichText::new(ago(now.saturating_sub(row.time))).weak().monospace());
+                    let title = if row.title.is_empty() { &row.url } else { &row.title };
+                    let response = ui.link(title).on_hover_text(&row.url);
+                    if response.clicked() {
+                        open = Some(row.url.clone());
+                    }
+                    response.context_menu(|ui| {
+                        if ui.button("Forget this page").clicked() {
+                            forget = Some(Forget::Page(row.url.clone()));
+                            ui.close_menu();
+                        }
+                        if let Some(host) = history::host_of(&row.url).filter(|host| !host.is_empty())
+                            && ui.button(format!("Forget everything on {}", host)).clicked()
+                        {
+                            forget = Some(Forget::Site(host));
+                            ui.close_menu();
+                        }
+                    });
+                    ui.label(egui::RichText::new(&row.url).weak().small());
+                    if let Some(visits) = row.visits {
+                        ui.label(egui::RichText::new(format!("{} visits", visits)).weak().small());
+                    }
+                });
+                if index + 1 < rows.len() && rows[index + 1].time / 86_400 != row.time / 86_400 {
+                    ui.separator();
+                }
+            }
+        });
+        match forget {
+            Some(Forget::Page(url)) => self.report(history.delete_url(&url).map(drop)),
+            Some(Forget::Site(host)) => self.report(history.delete_domain(&host).map(drop)),
+            None => {}
+        }
+        open
+    }
+
+    /// Recent visits, or the pages matching the search.
+    fn list(&mut self, history: &History) -> Vec<Row> {
+        let rows = if self.query.trim().is_empty() {
+            history.visits(0, RECENT_VISITS).map(|visits| {
+                visits
+                    .into_iter()
+                    .map(|visit| Row { url: visit.url, title: visit.title, time: visit.visited_at, visits: None })
+                    .collect::<Vec<_>>()
+            })
+        } else {
+            history.search(&self.query, SEARCH_RESULTS).map(|entries| {
+                entries
+                    .into_iter()
+                    .map(|entry| Row { url: entry.url, title: entry.title, time: entry.last_visit, visits: Some(entry.visit_count) })
+                    .collect()
+            })
+        };
+        rows.unwrap_or_else(|e| {
+            self.error = Some(e.to_string());
+            Vec::new()
+        })
+    }
+
+    /// Shows the outcome of forgetting something, and lists the page again.
+    fn report(&mut self, result: Result<(), HistoryError>) {
+        self.error = result.err().map(|e| e.to_string());
+        self.rows = None;
+    }
+}
+
+enum Forget {
+    Page(String),
+    Site(String),
+}
+
+/// How long ago, in the largest whole unit.
+fn ago(seconds: u64) -> String {
+    match seconds {
+        0..60 => "just now".to_string(),
+        60..3600 => format!("{:>3} min ago", seconds / 60),
+        3600..86_400 => format!("{:>3} h ago", seconds / 3600),
+        _ => format!("{:>3} d ago", seconds / 86_400),
+    }
+}
@@ -0,0 +1,99 @@
+use eframe::egui;
+
+use wave_core::spaces::{Axis, Layout};
+
+/// Thickness of the grab area between two split children.
+const DIVIDER_WIDTH: f32 = 6.0;
+
+/// Something the user did to the layout while it was drawn; applied once drawing is done.
+pub enum LayoutAction {
+    DragDivider { path: Vec<usize>, divider: usize, delta: f32 },
+    /// A drag ended; the new sizes are worth saving
+    DividerReleased,
+}
+
+/// Draws a layout tree into `rect`, calling `show_panel` with each panel's ID and area.
+pub fn show(
+    ui: &mut egui::Ui,
+    layout: &Layout,
+    rect: egui::Rect,
+    show_panel: &mut dyn FnMut(&mut egui::Ui, &str, egui::Rect),
+) -> Vec<LayoutAction> {
+    let mut actions = Vec::new();
+    show_node(ui, layout, rect, &mut Vec::new(), show_panel, &mut actions);
+    actions
+}
+
+fn show_node(
+    ui: &mut egui::Ui,
+    node: &Layout,
+    rect: egui::Rect,
+    path: &mut Vec<usize>,
+    show_panel: &mut dyn FnMut(&mut egui::Ui, &str, egui::Rect),
+    actions: &mut Vec<LayoutAction>,
+) {
+    match node {
+        Layout::Empty => {}
+        Layout::Panel(id) => show_panel(ui, id, rect),
+        Layout::Split { axis, children } => {
+            let extent = match axis {
+                Axis::Row => rect.width(),
+                Axis::Column => rect.height(),
+            };
+            let dividers = DIVIDER_WIDTH * (children.len() - 1) as f32;
+            let available = (extent - dividers).max(0.0);
+
+            let mut offset = 0.0;
+            for (index, child) in children.iter().enumerate() {
+                let size = available * child.ratio;
+                let child_rect = span(rect, *axis, offset, size);
+                path.push(index);
+                show_node(ui, &child.node, child_rect, path, show_panel, actions);
+                path.pop();
+                offset += size;
+
+                if index + 1 < children.len() {
+                    let divider_rect = span(rect, *axis, offset, DIVIDER_WIDTH);
+                    let id = ui.id().with(("layout_divider", path.clone(), index));
+                    let response = ui.interact(divider_rect, id, egui::Sense::drag());
+                    let cursor = match axis {
+                        Axis::Row => egui::CursorIcon::ResizeHorizontal,
+                        Axis::Column => egui::CursorIcon::ResizeVertical,
+                    };
+                    let response = response.on_hover_cursor(cursor);
+
+                    let color = if response.hovered() || response.dragged() {
+                        ui.visuals().widgets.hovered.bg_fill
+                    } else {
+                        ui.visuals().widgets.noninteractive.bg_stroke.color
+                    };
+                    ui.painter().rect_filled(divider_rect.shrink(1.0), 1.0, color);
+
+                    let drag = match axis {
+                        Axis::Row => response.drag_delta().x,
+                        Axis::Column => response.drag_delta().y,
+                    };
+                    if drag != 0.0 && available > 0.0 {
+                        actions.push(LayoutAction::DragDivider {
+                            path: path.clone(),
+                            divider: index,
+                            delta: drag / available,
+                        });
+                    }
+                    if response.drag_released() {
+                        actions.push(LayoutAction::DividerReleased);
+                    }
+                    offset += DIVIDER_WIDTH;
+                }
+            }
+        }
+    }
+}
+
+/// The part of `rect` from `offset` to `offset + size` along `axis`.
+fn span(rect: egui::Rect, axis: Axis, offset: f32, size: f32) -> egui::Rect {
+    match axis {
+        Axis::Row => egui::Rect::from_min_size(rect.min + egui::vec2(offset, 0.0), egui::vec2(size, rect.height())),
+        Axis::Column => egui::Rect::from_min_size(rect.min + egui::vec2(0.0, offset), egui::vec2(rect.width(), size)),
+    }
+}
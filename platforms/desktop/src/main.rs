@@ -4,64 +4,771 @@ use eframe::egui;
 use window_vibrancy::{apply_mica, apply_acrylic, Color};
 #[cfg(target_os = "macos")]
 use window_vibrancy::{apply_vibrancy, NSVisualEffectMaterial};
+use std::collections::HashMap;
 use std::sync::Arc;
 
-use wave_core::{shield::WaveShield, WaveEngine, EngineEvent, spaces::Space};
-use wave_common::theme::Theme;
+use wave_core::{bookmarks::{self, Bookmark, Bookmarks, BookmarksStore}, history::{History, Transition}, omnibox::{OmniboxAction, Provider, SuggestionKind, providers::{AboutPagesProvider, BookmarksProvider, HistoryProvider, PanelsProvider, TypedProvider}, search::{self, SearchEngine}}, profile, reading_list::{ReadingList, ReadingListStore, reader::{self, Article}}, shield::{Decision, ResourceType, WaveShield, safe_browsing}, WaveEngine, EngineEvent, EngineNotification, spaces::{Axis, ClosedItem, Essentials, Layout, LifecycleManager, Panel, PanelState, RecentlyClosed, Space, journal::{PreviousSession, SessionJournal}, store::{SpacesState, SpacesStore}, template::{self, OnConflict, SpaceTemplate}, SpaceIdentity}};
+use wave_common::theme::{AccentColor, CatppuccinFlavor, Theme};
+use wave_extensions::ExtensionManager;
 
 mod constants {
     pub const WINDOW_TITLE: &str = "Wave Browser";
+    /// Width of the screenshots kept for discarded panels
+    pub const THUMBNAIL_WIDTH: usize = 320;
 }
+mod bookmarks_view;
+mod history_view;
+mod layout_view;
+mod omnibox_view;
+mod reading_view;
 mod render_glue;
+mod rules_editor;
+mod safe_browsing_updater;
 
 struct WaveApp {
-    _shield: WaveShield,
+    shield: WaveShield,
+    rules_editor: rules_editor::RulesEditor,
+    safe_browsing_updater: safe_browsing_updater::SafeBrowsingUpdater,
     engine: WaveEngine,
-    _theme: Theme,
+    // The browser theme; spaces may override its flavor and accent
+    theme: Theme,
+    // Flavor and accent the UI is drawn with now
+    applied_theme: Option<(CatppuccinFlavor, AccentColor)>,
     renderer: Option<render_glue::OffscreenRenderer>,
     url_input: String,
     // Spaces Logic
     spaces: Vec<Space>,
     active_space_idx: usize,
+    essentials: Essentials,
+    // Essential shown in place of the active space's panels
+    open_essential: Option<String>,
+    spaces_store: SpacesStore,
+    journal: Option<SessionJournal>,
+    // Left by a run that crashed, until the user decides whether to restore it
+    previous_session: Option<PreviousSession>,
+    recently_closed: RecentlyClosed,
+    lifecycle: LifecycleManager,
+    // Panel whose page the engine holds
+    shown_panel: Option<String>,
+    // Textures of discarded panels' thumbnails, made when first drawn
+    thumbnails: HashMap<String, egui::TextureHandle>,
+    space_file: Option<SpaceFileDialog>,
+    space_settings: Option<SpaceSettingsDialog>,
+    bookmarks: Bookmarks,
+    bookmarks_store: BookmarksStore,
+    bookmark_editor: Option<bookmarks_view::BookmarkEditor>,
+    bookmark_file: Option<BookmarkFileDialog>,
+    history: History,
+    history_page: history_view::HistoryPage,
+    // How the next page the engine commits was reached
+    next_transition: Option<Transition>,
+    // The engine is reloading a panel being switched to, which isn't a visit; until that load
+    // commits, fails, or the user navigates elsewhere
+    restoring_panel: bool,
+    omnibox: omnibox_view::Omnibox,
+    search_engine: SearchEngine,
+    extensions: ExtensionManager,
+    reading_list: ReadingList,
+    reading_list_store: ReadingListStore,
+    // Snapshot shown at `about:reader`
+    reader_snapshot: Option<Article>,
+}
+
+/// The browser's own pages, as the omnibox offers them.
+const ABOUT_PAGES: &[(&str, &str)] = &[("about:home", "Home"), (history_view::PAGE_URL, "History"), (rules_editor::PAGE_URL, "Shield rules")];
+
+/// Asks where to import bookmarks from or export them to.
+struct BookmarkFileDialog {
+    export: bool,
+    path: String,
+    error: Option<String>,
+}
+
+/// Edits a space's name and identity; nothing changes until saved.
+struct SpaceSettingsDialog {
+    space_id: String,
+    name: String,
+    icon: String,
+    identity: SpaceIdentity,
+}
+
+/// Asks where to export a space to or import one from.
+struct SpaceFileDialog {
+    // ID of the space to export; `None` when importing
+    export: Option<String>,
+    path: String,
+    on_conflict: OnConflict,
+    error: Option<String>,
 }
 
 impl WaveApp {
     fn new(cc: &eframe::CreationContext<'_>) -> Self {
         let theme = Theme::default_wave();
-        
-        let mut visuals = egui::Visuals::dark();
-        let bg_color = egui::Color32::from_rgb(30, 30, 46);
-        visuals.panel_fill = bg_color;
-        visuals.window_fill = bg_color;
-        cc.egui_ctx.set_visuals(visuals);
+        cc.egui_ctx.set_visuals(visuals_for(&theme));
 
         // Initialize OpenGL Glue
-        let renderer = if let Some(gl) = cc.gl.as_ref() {
-            Some(render_glue::OffscreenRenderer::new(gl.clone(), 800, 600))
-        } else {
-            None
-        };
+        let renderer = cc
+            .gl
+            .as_ref()
+            .map(|gl| render_glue::OffscreenRenderer::new(gl.clone(), 800, 600));
 
         // Initialize Engine
         let engine = WaveEngine::new(std::ptr::null_mut());
 
-        // Initialize Default Spaces
-        let mut work_space = Space::new("Work");
-        work_space.add_panel("https://github.com");
-        work_space.add_panel("https://slack.com");
+        // Restore Spaces from the profile
+        let profile_dir = profile::default_profile_dir();
+        let spaces_store = SpacesStore::in_profile(&profile_dir);
+        let state = spaces_store.load_or_default();
+        // Journal this run; one that crashed may have left changes the spaces file missed
+        let (journal, previous_session) = match SessionJournal::open(&profile_dir, &state) {
+            Ok((journal, previous)) => (Some(journal), previous),
+            Err(e) => {
+                log::warn!("Session journal unavailable: {}", e);
+                (None, None)
+            }
+        };
+        let SpacesState { spaces, active_space, essentials } = state;
+        let bookmarks_store = BookmarksStore::in_profile(&profile_dir);
+        let bookmarks = bookmarks_store.load_or_default();
+        let reading_list_store = ReadingListStore::in_profile(&profile_dir);
+        let reading_list = reading_list_store.load_or_default();
+        let search_engine = SearchEngine::in_profile(&profile_dir);
+        let mut history = History::open_in_profile(&profile_dir);
+        if let Err(e) = history.refresh_frecency() {
+            log::warn!("Could not refresh history ranking: {}", e);
+        }
 
-        let mut personal_space = Space::new("Personal");
-        personal_space.add_panel("https://youtube.com");
+        // The user's own shield rules, on top of the subscriptions
+        let mut shield = WaveShield::new();
+        let rules_editor = rules_editor::RulesEditor::open(profile_dir.join("user.rules"));
+        shield.load_user_rules(rules_editor.compiled());
+        shield.open_safe_browsing(&profile_dir);
+        shield.open_cookie_banner_opt_outs(&profile_dir);
+        let safe_browsing_updater = safe_browsing_updater::SafeBrowsingUpdater::new(safe_browsing::update_url(&profile_dir));
 
-        Self {
-            _shield: WaveShield::new(),
+        let mut app = Self {
+            shield,
+            rules_editor,
+            safe_browsing_updater,
             engine,
-            _theme: theme,
+            applied_theme: Some((theme.flavor, theme.accent)),
+            theme,
             renderer,
             url_input: String::from("https://example.com"),
-            spaces: vec![work_space, personal_space],
-            active_space_idx: 0,
+            spaces,
+            active_space_idx: active_space,
+            essentials,
+            open_essential: None,
+            spaces_store,
+            journal,
+            previous_session,
+            recently_closed: RecentlyClosed::default(),
+            lifecycle: LifecycleManager::default(),
+            shown_panel: None,
+            thumbnails: HashMap::new(),
+            space_file: None,
+            space_settings: None,
+            bookmarks,
+            bookmarks_store,
+            bookmark_editor: None,
+            bookmark_file: None,
+            history,
+            history_page: history_view::HistoryPage::default(),
+            next_transition: None,
+            restoring_panel: false,
+            omnibox: omnibox_view::Omnibox::new(),
+            search_engine,
+            extensions: ExtensionManager::new(),
+            reading_list,
+            reading_list_store,
+            reader_snapshot: None,
+        };
+        app.open_focused_panel();
+        app
+    }
+
+    /// Draws the engine's surface into `rect` and forwards pointer input to it.
+    fn show_web_view(&mut self, ui: &mut egui::Ui, rect: egui::Rect, time: f64) {
+        let (w, h) = (rect.width() as i32, rect.height() as i32);
+
+        if let Some(renderer) = &mut self.renderer {
+            if renderer.width != w || renderer.height != h {
+                renderer.resize(w, h);
+                self.engine.dispatch(EngineEvent::Resize { width: w as u32, height: h as u32 });
+            }
+
+            // EXECUTE ENGINE PAINT
+            // 1. Prepare offscreen buffer
+            renderer.begin_paint();
+            
+            // 2. Tell Wave Engine (Servo) to draw into the current context
+            self.engine.paint(time);
+            
+            // 3. Finish up
+            renderer.end_paint();
+
+            // Capture clicks on the web view
+            let response = ui.interact(rect, ui.id().with("web_view"), egui::Sense::click());
+            if let Some(picker) = self.engine.element_picker() {
+                if let Some(pos) = response.hover_pos()
+                    && !picker.is_picked()
+                {
+                    self.engine.dispatch(EngineEvent::InputHover {
+                        x: pos.x - rect.left(),
+                        y: pos.y - rect.top(),
+                    });
+                }
+                if let Some(element) = self.engine.element_picker().and_then(|picker| picker.highlighted()) {
+                    let [x, y, width, height] = element.rect;
+                    let highlight = egui::Rect::from_min_size(rect.min + egui::vec2(x, y), egui::vec2(width, height));
+                    let color = egui::Color32::from_rgb(137, 180, 250);
+                    ui.painter().rect(highlight, 2.0, color.linear_multiply(0.2), egui::Stroke::new(2.0, color));
+                }
+            }
+            if response.clicked()
+                && let Some(pos) = response.hover_pos()
+            {
+                // A site the user clicks in is more than a redirect hop
+                if self.engine.element_picker().is_none() {
+                    self.shield.record_user_interaction(self.engine.get_url());
+                }
+                self.engine.dispatch(EngineEvent::InputClick { 
+                    x: pos.x - rect.left(), 
+                    y: pos.y - rect.top() 
+                });
+            }
+
+            ui.painter().add(egui::PaintCallback {
+                rect,
+                callback: Arc::new(eframe::egui_glow::CallbackFn::new(move |_info, _painter| {
+                     // Real engine blitting would involve:
+                     // 1. Binding the renderer's texture
+                     // 2. Drawing a textured quad over 'rect'
+                })),
+            });
+            
+            // Overlay info (simulating what the web page might be)
+            ui.put(rect, egui::Label::new(format!("Browsing: {}\nEngine Surface: {}x{}", self.engine.get_url(), w, h)));
+        }
+    }
+
+    /// Opens a new panel next to the focused one.
+    fn split_active_panel(&mut self, axis: Axis) {
+        let space = &mut self.spaces[self.active_space_idx];
+        // A space without panels shows the current page; that becomes its first panel
+        let target = match space.focused() {
+            Some(panel) => panel.id.clone(),
+            None => space.add_panel(self.engine.get_url()),
+        };
+        if let Some(id) = space.split_panel(&target, "about:home", axis) {
+            space.focus_panel(&id);
+            self.open_focused_panel();
+        }
+        self.save_spaces();
+    }
+
+    fn close_active_panel(&mut self) {
+        let space = &mut self.spaces[self.active_space_idx];
+        let Some(id) = space.focused().map(|panel| panel.id.clone()) else {
+            return;
+        };
+        if let Some(closed) = space.close_panel(&id) {
+            self.recently_closed.push(ClosedItem::Panel(closed));
+        }
+        self.open_focused_panel();
+        self.save_spaces();
+    }
+
+    /// Closes a space; the last one stays open.
+    fn close_space(&mut self, index: usize) {
+        if self.spaces.len() < 2 || index >= self.spaces.len() {
+            return;
+        }
+        let space = self.spaces.remove(index);
+        self.recently_closed.push(ClosedItem::closed_space(index, space));
+        if self.active_space_idx > index || self.active_space_idx == self.spaces.len() {
+            self.active_space_idx -= 1;
+        }
+        self.open_focused_panel();
+        self.save_spaces();
+    }
+
+    /// The browser theme with the active space's overrides.
+    fn active_theme(&self) -> Theme {
+        self.spaces[self.active_space_idx].identity.theme(&self.theme)
+    }
+
+    /// Stores what the space settings dialog holds.
+    fn save_space_settings(&mut self) {
+        let Some(dialog) = self.space_settings.take() else {
+            return;
+        };
+        if let Some(space) = self.spaces.iter_mut().find(|space| space.id == dialog.space_id) {
+            let name = dialog.name.trim();
+            if !name.is_empty() {
+                space.name = name.to_string();
+            }
+            space.identity = dialog.identity;
+            space.identity.set_icon(&dialog.icon);
+            self.save_spaces();
+        }
+    }
+
+    /// Opens a space made from a template or space file and switches to it. A space it
+    /// replaces can be reopened like a closed one.
+    fn add_space_from(&mut self, template: &SpaceTemplate, on_conflict: OnConflict) {
+        let (index, replaced) = template::add_imported(&mut self.spaces, template.instantiate(), on_conflict);
+        if let Some(replaced) = replaced {
+            self.recently_closed.push(ClosedItem::closed_space(index, replaced));
+        }
+        self.active_space_idx = index;
+        self.open_focused_panel();
+        self.save_spaces();
+    }
+
+    /// Carries out the space file dialog; on failure it stays open with the error.
+    fn finish_space_file(&mut self) {
+        let Some((export, path, on_conflict)) = self
+            .space_file
+            .as_ref()
+            .map(|dialog| (dialog.export.clone(), std::path::PathBuf::from(dialog.path.trim()), dialog.on_conflict))
+        else {
+            return;
+        };
+        let result = match export {
+            Some(id) => match self.spaces.iter().find(|space| space.id == id) {
+                Some(space) => SpaceTemplate::from_space(space).save(&path),
+                // Closed while the dialog was open
+                None => Ok(()),
+            },
+            None => SpaceTemplate::load(&path).map(|template| self.add_space_from(&template, on_conflict)),
+        };
+        match result {
+            Ok(()) => self.space_file = None,
+            Err(e) => {
+                log::warn!("Space file {}: {}", path.display(), e);
+                if let Some(dialog) = self.space_file.as_mut() {
+                    dialog.error = Some(e.to_string());
+                }
+            }
+        }
+    }
+
+    /// Undoes a close: the most recent one, or the one at `index` of the sidebar list.
+    fn reopen_closed(&mut self, index: Option<usize>) {
+        let item = match index {
+            Some(index) => self.recently_closed.take(index),
+            None => self.recently_closed.pop(),
+        };
+        let Some(item) = item else {
+            return;
+        };
+        let panel_id = match &item {
+            ClosedItem::Panel(closed) => Some(closed.panel.id.clone()),
+            ClosedItem::Space { .. } => None,
+        };
+        let Some(space_index) = item.restore(&mut self.spaces, self.active_space_idx) else {
+            return;
+        };
+        self.active_space_idx = space_index;
+        if let Some(id) = panel_id {
+            self.spaces[space_index].focus_panel(&id);
+        }
+        self.open_focused_panel();
+        self.save_spaces();
+    }
+
+    /// Shows the active space's focused panel in the engine, where the user left it.
+    fn open_focused_panel(&mut self) {
+        self.open_essential = None;
+        self.show_current_panel();
+    }
+
+    /// Shows an essential in place of the active space's panels.
+    fn open_essential(&mut self, id: &str) {
+        self.open_essential = Some(id.to_string());
+        self.show_current_panel();
+    }
+
+    /// Loads the open essential or the focused panel into the engine.
+    fn show_current_panel(&mut self) {
+        let panel = match &self.open_essential {
+            Some(id) => self.essentials.get_mut(id),
+            None => self.spaces[self.active_space_idx].focused_mut(),
+        };
+        let Some(panel) = panel else {
+            self.shown_panel = None;
+            return;
+        };
+        // What the engine showed until now is kept as a picture, in case that panel is discarded
+        if self.shown_panel.as_ref() != Some(&panel.id) {
+            if let (Some(shown), Some(renderer)) = (&self.shown_panel, &self.renderer) {
+                self.lifecycle.set_thumbnail(shown, renderer.thumbnail(constants::THUMBNAIL_WIDTH));
+                self.thumbnails.remove(shown);
+            }
+            self.shown_panel = Some(panel.id.clone());
+        }
+        if self.lifecycle.activate(&panel.id) == PanelState::Discarded {
+            log::debug!("Reloading discarded panel {}", panel.id);
+        }
+        panel.touch();
+        let [x, y] = panel.scroll_offset;
+        let zoom = panel.zoom;
+        self.url_input = panel.url.clone();
+        self.restoring_panel = true;
+        self.engine.dispatch(EngineEvent::LockOrigin(panel.locked_origin()));
+        self.engine.dispatch(EngineEvent::LoadUrl(panel.url.clone()));
+        self.engine.dispatch(EngineEvent::SetZoom(zoom));
+        self.engine.dispatch(EngineEvent::ScrollTo { x, y });
+    }
+
+    /// Opens `url` in a new panel next to the focused one in the active space, and shows it.
+    fn open_new_panel(&mut self, url: &str) {
+        let space = &mut self.spaces[self.active_space_idx];
+        let id = match space.focused().map(|panel| panel.id.clone()) {
+            Some(target) => space.split_panel(&target, url, Axis::Row),
+            None => Some(space.add_panel(url)),
+        };
+        if let Some(id) = id {
+            space.focus_panel(&id);
+        }
+        self.open_focused_panel();
+        self.save_spaces();
+    }
+
+    /// Moves through the shown panel's history; without panels the engine handles it.
+    fn step_history(&mut self, back: bool) {
+        let panel = match &self.open_essential {
+            Some(id) => self.essentials.get_mut(id),
+            None => self.spaces[self.active_space_idx].focused_mut(),
+        };
+        let Some(panel) = panel else {
+            self.engine.dispatch(if back { EngineEvent::Back } else { EngineEvent::Forward });
+            return;
+        };
+        let url = if back { panel.go_back() } else { panel.go_forward() };
+        if let Some(url) = url.map(str::to_string) {
+            let [x, y] = panel.scroll_offset;
+            self.url_input = url.clone();
+            self.next_transition = Some(Transition::BackForward);
+            self.engine.dispatch(EngineEvent::LoadUrl(url));
+            self.engine.dispatch(EngineEvent::ScrollTo { x, y });
+        }
+    }
+
+    /// Records what the engine reported in the shown panel.
+    fn apply_engine_notifications(&mut self) {
+        let notifications = self.engine.take_notifications();
+        if notifications.is_empty() {
+            return;
+        }
+        self.record_history(&notifications);
+        let panel = match &self.open_essential {
+            Some(id) => self.essentials.get_mut(id),
+            None => self.spaces[self.active_space_idx].focused_mut(),
+        };
+        let Some(panel) = panel else {
+            return;
+        };
+        let mut navigated = false;
+        let mut bookmarks_changed = false;
+        let mut elsewhere = Vec::new();
+        let mut failed = None;
+        for notification in notifications {
+            match notification {
+                EngineNotification::UrlChanged(url) => {
+                    panel.record_navigation(&url);
+                    navigated = true;
+                }
+                EngineNotification::TitleChanged(title) => {
+                    panel.set_title(&title);
+                    navigated = true;
+                }
+                EngineNotification::FaviconChanged(url) => {
+                    if self.bookmarks.set_favicon(&panel.url, &url) {
+                        bookmarks_changed = true;
+                    }
+                    panel.favicon = Some(url);
+                }
+                EngineNotification::ScrollChanged { x, y } => panel.scroll_offset = [x, y],
+                EngineNotification::ZoomChanged(zoom) => panel.zoom = zoom,
+                EngineNotification::MemoryUsage(bytes) => {
+                    if let Some(shown) = &self.shown_panel {
+                        self.lifecycle.report_memory(shown, bytes);
+                    }
+                }
+                EngineNotification::OpenInNewPanel(url) => elsewhere.push(url),
+                EngineNotification::LoadFailed(url) => failed = Some(url),
+            }
+        }
+        if bookmarks_changed {
+            self.save_bookmarks();
+        }
+        // Links out of a pinned panel
+        for url in elsewhere {
+            self.navigate(&url);
+        }
+        // Offline, a page saved for later still opens from its snapshot
+        if let Some(url) = failed
+            && let Some(item) = self.reading_list.offline_copy(&url)
+        {
+            let id = item.id.clone();
+            self.show_snapshot(&id);
+        }
+        // Scrolling alone only goes to the session journal
+        if navigated {
+            self.save_spaces();
+        } else {
+            let state = self.spaces_state();
+            self.record_session(&state);
+        }
+    }
+
+    /// Records the pages the engine committed as visits, and their titles.
+    fn record_history(&mut self, notifications: &[EngineNotification]) {
+        let mut url = self.engine.get_url().to_string();
+        for notification in notifications {
+            let result = match notification {
+                EngineNotification::UrlChanged(committed) => {
+                    url = committed.clone();
+                    if std::mem::take(&mut self.restoring_panel) {
+                        continue;
+                    }
+                    let transition = self.next_transition.take().unwrap_or(Transition::Link);
+                    self.history.record_visit(committed, transition)
+                }
+                // The restore never commits; the next page is a visit again
+                EngineNotification::LoadFailed(_) => {
+                    self.restoring_panel = false;
+                    continue;
+                }
+                EngineNotification::TitleChanged(title) => self.history.set_title(&url, title),
+                _ => continue,
+            };
+            match result {
+                Ok(()) => self.history_page.invalidate(),
+                Err(e) => log::warn!("Could not record history for {}: {}", url, e),
+            }
+        }
+    }
+
+    fn spaces_state(&self) -> SpacesState {
+        SpacesState {
+            spaces: self.spaces.clone(),
+            active_space: self.active_space_idx,
+            essentials: self.essentials.clone(),
+        }
+    }
+
+    fn save_spaces(&mut self) {
+        let state = self.spaces_state();
+        if let Err(e) = self.spaces_store.save(&state) {
+            log::error!("Failed to save spaces to {}: {}", self.spaces_store.path().display(), e);
         }
+        self.record_session(&state);
+    }
+
+    fn save_bookmarks(&self) {
+        if let Err(e) = self.bookmarks_store.save(&self.bookmarks) {
+            log::error!("Failed to save bookmarks to {}: {}", self.bookmarks_store.path().display(), e);
+        }
+    }
+
+    /// Bookmarks the shown page, or edits its bookmark if it has one.
+    fn bookmark_current_page(&mut self) {
+        let url = self.engine.get_url().to_string();
+        if let Some(bookmark) = self.bookmarks.find_url(&url) {
+            self.bookmark_editor = Some(bookmarks_view::BookmarkEditor::open(bookmark));
+            return;
+        }
+        let shown = match &self.open_essential {
+            Some(id) => self.essentials.get(id),
+            None => self.spaces[self.active_space_idx].focused(),
+        };
+        let mut bookmark = Bookmark::new(shown.map_or(url.as_str(), |panel| panel.title.as_str()), &url);
+        bookmark.favicon = shown.and_then(|panel| panel.favicon.clone());
+        if let Err(e) = self.bookmarks.add(bookmarks::ROOT_ID, bookmark) {
+            log::error!("Failed to bookmark {}: {}", url, e);
+            return;
+        }
+        self.save_bookmarks();
+    }
+
+    fn save_reading_list(&self) {
+        if let Err(e) = self.reading_list_store.save(&self.reading_list) {
+            log::error!("Failed to save reading list to {}: {}", self.reading_list_store.path().display(), e);
+        }
+    }
+
+    /// Adds the shown page to the reading list, with a reader-mode snapshot if it has an article.
+    fn save_for_later(&mut self) {
+        let url = self.engine.get_url().to_string();
+        let shown = match &self.open_essential {
+            Some(id) => self.essentials.get(id),
+            None => self.spaces[self.active_space_idx].focused(),
+        };
+        let article = self.engine.document_html().and_then(|html| reader::extract(html, &url));
+        let title = article.as_ref().map(|article| article.title.clone()).or_else(|| shown.map(|panel| panel.title.clone())).unwrap_or_else(|| url.clone());
+        let item = self.reading_list.add(&url, &title);
+        match article {
+            Some(article) => match self.reading_list_store.save_snapshot(&item.id, &article) {
+                Ok(()) => {
+                    item.has_snapshot = true;
+                    item.word_count = article.word_count();
+                }
+                Err(e) => log::warn!("Could not save a snapshot of {}: {}", url, e),
+            },
+            None => log::info!("No article to keep offline on {}", url),
+        }
+        self.save_reading_list();
+    }
+
+    /// Opens a reading list item: its snapshot if asked for, else the page itself, which falls
+    /// back to the snapshot if it fails to load.
+    fn open_reading_item(&mut self, id: &str, snapshot: bool) {
+        let Some(item) = self.reading_list.get(id) else {
+            return;
+        };
+        let url = item.url.clone();
+        if !(item.has_snapshot && snapshot && self.show_snapshot(id)) {
+            self.url_input = url.clone();
+            self.navigate(&url);
+        }
+        if self.reading_list.set_read(id, true) {
+            self.save_reading_list();
+        }
+    }
+
+    /// Shows a reading list item's snapshot; returns `false` if it couldn't be loaded.
+    fn show_snapshot(&mut self, id: &str) -> bool {
+        match self.reading_list_store.load_snapshot(id) {
+            Ok(article) => {
+                self.reader_snapshot = Some(article);
+                self.url_input = reading_view::PAGE_URL.into();
+                self.navigate(reading_view::PAGE_URL);
+                true
+            }
+            Err(e) => {
+                log::warn!("Could not open the offline copy of {}: {}", id, e);
+                false
+            }
+        }
+    }
+
+    /// Carries out the bookmark file dialog; on failure it stays open with the error.
+    fn finish_bookmark_file(&mut self) {
+        let Some(dialog) = &mut self.bookmark_file else {
+            return;
+        };
+        let path = std::path::PathBuf::from(dialog.path.trim());
+        let result = if dialog.export {
+            std::fs::write(&path, bookmarks::netscape::export(&self.bookmarks)).map_err(bookmarks::BookmarkError::from)
+        } else {
+            bookmarks::import_file(&path, "Imported").map(|folder| {
+                self.bookmarks.import(folder);
+            })
+        };
+        match result {
+            Ok(()) => {
+                self.bookmark_file = None;
+                self.save_bookmarks();
+            }
+            Err(e) => {
+                log::warn!("Bookmarks file {}: {}", path.display(), e);
+                dialog.error = Some(e.to_string());
+            }
+        }
+    }
+
+    /// Journals the spaces without rewriting the spaces file, for frequent small changes.
+    fn record_session(&mut self, state: &SpacesState) {
+        if let Some(journal) = &mut self.journal
+            && let Err(e) = journal.record(state)
+        {
+            log::error!("Failed to journal session to {}: {}", journal.path().display(), e);
+        }
+    }
+
+    /// The crashed session was restored or turned down; it won't be offered again.
+    fn discard_previous_session(&self) {
+        if let Some(journal) = &self.journal
+            && let Err(e) = journal.discard_previous()
+        {
+            log::error!("Failed to remove the previous session journal: {}", e);
+        }
+    }
+
+    fn apply_omnibox_action(&mut self, kind: SuggestionKind, action: OmniboxAction) {
+        match action {
+            OmniboxAction::Navigate(url) => {
+                self.url_input = url.clone();
+                self.next_transition = Some(if kind == SuggestionKind::Keyword { Transition::Keyword } else { Transition::Typed });
+                self.navigate(&url);
+            }
+            OmniboxAction::SwitchToPanel { space, panel } => self.switch_to_panel(space.as_deref(), &panel),
+            OmniboxAction::Extension { extension_id, text } => {
+                self.extensions.omnibox_input_entered(&extension_id, &text);
+                self.url_input = self.engine.get_url().to_string();
+            }
+        }
+    }
+
+    /// Shows a panel of any space, or an essential when `space` is `None`.
+    fn switch_to_panel(&mut self, space: Option<&str>, panel: &str) {
+        let Some(space) = space else {
+            if self.essentials.get(panel).is_some() {
+                self.open_essential(panel);
+            }
+            return;
+        };
+        let Some(index) = self.spaces.iter().position(|candidate| candidate.id == space) else {
+            return;
+        };
+        self.active_space_idx = index;
+        self.spaces[index].focus_panel(panel);
+        self.open_focused_panel();
+        self.save_spaces();
+    }
+
+    /// Top-level navigation: skips link decorators and stops at known-dangerous sites.
+    fn navigate(&mut self, input: &str) {
+        // Superseded, if the panel being switched to hasn't loaded yet
+        self.restoring_panel = false;
+        let mut url = if let Some(url) = self.bookmarks.expand_keyword(input) {
+            self.next_transition = Some(Transition::Keyword);
+            url
+        } else if search::looks_like_url(input) {
+            search::fixup_url(input)
+        } else {
+            self.search_engine.search_url(input.trim())
+        };
+
+        if let Some(destination) = self.shield.debounce_navigation(&url) {
+            url = destination;
+            self.url_input = url.clone();
+        }
+
+        if let Ok(Decision::Dangerous(threat)) = self.shield.should_allow_request(&url, &url, ResourceType::Document)
+            && !self.engine.is_bypassed(&url)
+        {
+            self.engine.show_interstitial(&url, threat);
+            self.next_transition = None;
+            return;
+        }
+
+        // Pinned panels keep to their origin; anywhere else opens beside them
+        let shown = match &self.open_essential {
+            Some(id) => self.essentials.get(id),
+            None => self.spaces[self.active_space_idx].focused(),
+        };
+        if shown.is_some_and(|panel| !panel.allows_navigation(&url)) {
+            self.open_new_panel(&url);
+            // A visit like any other, not a panel shown again
+            self.restoring_panel = false;
+            return;
+        }
+
+        self.engine.dispatch(EngineEvent::LoadUrl(url));
     }
 }
 
@@ -80,6 +787,28 @@ impl eframe::App for WaveApp {
         //     }
         // });
 
+        // Keep the focused panel in step with the page
+        self.apply_engine_notifications();
+        self.safe_browsing_updater.poll(ctx, &self.shield);
+
+        // The active space's colors
+        let theme = self.active_theme();
+        if self.applied_theme != Some((theme.flavor, theme.accent)) {
+            ctx.set_visuals(visuals_for(&theme));
+            self.applied_theme = Some((theme.flavor, theme.accent));
+        }
+        let accent = color(theme.flavor.accent(theme.accent));
+
+        if ctx.input_mut(|i| i.consume_key(egui::Modifiers::COMMAND | egui::Modifiers::SHIFT, egui::Key::T)) {
+            self.reopen_closed(None);
+        }
+
+        // Put unused panels to sleep
+        for transition in self.lifecycle.tick(&self.spaces, &self.essentials, self.shown_panel.as_deref()) {
+            log::info!("Panel {}: {:?} -> {:?}", transition.panel_id, transition.from, transition.to);
+            // self.constellation.send(ConstellationMsg::SetWebViewThrottled / CloseWebView(..))
+        }
+
         // Sidebar
         egui::SidePanel::left("wave_sidebar")
             .resizable(true)
@@ -88,6 +817,47 @@ impl eframe::App for WaveApp {
                 ui.add_space(20.0);
                 ui.heading("Wave");
                 ui.separator();
+
+                // Essentials: the same panels in every space
+                let mut essential_clicked = None;
+                let mut essential_to_space = None;
+                ui.horizontal_wrapped(|ui| {
+                    for panel in self.essentials.iter() {
+                        let open = self.open_essential.as_deref() == Some(panel.id.as_str());
+                        let response = ui.selectable_label(open, panel_initial(panel)).on_hover_text(&panel.title);
+                        if response.clicked() {
+                            essential_clicked = Some(panel.id.clone());
+                        }
+                        response.context_menu(|ui| {
+                            if ui.button("Move to this space").clicked() {
+                                essential_to_space = Some(panel.id.clone());
+                                ui.close_menu();
+                            }
+                        });
+                    }
+                    if ui.small_button("+").on_hover_text("Add this page to the essentials").clicked() {
+                        let url = self.engine.get_url().to_string();
+                        let id = self.essentials.add(&url);
+                        essential_clicked = Some(id);
+                    }
+                });
+                if let Some(id) = essential_clicked {
+                    self.open_essential(&id);
+                    self.save_spaces();
+                }
+                if let Some(id) = essential_to_space
+                    && let Some(panel) = self.essentials.remove(&id)
+                {
+                    let space = &mut self.spaces[self.active_space_idx];
+                    if space.insert_panel(panel) {
+                        space.focus_panel(&id);
+                    }
+                    if self.open_essential.as_deref() == Some(id.as_str()) {
+                        self.open_focused_panel();
+                    }
+                    self.save_spaces();
+                }
+                ui.separator();
                 ui.label("OPEN PANELS");
                 if ui.button(" 🏠  Home ").clicked() {
                     self.engine.dispatch(EngineEvent::LoadUrl("about:home".into()));
@@ -101,37 +871,267 @@ impl eframe::App for WaveApp {
                 
                 // Dynamic Spaces List
                 let mut space_clicked_idx = None;
+                let mut space_closed_idx = None;
+                let mut space_exported_idx = None;
+                let mut space_settings_idx = None;
+                let mut never_sleep_toggled = None;
+                let mut pin_toggled = None;
+                let mut made_essential = None;
+                let mut panel_clicked = None;
+                let mut close_all_idx = None;
+                let can_close = self.spaces.len() > 1;
                 for (idx, space) in self.spaces.iter().enumerate() {
                     let is_active = idx == self.active_space_idx;
+                    let icon = space.identity.icon.as_deref().unwrap_or("📁");
                     let label = if is_active {
-                        format!("👉 {}", space.name)
+                        egui::RichText::new(format!("👉 {} {}", icon, space.name)).color(accent)
                     } else {
-                        format!("  📁 {}", space.name)
+                        egui::RichText::new(format!("  {} {}", icon, space.name))
                     };
                     
-                    if ui.selectable_label(is_active, label).clicked() {
+                    let response = ui.selectable_label(is_active, label);
+                    if response.clicked() {
                         space_clicked_idx = Some(idx);
                     }
+                    response.context_menu(|ui| {
+                        if ui.button("Space settings…").clicked() {
+                            space_settings_idx = Some(idx);
+                            ui.close_menu();
+                        }
+                        if ui.button("Export space…").clicked() {
+                            space_exported_idx = Some(idx);
+                            ui.close_menu();
+                        }
+                        if ui.button("Close all panels").on_hover_text("Pinned panels stay").clicked() {
+                            close_all_idx = Some(idx);
+                            ui.close_menu();
+                        }
+                        if can_close && ui.button("Close space").clicked() {
+                            space_closed_idx = Some(idx);
+                            ui.close_menu();
+                        }
+                    });
                     
                     if is_active {
-                        // Show panels in this space
-                        for panel in &space.panels {
-                            ui.label(format!("    📄 {}", panel.title));
+                        // Show panels in this space: pinned ones as a strip, the rest as a list
+                        let mut panel_menu = |ui: &mut egui::Ui, panel: &Panel| {
+                            let mut pinned = panel.pinned;
+                            if ui.checkbox(&mut pinned, "Pinned").changed() {
+                                pin_toggled = Some(panel.id.clone());
+                                ui.close_menu();
+                            }
+                            let mut never_sleep = panel.never_sleep;
+                            if ui.checkbox(&mut never_sleep, "Never sleep").changed() {
+                                never_sleep_toggled = Some(panel.id.clone());
+                                ui.close_menu();
+                            }
+                            if ui.button("Add to essentials").clicked() {
+                                made_essential = Some(panel.id.clone());
+                                ui.close_menu();
+                            }
+                        };
+                        let focused = space.focused().map(|panel| panel.id.as_str()).filter(|_| self.open_essential.is_none());
+                        ui.horizontal_wrapped(|ui| {
+                            ui.add_space(16.0);
+                            for panel in space.panels.iter().filter(|panel| panel.pinned) {
+                                let response = ui.selectable_label(focused == Some(panel.id.as_str()), panel_initial(panel)).on_hover_text(&panel.title);
+                                if response.clicked() {
+                                    panel_clicked = Some(panel.id.clone());
+                                }
+                                response.context_menu(|ui| panel_menu(ui, panel));
+                            }
+                        });
+                        for panel in space.panels.iter().filter(|panel| !panel.pinned) {
+                            let icon = match self.lifecycle.state(&panel.id) {
+                                PanelState::Active => "📄",
+                                PanelState::Frozen => "❄",
+                                PanelState::Discarded => "💤",
+                            };
+                            let response = ui.selectable_label(focused == Some(panel.id.as_str()), format!("    {} {}", icon, panel.title));
+                            if response.clicked() {
+                                panel_clicked = Some(panel.id.clone());
+                            }
+                            response.context_menu(|ui| panel_menu(ui, panel));
                         }
                     }
                 }
 
                 if let Some(idx) = space_clicked_idx {
                     self.active_space_idx = idx;
+                    self.open_focused_panel();
+                    self.save_spaces();
+                }
+                if let Some(idx) = space_closed_idx {
+                    self.close_space(idx);
+                }
+                if let Some(idx) = space_settings_idx {
+                    let space = &self.spaces[idx];
+                    self.space_settings = Some(SpaceSettingsDialog {
+                        space_id: space.id.clone(),
+                        name: space.name.clone(),
+                        icon: space.identity.icon.clone().unwrap_or_default(),
+                        identity: space.identity.clone(),
+                    });
+                }
+                if let Some(idx) = space_exported_idx {
+                    self.space_file = Some(SpaceFileDialog {
+                        export: Some(self.spaces[idx].id.clone()),
+                        path: format!("{}.{}", self.spaces[idx].name, template::FILE_EXTENSION),
+                        on_conflict: OnConflict::KeepBoth,
+                        error: None,
+                    });
+                }
+                if let Some(id) = never_sleep_toggled
+                    && let Some(panel) = self.spaces[self.active_space_idx].panel_mut(&id)
+                {
+                    panel.never_sleep = !panel.never_sleep;
+                    self.save_spaces();
+                }
+                if let Some(id) = pin_toggled
+                    && let Some(panel) = self.spaces[self.active_space_idx].panel_mut(&id)
+                {
+                    panel.pinned = !panel.pinned;
+                    if self.shown_panel.as_deref() == Some(id.as_str()) {
+                        self.engine.dispatch(EngineEvent::LockOrigin(panel.locked_origin()));
+                    }
+                    self.save_spaces();
+                }
+                if let Some(id) = panel_clicked {
+                    self.spaces[self.active_space_idx].focus_panel(&id);
+                    self.open_focused_panel();
+                    self.save_spaces();
+                }
+                if let Some(id) = made_essential
+                    && let Some(panel) = self.spaces[self.active_space_idx].remove_panel(&id)
+                {
+                    self.essentials.adopt(panel);
+                    // The engine already shows it; keep it on screen
+                    if self.shown_panel.as_deref() == Some(id.as_str()) {
+                        self.open_essential(&id);
+                    }
+                    self.save_spaces();
+                }
+                if let Some(idx) = close_all_idx {
+                    let space = &mut self.spaces[idx];
+                    for closed in space.close_all() {
+                        self.recently_closed.push(ClosedItem::Panel(closed));
+                    }
+                    if idx == self.active_space_idx && self.open_essential.is_none() {
+                        self.open_focused_panel();
+                    }
+                    self.save_spaces();
+                }
+
+                if !self.recently_closed.is_empty() {
+                    ui.add_space(20.0);
+                    ui.label("RECENTLY CLOSED");
+                    let mut reopen_idx = None;
+                    for (idx, item) in self.recently_closed.iter().enumerate() {
+                        let icon = match item {
+                            ClosedItem::Panel(_) => "📄",
+                            ClosedItem::Space { .. } => "📁",
+                        };
+                        if ui.small_button(format!("  ↺ {} {}", icon, item.title())).on_hover_text("Reopen").clicked() {
+                            reopen_idx = Some(idx);
+                        }
+                    }
+                    if let Some(idx) = reopen_idx {
+                        self.reopen_closed(Some(idx));
+                    }
                 }
 
                 ui.add_space(20.0);
                 if ui.button(" + New Space ").clicked() {
                     self.spaces.push(Space::new("New Space"));
+                    self.save_spaces();
+                }
+                ui.menu_button(" ⧉ From template ", |ui| {
+                    for template in template::builtin() {
+                        if ui.button(&template.name).clicked() {
+                            self.add_space_from(&template, OnConflict::KeepBoth);
+                            ui.close_menu();
+                        }
+                    }
+                });
+                if ui.button(" ⤓ Import Space… ").clicked() {
+                    self.space_file = Some(SpaceFileDialog {
+                        export: None,
+                        path: String::new(),
+                        on_conflict: OnConflict::KeepBoth,
+                        error: None,
+                    });
                 }
                 
+                ui.add_space(20.0);
+                ui.horizontal(|ui| {
+                    ui.label("BOOKMARKS");
+                    if ui.small_button("⤓").on_hover_text("Import bookmarks (HTML, Firefox or Chromium JSON)").clicked() {
+                        self.bookmark_file = Some(BookmarkFileDialog { export: false, path: String::new(), error: None });
+                    }
+                    if ui.small_button("⤒").on_hover_text("Export bookmarks as HTML").clicked() {
+                        self.bookmark_file = Some(BookmarkFileDialog { export: true, path: "bookmarks.html".into(), error: None });
+                    }
+                });
+                egui::ScrollArea::vertical().id_source("bookmarks").max_height(240.0).show(ui, |ui| {
+                    match bookmarks_view::show_tree(ui, &self.bookmarks) {
+                        Some(bookmarks_view::BookmarkAction::Open(url)) => {
+                            self.url_input = url.clone();
+                            self.next_transition = Some(Transition::Bookmark);
+                            self.navigate(&url);
+                        }
+                        Some(bookmarks_view::BookmarkAction::Edit(id)) => {
+                            self.bookmark_editor = self.bookmarks.bookmark(&id).map(bookmarks_view::BookmarkEditor::open);
+                        }
+                        Some(bookmarks_view::BookmarkAction::Delete(id)) => {
+                            self.bookmarks.remove(&id);
+                            self.save_bookmarks();
+                        }
+                        None => {}
+                    }
+                });
+
+                if !self.reading_list.is_empty() {
+                    ui.add_space(20.0);
+                    ui.label(format!("READING LIST ({} unread)", self.reading_list.unread_count()));
+                    let action = egui::ScrollArea::vertical()
+                        .id_source("reading_list")
+                        .max_height(200.0)
+                        .show(ui, |ui| reading_view::show_list(ui, &self.reading_list))
+                        .inner;
+                    match action {
+                        Some(reading_view::ReadingAction::Open(id)) => self.open_reading_item(&id, false),
+                        Some(reading_view::ReadingAction::OpenSnapshot(id)) => self.open_reading_item(&id, true),
+                        Some(reading_view::ReadingAction::SetRead(id, read)) => {
+                            self.reading_list.set_read(&id, read);
+                            self.save_reading_list();
+                        }
+                        Some(reading_view::ReadingAction::Remove(id)) => {
+                            self.reading_list.remove(&id);
+                            if let Err(e) = self.reading_list_store.remove_snapshot(&id) {
+                                log::warn!("Could not delete the offline copy: {}", e);
+                            }
+                            self.save_reading_list();
+                        }
+                        None => {}
+                    }
+                }
+
                 ui.with_layout(egui::Layout::bottom_up(egui::Align::LEFT), |ui| {
-                    ui.label(format!("Shield: {}", if true { "Active" } else { "Off" }));
+                    if ui.button(format!("Shield: {}", if true { "Active" } else { "Off" })).clicked() {
+                        self.url_input = rules_editor::PAGE_URL.into();
+                        self.navigate(rules_editor::PAGE_URL);
+                    }
+                    // Takes effect from the next page the site loads
+                    if let Some(host) = self.engine.current_host() {
+                        let mut handled = self.shield.cookie_banner_handling(&host);
+                        if ui.checkbox(&mut handled, "Reject cookie banners").on_hover_text(format!("On {} and its subdomains", host)).changed() {
+                            self.shield.set_cookie_banner_handling(&host, handled);
+                        }
+                    }
+                    if ui.button("🕓 History").clicked() {
+                        self.url_input = history_view::PAGE_URL.into();
+                        self.navigate(history_view::PAGE_URL);
+                    }
                     ui.separator();
                 });
             });
@@ -140,82 +1140,456 @@ impl eframe::App for WaveApp {
         egui::TopBottomPanel::top("wave_topbar").show(ctx, |ui| {
             ui.horizontal(|ui| {
                 if ui.button(" < ").clicked() {
-                    self.engine.dispatch(EngineEvent::Back);
+                    self.step_history(true);
                 }
                 if ui.button(" > ").clicked() {
-                    self.engine.dispatch(EngineEvent::Forward);
+                    self.step_history(false);
                 }
                 if ui.button(" ↻ ").clicked() {
                     self.engine.dispatch(EngineEvent::Refresh);
                 }
+                if ui.button(" ◫ ").on_hover_text("Split right").clicked() {
+                    self.split_active_panel(Axis::Row);
+                }
+                if ui.button(" ⊟ ").on_hover_text("Split down").clicked() {
+                    self.split_active_panel(Axis::Column);
+                }
+                if matches!(self.spaces[self.active_space_idx].layout, Layout::Split { .. }) {
+                    if ui.button(" ✕ ").on_hover_text("Close panel").clicked() {
+                        self.close_active_panel();
+                    }
+                    if ui.button(" ⚖ ").on_hover_text("Equal sizes").clicked() {
+                        self.spaces[self.active_space_idx].layout.rebalance();
+                        self.save_spaces();
+                    }
+                }
+                let picking = self.engine.element_picker().is_some();
+                if ui.selectable_label(picking, " 🎯 ").on_hover_text("Hide an element on this page").clicked() {
+                    self.engine.dispatch(if picking { EngineEvent::StopElementPicker } else { EngineEvent::StartElementPicker });
+                }
+                let saved = self.reading_list.find_url(self.engine.get_url()).is_some();
+                if ui.selectable_label(saved, " 📖 ").on_hover_text("Save to reading list").clicked() {
+                    self.save_for_later();
+                }
+                let bookmarked = self.bookmarks.find_url(self.engine.get_url()).is_some();
+                if ui.selectable_label(bookmarked, if bookmarked { " ★ " } else { " ☆ " }).on_hover_text("Bookmark this page").clicked() {
+                    self.bookmark_current_page();
+                }
                 
                 // URL Bar Logic
-                let response = ui.add(egui::TextEdit::singleline(&mut self.url_input).desired_width(f32::INFINITY));
-                if response.lost_focus() && ctx.input(|i| i.key_pressed(egui::Key::Enter)) {
-                    self.engine.dispatch(EngineEvent::LoadUrl(self.url_input.clone()));
+                let typed = TypedProvider { engine: &self.search_engine };
+                let bookmarks = BookmarksProvider { bookmarks: &self.bookmarks };
+                let panels = PanelsProvider { spaces: &self.spaces, essentials: &self.essentials, shown: self.shown_panel.as_deref() };
+                let history = HistoryProvider { history: &self.history, limit: 20 };
+                let about = AboutPagesProvider { pages: ABOUT_PAGES };
+                let providers: [&dyn Provider; 6] = [&typed, &bookmarks, &panels, &history, &about, &self.extensions];
+                if let Some((kind, action)) = self.omnibox.show(ui, &mut self.url_input, &self.search_engine, &providers) {
+                    self.apply_omnibox_action(kind, action);
                 }
             });
         });
 
+        // A run that crashed: offer to bring back what it had open
+        if let Some(closed) = self.previous_session.as_ref().map(|previous| previous.recently_closed.len()) {
+            let mut restore = None;
+            let mut dismiss = false;
+            egui::Window::new("Restore previous session?")
+                .collapsible(false)
+                .resizable(false)
+                .anchor(egui::Align2::CENTER_TOP, [0.0, 60.0])
+                .show(ctx, |ui| {
+                    ui.label("Wave didn't shut down properly. Restore the spaces and panels you had open?");
+                    ui.horizontal(|ui| {
+                        if ui.button(" Restore ").clicked() {
+                            restore = Some(false);
+                        }
+                        if closed > 0 && ui.button(format!(" Restore with {} closed panels ", closed)).clicked() {
+                            restore = Some(true);
+                        }
+                        if ui.button(" Start fresh ").clicked() {
+                            dismiss = true;
+                        }
+                    });
+                });
+            if let Some(reopen_closed) = restore
+                && let Some(previous) = self.previous_session.take()
+            {
+                let state = previous.restore(reopen_closed);
+                self.spaces = state.spaces;
+                self.active_space_idx = state.active_space;
+                self.essentials = state.essentials;
+                self.open_focused_panel();
+                self.save_spaces();
+                self.discard_previous_session();
+            } else if dismiss {
+                self.previous_session = None;
+                self.discard_previous_session();
+            }
+        }
+
+        // Space settings: name, icon and colors
+        if let Some(dialog) = &mut self.space_settings {
+            let mut save = false;
+            let mut cancel = false;
+            egui::Window::new("Space settings")
+                .collapsible(false)
+                .resizable(false)
+                .anchor(egui::Align2::CENTER_TOP, [0.0, 60.0])
+                .show(ctx, |ui| {
+                    egui::Grid::new("space_settings").num_columns(2).show(ui, |ui| {
+                        ui.label("Name");
+                        ui.text_edit_singleline(&mut dialog.name);
+                        ui.end_row();
+
+                        ui.label("Icon");
+                        ui.add(egui::TextEdit::singleline(&mut dialog.icon).hint_text("📁").desired_width(60.0));
+                        ui.end_row();
+
+                        ui.label("Accent");
+                        egui::ComboBox::from_id_source("space_accent")
+                            .selected_text(dialog.identity.accent.map_or("Browser theme", |accent| accent.name()))
+                            .show_ui(ui, |ui| {
+                                ui.selectable_value(&mut dialog.identity.accent, None, "Browser theme");
+                                for accent in AccentColor::ALL {
+                                    ui.selectable_value(&mut dialog.identity.accent, Some(accent), accent.name());
+                                }
+                            });
+                        ui.end_row();
+
+                        ui.label("Flavor");
+                        egui::ComboBox::from_id_source("space_flavor")
+                            .selected_text(dialog.identity.flavor.map_or("Browser theme", |flavor| flavor.name()))
+                            .show_ui(ui, |ui| {
+                                ui.selectable_value(&mut dialog.identity.flavor, None, "Browser theme");
+                                for flavor in CatppuccinFlavor::ALL {
+                                    ui.selectable_value(&mut dialog.identity.flavor, Some(flavor), flavor.name());
+                                }
+                            });
+                        ui.end_row();
+                    });
+
+                    // Preview of the accent on the flavor
+                    let preview = dialog.identity.theme(&self.theme);
+                    let (rect, _) = ui.allocate_exact_size(egui::vec2(ui.available_width(), 24.0), egui::Sense::hover());
+                    ui.painter().rect_filled(rect, 4.0, color(preview.flavor.base()));
+                    ui.painter().text(
+                        rect.center(),
+                        egui::Align2::CENTER_CENTER,
+                        format!("{} {}", dialog.icon.trim(), dialog.name.trim()),
+                        egui::FontId::proportional(14.0),
+                        color(preview.flavor.accent(preview.accent)),
+                    );
+
+                    ui.horizontal(|ui| {
+                        save = ui.button(" Save ").clicked();
+                        cancel = ui.button(" Cancel ").clicked();
+                    });
+                });
+            if save {
+                self.save_space_settings();
+            } else if cancel {
+                self.space_settings = None;
+            }
+        }
+
+        // Bookmark editing and import / export
+        if let Some(editor) = &mut self.bookmark_editor {
+            match editor.show(ctx, &mut self.bookmarks) {
+                bookmarks_view::EditorOutcome::Open => {}
+                bookmarks_view::EditorOutcome::Changed => {
+                    self.bookmark_editor = None;
+                    self.save_bookmarks();
+                }
+                bookmarks_view::EditorOutcome::Cancelled => self.bookmark_editor = None,
+            }
+        }
+        if let Some(dialog) = &mut self.bookmark_file {
+            let mut finish = false;
+            let mut cancel = false;
+            egui::Window::new(if dialog.export { "Export bookmarks" } else { "Import bookmarks" })
+                .collapsible(false)
+                .resizable(false)
+                .anchor(egui::Align2::CENTER_TOP, [0.0, 60.0])
+                .show(ctx, |ui| {
+                    ui.label("File:");
+                    let response = ui.add(egui::TextEdit::singleline(&mut dialog.path).desired_width(360.0));
+                    finish = response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                    if !dialog.export {
+                        ui.label(egui::RichText::new("Bookmarks go into a new \"Imported\" folder.").weak());
+                    }
+                    if let Some(error) = &dialog.error {
+                        ui.colored_label(egui::Color32::from_rgb(243, 139, 168), error);
+                    }
+                    ui.horizontal(|ui| {
+                        finish |= ui.button(if dialog.export { " Export " } else { " Import " }).clicked();
+                        cancel = ui.button(" Cancel ").clicked();
+                    });
+                });
+            if cancel {
+                self.bookmark_file = None;
+            } else if finish {
+                self.finish_bookmark_file();
+            }
+        }
+
+        // Space file export / import
+        if let Some(dialog) = &mut self.space_file {
+            let title = match &dialog.export {
+                Some(id) => {
+                    let name = self.spaces.iter().find(|space| &space.id == id).map_or("space", |space| space.name.as_str());
+                    format!("Export “{}”", name)
+                }
+                None => "Import space".to_string(),
+            };
+            let mut finish = false;
+            let mut cancel = false;
+            egui::Window::new(title)
+                .collapsible(false)
+                .resizable(false)
+                .anchor(egui::Align2::CENTER_TOP, [0.0, 60.0])
+                .show(ctx, |ui| {
+                    ui.label("File:");
+                    let response = ui.add(egui::TextEdit::singleline(&mut dialog.path).desired_width(360.0));
+                    finish = response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                    if dialog.export.is_none() {
+                        ui.label("If a space with the same name is open:");
+                        ui.radio_value(&mut dialog.on_conflict, OnConflict::KeepBoth, "Keep both");
+                        ui.radio_value(&mut dialog.on_conflict, OnConflict::Replace, "Replace it");
+                    }
+                    if let Some(error) = &dialog.error {
+                        ui.colored_label(egui::Color32::from_rgb(243, 139, 168), error);
+                    }
+                    ui.horizontal(|ui| {
+                        let action = if dialog.export.is_some() { " Export " } else { " Import " };
+                        finish |= ui.button(action).clicked();
+                        cancel = ui.button(" Cancel ").clicked();
+                    });
+                });
+            if cancel {
+                self.space_file = None;
+            } else if finish {
+                self.finish_space_file();
+            }
+        }
+
+        // Storage Access API prompt
+        if let Some(prompt) = self.engine.storage_access_prompt().cloned() {
+            egui::Window::new("Allow cookies?")
+                .collapsible(false)
+                .resizable(false)
+                .anchor(egui::Align2::CENTER_TOP, [0.0, 60.0])
+                .show(ctx, |ui| {
+                    ui.label(format!(
+                        "{} wants to use its cookies while you visit {}.",
+                        prompt.embedded_url, prompt.top_level_url
+                    ));
+                    ui.label(egui::RichText::new("This lets it recognize you across sites.").weak());
+                    ui.horizontal(|ui| {
+                        if ui.button(" Allow ").clicked() {
+                            self.engine.dispatch(EngineEvent::StorageAccessResponse { grant: true });
+                        }
+                        if ui.button(" Don't allow ").clicked() {
+                            self.engine.dispatch(EngineEvent::StorageAccessResponse { grant: false });
+                        }
+                    });
+                });
+        }
+
+        // Element picker: adjust the picked element's selector and save it as a rule
+        if self.engine.element_picker().is_some() && ctx.input(|i| i.key_pressed(egui::Key::Escape)) {
+            self.engine.dispatch(EngineEvent::StopElementPicker);
+        }
+        if let Some(picker) = self.engine.element_picker().filter(|picker| picker.is_picked()) {
+            let candidates = picker.candidates().to_vec();
+            let mut specificity = picker.specificity();
+            egui::Window::new("Hide element")
+                .collapsible(false)
+                .resizable(false)
+                .anchor(egui::Align2::RIGHT_BOTTOM, [-20.0, -20.0])
+                .show(ctx, |ui| {
+                    ui.label("Broader rules hide more of the page; the preview shows what would be hidden.");
+                    if candidates.len() > 1 {
+                        ui.add(egui::Slider::new(&mut specificity, 0..=candidates.len() - 1).show_value(false).text("Specificity"));
+                    }
+                    ui.monospace(candidates.get(specificity).map(String::as_str).unwrap_or_default());
+                    ui.horizontal(|ui| {
+                        if ui.button(" Create rule ").clicked()
+                            && let Some(selector) = candidates.get(specificity)
+                        {
+                            if let Some(host) = self.engine.current_host() {
+                                let site = host.strip_prefix("www.").unwrap_or(&host).to_string();
+                                self.rules_editor.add_site_rule(&site, &format!("##{}", selector), &self.shield);
+                            }
+                            self.engine.dispatch(EngineEvent::StopElementPicker);
+                        }
+                        if ui.button(" Pick again ").clicked() {
+                            self.engine.repick_element();
+                        }
+                        if ui.button(" Cancel ").clicked() {
+                            self.engine.dispatch(EngineEvent::StopElementPicker);
+                        }
+                    });
+                });
+            if self.engine.element_picker().is_some_and(|picker| picker.specificity() != specificity) {
+                self.engine.set_picker_specificity(specificity);
+            }
+        }
+
         // Central Content
         egui::CentralPanel::default().show(ctx, |ui| {
-            let available_size = ui.available_size();
-            let (w, h) = (available_size.x as i32, available_size.y as i32);
+            if let Some(interstitial) = self.engine.interstitial().cloned() {
+                ui.vertical_centered(|ui| {
+                    ui.add_space(ui.available_height() / 4.0);
+                    ui.heading(egui::RichText::new("⚠ Dangerous site ahead").color(egui::Color32::from_rgb(243, 139, 168)));
+                    ui.add_space(10.0);
+                    ui.label(interstitial.threat.to_string());
+                    ui.label(egui::RichText::new(&interstitial.url).weak());
+                    ui.add_space(20.0);
+                    if ui.button(" Go back to safety ").clicked() {
+                        self.engine.dispatch(EngineEvent::InterstitialGoBack);
+                        self.url_input = self.engine.get_url().to_string();
+                    }
+                    if ui.small_button("Proceed anyway (unsafe)").clicked() {
+                        self.engine.dispatch(EngineEvent::InterstitialProceed);
+                    }
+                });
+                return;
+            }
 
-            if let Some(renderer) = &mut self.renderer {
-                if renderer.width != w || renderer.height != h {
-                    renderer.resize(w, h);
-                    self.engine.dispatch(EngineEvent::Resize { width: w as u32, height: h as u32 });
+            if self.engine.get_url() == rules_editor::PAGE_URL {
+                if let Some(site) = self.rules_editor.show(ui, &self.shield) {
+                    self.shield.set_cookie_banner_handling(&site, true);
                 }
+                return;
+            }
+            if self.engine.get_url() == reading_view::PAGE_URL {
+                match &self.reader_snapshot {
+                    Some(article) => {
+                        if let Some(url) = reading_view::show_article(ui, article) {
+                            self.url_input = url.clone();
+                            self.navigate(&url);
+                        }
+                    }
+                    None => {
+                        ui.label("Open an offline copy from the reading list.");
+                    }
+                }
+                return;
+            }
+            if self.engine.get_url() == history_view::PAGE_URL {
+                let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_secs());
+                if let Some(url) = self.history_page.show(ui, &mut self.history, now) {
+                    self.url_input = url.clone();
+                    self.navigate(&url);
+                }
+                return;
+            }
 
-                // EXECUTE ENGINE PAINT
-                // 1. Prepare offscreen buffer
-                renderer.begin_paint();
-                
-                // 2. Tell Wave Engine (Servo) to draw into the current context
-                let time = ctx.input(|i| i.time);
-                self.engine.paint(time);
-                
-                // 3. Finish up
-                renderer.end_paint();
+            let time = ctx.input(|i| i.time);
+            let rect = ui.max_rect();
+            let space = &self.spaces[self.active_space_idx];
+            if space.layout == Layout::Empty || self.open_essential.is_some() {
+                self.show_web_view(ui, rect, time);
+                return;
+            }
 
-                let rect = ui.max_rect();
-                
-                // Capture clicks on the web view
-                let response = ui.interact(rect, ui.id(), egui::Sense::click());
+            let layout = space.layout.clone();
+            let panels = space.panels.clone();
+            let focused = space.focused().map(|panel| panel.id.clone());
+
+            let mut focus = None;
+            let actions = layout_view::show(ui, &layout, rect, &mut |ui, id, rect| {
+                if focused.as_deref() == Some(id) {
+                    self.show_web_view(ui, rect, time);
+                    return;
+                }
+                // Inactive panels show what they hold until focused; discarded ones, a picture of it
+                let panel = panels.iter().find(|panel| panel.id == id);
+                let response = ui.interact(rect, ui.id().with(("panel", id)), egui::Sense::click());
+                let state = self.lifecycle.state(id);
+                if state == PanelState::Discarded
+                    && let Some(thumbnail) = self.lifecycle.thumbnail(id)
+                {
+                    let texture = self.thumbnails.entry(id.to_string()).or_insert_with(|| {
+                        let image = egui::ColorImage::from_rgba_unmultiplied([thumbnail.width, thumbnail.height], &thumbnail.rgba);
+                        ui.ctx().load_texture(format!("thumbnail-{}", id), image, egui::TextureOptions::LINEAR)
+                    });
+                    let uv = egui::Rect::from_min_max(egui::pos2(0.0, 0.0), egui::pos2(1.0, 1.0));
+                    ui.painter().image(texture.id(), rect, uv, egui::Color32::from_gray(128));
+                }
+                ui.painter().rect_stroke(rect.shrink(1.0), 4.0, ui.visuals().widgets.noninteractive.bg_stroke);
+                let icon = match state {
+                    PanelState::Active => "",
+                    PanelState::Frozen => "❄ ",
+                    PanelState::Discarded => "💤 ",
+                };
+                ui.put(rect, egui::Label::new(egui::RichText::new(
+                    panel.map(|panel| format!("{}{}\n{}", icon, panel.title, panel.url)).unwrap_or_default(),
+                ).weak()));
                 if response.clicked() {
-                    if let Some(pos) = response.hover_pos() {
-                        self.engine.dispatch(EngineEvent::InputClick { 
-                            x: pos.x - rect.left(), 
-                            y: pos.y - rect.top() 
-                        });
-                    }
+                    focus = panel.cloned();
                 }
+            });
 
-                ui.painter().add(egui::PaintCallback {
-                    rect,
-                    callback: Arc::new(eframe::egui_glow::CallbackFn::new(move |_info, _painter| {
-                         // Real engine blitting would involve:
-                         // 1. Binding the renderer's texture
-                         // 2. Drawing a textured quad over 'rect'
-                    })),
-                });
-                
-                // Overlay info (simulating what the web page might be)
-                ui.centered_and_justified(|ui| {
-                     ui.label(format!("Browsing: {}\nEngine Surface: {}x{}", self.engine.get_url(), w, h));
-                });
+            for action in actions {
+                match action {
+                    layout_view::LayoutAction::DragDivider { path, divider, delta } => {
+                        self.spaces[self.active_space_idx].layout.drag_divider(&path, divider, delta);
+                    }
+                    layout_view::LayoutAction::DividerReleased => self.save_spaces(),
+                }
+            }
+            if let Some(panel) = focus {
+                self.spaces[self.active_space_idx].focus_panel(&panel.id);
+                self.open_focused_panel();
+                self.save_spaces();
             }
         });
         
         ctx.request_repaint();
     }
     
+    fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
+        self.save_spaces();
+        if let Some(journal) = &mut self.journal
+            && let Err(e) = journal.shutdown()
+        {
+            log::error!("Failed to close session journal: {}", e);
+        }
+    }
+
     fn clear_color(&self, _visuals: &egui::Visuals) -> [f32; 4] {
         egui::Rgba::TRANSPARENT.to_array() 
     }
 }
 
+/// A letter standing for a panel in the icon strips, until favicons are drawn.
+fn panel_initial(panel: &Panel) -> String {
+    let host = panel.url.split("://").nth(1).and_then(|rest| rest.split(['/', ':', '?', '#']).next()).filter(|host| !host.is_empty());
+    let name = host.map_or(panel.title.as_str(), |host| host.trim_start_matches("www."));
+    name.chars().next().map_or_else(|| "•".to_string(), |initial| format!(" {} ", initial.to_uppercase()))
+}
+
+/// A Catppuccin hex code as an egui color.
+fn color(hex: u32) -> egui::Color32 {
+    egui::Color32::from_rgb((hex >> 16) as u8, (hex >> 8) as u8, hex as u8)
+}
+
+/// egui's look for a theme: its flavor's background and text, its accent for selections and links.
+fn visuals_for(theme: &Theme) -> egui::Visuals {
+    let mut visuals = if theme.flavor.is_light() { egui::Visuals::light() } else { egui::Visuals::dark() };
+    let base = color(theme.flavor.base());
+    let accent = color(theme.flavor.accent(theme.accent));
+    visuals.panel_fill = base;
+    visuals.window_fill = base;
+    visuals.extreme_bg_color = color(theme.flavor.mantle());
+    visuals.override_text_color = Some(color(theme.flavor.text()));
+    visuals.hyperlink_color = accent;
+    visuals.selection.bg_fill = accent.linear_multiply(0.4);
+    visuals.selection.stroke.color = accent;
+    visuals
+}
+
 fn main() -> eframe::Result<()> {
     env_logger::init();
     
@@ -0,0 +1,203 @@
+use eframe::egui;
+use std::sync::mpsc;
+use std::time::Duration;
+
+use wave_core::omnibox::{self, OmniboxAction, Provider, Suggestion, SuggestionKind, providers::SearchSuggestionsProvider, search::{self, SearchEngine}};
+
+/// Rows in the dropdown.
+const MAX_SUGGESTIONS: usize = 8;
+const SUGGEST_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// The URL bar and its dropdown of suggestions.
+pub struct Omnibox {
+    suggestions: Vec<Suggestion>,
+    selected: usize,
+    open: bool,
+    // Where the dropdown was last drawn, so clicking it doesn't count as leaving the URL bar
+    popup_rect: Option<egui::Rect>,
+    fetcher: SuggestFetcher,
+}
+
+impl Omnibox {
+    pub fn new() -> Self {
+        Self {
+            suggestions: Vec::new(),
+            selected: 0,
+            open: false,
+            popup_rect: None,
+            fetcher: SuggestFetcher::new(),
+        }
+    }
+
+    /// Draws the URL bar; returns what the user chose, and the kind of suggestion it was. Enter
+    /// with nothing suggested navigates to `text` as typed.
+    pub fn show(&mut self, ui: &mut egui::Ui, text: &mut String, engine: &SearchEngine, providers: &[&dyn Provider]) -> Option<(SuggestionKind, OmniboxAction)> {
+        // Arrow keys move through the dropdown before the text field can take them
+        let id = ui.id().with("omnibox");
+        if self.open && ui.memory(|memory| memory.has_focus(id)) {
+            ui.input_mut(|i| {
+                if i.consume_key(egui::Modifiers::NONE, egui::Key::ArrowDown) {
+                    self.selected = (self.selected + 1).min(self.suggestions.len().saturating_sub(1));
+                }
+                if i.consume_key(egui::Modifiers::NONE, egui::Key::ArrowUp) {
+                    self.selected = self.selected.saturating_sub(1);
+                }
+            });
+        }
+
+        let response = ui.add(egui::TextEdit::singleline(text).id(id).desired_width(f32::INFINITY));
+        if response.changed() {
+            self.open = true;
+            self.selected = 0;
+            self.fetcher.request(ui.ctx(), engine, text);
+            self.refresh(text, engine, providers);
+        } else if self.fetcher.poll(ui.ctx()) && self.open {
+            self.refresh(text, engine, providers);
+            self.selected = self.selected.min(self.suggestions.len().saturating_sub(1));
+        }
+
+        let mut chosen = None;
+        if response.lost_focus() {
+            if ui.input(|i| i.key_pressed(egui::Key::Enter)) {
+                chosen = Some(match self.suggestions.get(self.selected).filter(|_| self.open) {
+                    Some(suggestion) => (suggestion.kind, suggestion.action.clone()),
+                    None => (SuggestionKind::Typed, OmniboxAction::Navigate(text.clone())),
+                });
+            } else if !self.popup_rect.is_some_and(|rect| ui.rect_contains_pointer(rect)) {
+                // Escape, or a click elsewhere
+                self.open = false;
+            }
+        }
+
+        self.popup_rect = None;
+        if self.open && chosen.is_none() && !self.suggestions.is_empty() {
+            let area = egui::Area::new(ui.id().with("omnibox_popup"))
+                .order(egui::Order::Foreground)
+                .fixed_pos(response.rect.left_bottom() + egui::vec2(0.0, 4.0))
+                .show(ui.ctx(), |ui| {
+                    egui::Frame::popup(ui.style()).show(ui, |ui| {
+                        ui.set_width(response.rect.width());
+                        for (index, suggestion) in self.suggestions.iter().enumerate() {
+                            let row = ui.selectable_label(index == self.selected, row_text(suggestion));
+                            if row.hovered() {
+                                self.selected = index;
+                            }
+                            if row.clicked() {
+                                chosen = Some((suggestion.kind, suggestion.action.clone()));
+                            }
+                        }
+                    });
+                });
+            self.popup_rect = Some(area.response.rect);
+        }
+
+        if chosen.is_some() {
+            self.open = false;
+            self.suggestions.clear();
+        }
+        chosen
+    }
+
+    fn refresh(&mut self, text: &str, engine: &SearchEngine, providers: &[&dyn Provider]) {
+        let completions = SearchSuggestionsProvider { engine, completions: &self.fetcher.completions };
+        let mut providers = providers.to_vec();
+        providers.push(&completions);
+        self.suggestions = omnibox::suggest(text, &providers, MAX_SUGGESTIONS);
+    }
+}
+
+fn row_text(suggestion: &Suggestion) -> egui::text::LayoutJob {
+    let icon = match suggestion.kind {
+        SuggestionKind::Typed => "↵",
+        SuggestionKind::History => "🕓",
+        SuggestionKind::Bookmark | SuggestionKind::Keyword => "★",
+        SuggestionKind::OpenPanel => "⇥",
+        SuggestionKind::Search => "🔍",
+        SuggestionKind::AboutPage => "ⓘ",
+        SuggestionKind::Extension => "🧩",
+    };
+    let mut job = egui::text::LayoutJob::default();
+    let normal = egui::TextFormat::default();
+    let weak = egui::TextFormat { color: egui::Color32::GRAY, ..Default::default() };
+    job.append(&format!("{}  {}", icon, suggestion.title), 0.0, normal);
+    job.append(&suggestion.detail, 12.0, weak.clone());
+    if matches!(suggestion.action, OmniboxAction::SwitchToPanel { .. }) {
+        job.append("Switch to panel", 12.0, weak);
+    }
+    job
+}
+
+/// Asks the search engine for completions in the background, one request at a time; what is
+/// typed meanwhile is asked next.
+struct SuggestFetcher {
+    sender: mpsc::Sender<Vec<String>>,
+    receiver: mpsc::Receiver<Vec<String>>,
+    in_flight: bool,
+    // Suggestion URL for the latest input, while a request is in flight
+    pending: Option<String>,
+    completions: Vec<String>,
+}
+
+impl SuggestFetcher {
+    fn new() -> Self {
+        let (sender, receiver) = mpsc::channel();
+        Self {
+            sender,
+            receiver,
+            in_flight: false,
+            pending: None,
+            completions: Vec::new(),
+        }
+    }
+
+    fn request(&mut self, ctx: &egui::Context, engine: &SearchEngine, text: &str) {
+        // Addresses aren't sent to the search engine
+        let text = text.trim();
+        let url = (!text.is_empty() && !search::looks_like_url(text)).then(|| engine.suggest_url(text)).flatten();
+        let Some(url) = url else {
+            self.pending = None;
+            self.completions.clear();
+            return;
+        };
+        if self.in_flight {
+            self.pending = Some(url);
+        } else {
+            self.spawn(ctx, url);
+        }
+    }
+
+    fn spawn(&mut self, ctx: &egui::Context, url: String) {
+        self.in_flight = true;
+        let sender = self.sender.clone();
+        let ctx = ctx.clone();
+        std::thread::spawn(move || {
+            let body = ureq::get(&url)
+                .timeout(SUGGEST_TIMEOUT)
+                .call()
+                .map_err(|e| e.to_string())
+                .and_then(|response| response.into_string().map_err(|e| e.to_string()));
+            let completions = match body {
+                Ok(body) => search::parse_suggestions(&body),
+                Err(e) => {
+                    log::debug!("Search suggestions from {} failed: {}", url, e);
+                    Vec::new()
+                }
+            };
+            let _ = sender.send(completions);
+            ctx.request_repaint();
+        });
+    }
+
+    /// Takes finished results; returns whether the completions changed.
+    fn poll(&mut self, ctx: &egui::Context) -> bool {
+        let Ok(completions) = self.receiver.try_recv() else {
+            return false;
+        };
+        self.in_flight = false;
+        self.completions = completions;
+        if let Some(url) = self.pending.take() {
+            self.spawn(ctx, url);
+        }
+        true
+    }
+}
@@ -0,0 +1,101 @@
+use eframe::egui;
+
+use wave_core::reading_list::{ReadingList, reader::{Article, Block}};
+
+/// Where a snapshot is shown.
+pub const PAGE_URL: &str = "about:reader";
+
+/// For "n min read".
+const WORDS_PER_MINUTE: usize = 230;
+
+/// Something the user did in the reading list; applied once drawing is done.
+pub enum ReadingAction {
+    /// The page, or its snapshot if it fails to load
+    Open(String),
+    OpenSnapshot(String),
+    SetRead(String, bool),
+    Remove(String),
+}
+
+/// Draws the reading list, unread items first in bold.
+pub fn show_list(ui: &mut egui::Ui, list: &ReadingList) -> Option<ReadingAction> {
+    let mut action = None;
+    for item in list.iter() {
+        let mut label = egui::RichText::new(format!("📖 {}", if item.title.is_empty() { &item.url } else { &item.title }));
+        label = if item.read { label.weak() } else { label.strong() };
+        let mut hover = item.url.clone();
+        if item.has_snapshot {
+            hover.push_str(&format!("\nSaved for offline · {} min read", item.word_count.div_ceil(WORDS_PER_MINUTE).max(1)));
+        }
+        let response = ui.small_button(label).on_hover_text(hover);
+        if response.clicked() {
+            action = Some(ReadingAction::Open(item.id.clone()));
+        }
+        response.context_menu(|ui| {
+            if item.has_snapshot && ui.button("Read offline copy").clicked() {
+                action = Some(ReadingAction::OpenSnapshot(item.id.clone()));
+                ui.close_menu();
+            }
+            if ui.button(if item.read { "Mark unread" } else { "Mark read" }).clicked() {
+                action = Some(ReadingAction::SetRead(item.id.clone(), !item.read));
+                ui.close_menu();
+            }
+            if ui.button("Remove").clicked() {
+                action = Some(ReadingAction::Remove(item.id.clone()));
+                ui.close_menu();
+            }
+        });
+    }
+    action
+}
+
+/// Draws a snapshot as a page; returns the original URL if the user asked for it.
+pub fn show_article(ui: &mut egui::Ui, article: &Article) -> Option<String> {
+    let mut open = None;
+    egui::ScrollArea::vertical().id_source("reader").show(ui, |ui| {
+        ui.set_max_width(680.0);
+        ui.heading(&article.title);
+        ui.horizontal(|ui| {
+            if let Some(byline) = &article.byline {
+                ui.label(egui::RichText::new(byline).weak());
+            }
+            if ui.link(egui::RichText::new(&article.url).weak().small()).clicked() {
+                open = Some(article.url.clone());
+            }
+        });
+        ui.label(egui::RichText::new("Offline copy").weak().small());
+        ui.add_space(16.0);
+        for block in &article.blocks {
+            match block {
+                Block::Heading(level, text) => {
+                    ui.add_space(8.0);
+                    let size = match level {
+                        1 => 24.0,
+                        2 => 20.0,
+                        _ => 17.0,
+                    };
+                    ui.label(egui::RichText::new(text).size(size).strong());
+                }
+                Block::Paragraph(text) => {
+                    ui.label(egui::RichText::new(text).size(15.0));
+                }
+                Block::ListItem(text) => {
+                    ui.label(egui::RichText::new(format!("  •  {}", text)).size(15.0));
+                }
+                Block::Quote(text) => {
+                    ui.horizontal(|ui| {
+                        ui.separator();
+                        ui.label(egui::RichText::new(text).size(15.0).italics());
+                    });
+                }
+                Block::Code(text) => {
+                    egui::Frame::group(ui.style()).show(ui, |ui| {
+                        ui.label(egui::RichText::new(text).monospace());
+                    });
+                }
+            }
+            ui.add_space(6.0);
+        }
+    });
+    open
+}
@@ -1,5 +1,6 @@
 use eframe::glow::{self, HasContext};
 use std::sync::Arc;
+use wave_core::spaces::lifecycle::Thumbnail;
 
 /// Handles the OpenGL texture sharing between the "Wave Engine" (Servo) and the UI.
 pub struct OffscreenRenderer {
@@ -89,6 +90,40 @@ impl OffscreenRenderer {
         }
     }
 
+    /// Reads back what the Engine last painted, scaled down to at most `max_width` pixels wide.
+    pub fn thumbnail(&self, max_width: usize) -> Thumbnail {
+        let (width, height) = (self.width.max(1) as usize, self.height.max(1) as usize);
+        let mut pixels = vec![0u8; width * height * 4];
+        unsafe {
+            self.gl.bind_framebuffer(glow::FRAMEBUFFER, Some(self.fbo_id));
+            self.gl.read_pixels(
+                0,
+                0,
+                width as i32,
+                height as i32,
+                glow::RGBA,
+                glow::UNSIGNED_BYTE,
+                glow::PixelPackData::Slice(&mut pixels),
+            );
+            self.gl.bind_framebuffer(glow::FRAMEBUFFER, None);
+        }
+
+        let scale = (width as f32 / max_width as f32).max(1.0);
+        let thumb_width = ((width as f32 / scale) as usize).max(1);
+        let thumb_height = ((height as f32 / scale) as usize).max(1);
+        let mut rgba = Vec::with_capacity(thumb_width * thumb_height * 4);
+        for y in 0..thumb_height {
+            // OpenGL rows run bottom to top
+            let src_y = height - 1 - ((y as f32 * scale) as usize).min(height - 1);
+            for x in 0..thumb_width {
+                let src_x = ((x as f32 * scale) as usize).min(width - 1);
+                let offset = (src_y * width + src_x) * 4;
+                rgba.extend_from_slice(&pixels[offset..offset + 4]);
+            }
+        }
+        Thumbnail { width: thumb_width, height: thumb_height, rgba }
+    }
+
     pub fn resize(&mut self, width: i32, height: i32) {
         if self.width == width && self.height == height {
             return;
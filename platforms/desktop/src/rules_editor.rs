@@ -0,0 +1,147 @@
+use eframe::egui;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use wave_core::shield::{WaveShield, user_rules::{self, CompiledRules}};
+
+pub const PAGE_URL: &str = "about:shield";
+
+/// The `about:shield` page: edits the user's rules file and re-checks it on every keystroke.
+pub struct RulesEditor {
+    path: PathBuf,
+    text: String,
+    compiled: CompiledRules,
+    // Whether `text` differs from what the shield is running
+    dirty: bool,
+    save_error: Option<String>,
+}
+
+impl RulesEditor {
+    /// Opens the rules file at `path` (a missing file is an empty rule set).
+    pub fn open(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        let text = std::fs::read_to_string(&path).unwrap_or_default();
+        let mut editor = Self {
+            path,
+            text,
+            compiled: CompiledRules::default(),
+            dirty: false,
+            save_error: None,
+        };
+        editor.recompile();
+        editor
+    }
+
+    pub fn compiled(&self) -> &CompiledRules {
+        &self.compiled
+    }
+
+    /// Adds a rule to the site's section of the file on disk and applies it right away. Edits
+    /// not saved yet stay unsaved, with the rule added to them as well.
+    pub fn add_site_rule(&mut self, site: &str, rule: &str, shield: &WaveShield) {
+        let saved = match std::fs::read_to_string(&self.path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => {
+                self.save_error = Some(format!("Could not read {}: {}", self.path.display(), e));
+                return;
+            }
+        };
+        let saved = user_rules::add_site_rule(&saved, site, rule);
+        if let Err(e) = write_rules(&self.path, &saved) {
+            self.save_error = Some(format!("Could not save {}: {}", self.path.display(), e));
+            return;
+        }
+        shield.load_user_rules(&compile(&saved, &self.path));
+        self.save_error = None;
+
+        self.text = if self.dirty { user_rules::add_site_rule(&self.text, site, rule) } else { saved };
+        self.recompile();
+    }
+
+    fn recompile(&mut self) {
+        self.compiled = compile(&self.text, &self.path);
+    }
+
+    fn save(&mut self, shield: &WaveShield) {
+        match write_rules(&self.path, &self.text) {
+            Ok(()) => {
+                shield.load_user_rules(&self.compiled);
+                self.dirty = false;
+                self.save_error = None;
+            }
+            Err(e) => self.save_error = Some(format!("Could not save {}: {}", self.path.display(), e)),
+        }
+    }
+
+    /// Shows the page. Returns a site the user turned cookie banner handling back on for.
+    pub fn show(&mut self, ui: &mut egui::Ui, shield: &WaveShield) -> Option<String> {
+        ui.heading("Shield rules");
+        ui.label(egui::RichText::new(self.path.display().to_string()).weak());
+        ui.add_space(10.0);
+
+        let mut handle_again = None;
+        let opt_outs: Vec<&str> = shield.cookie_banner_opt_outs().collect();
+        if !opt_outs.is_empty() {
+            ui.horizontal_wrapped(|ui| {
+                ui.label("Cookie banners left alone on:");
+                for site in opt_outs {
+                    if ui.small_button(format!("{} ✖", site)).on_hover_text("Reject its cookie banners again").clicked() {
+                        handle_again = Some(site.to_string());
+                    }
+                }
+            });
+            ui.add_space(10.0);
+        }
+
+        ui.horizontal(|ui| {
+            if ui.add_enabled(self.dirty, egui::Button::new(" Save and apply ")).clicked() {
+                self.save(shield);
+            }
+            ui.label(format!("{} rules", self.compiled.filters.len()));
+            if let Some(error) = &self.save_error {
+                ui.colored_label(egui::Color32::from_rgb(243, 139, 168), error);
+            }
+        });
+        ui.add_space(10.0);
+
+        // Diagnostics stay visible below the editor however long the file gets
+        let diagnostics_height = if self.compiled.has_errors() { 120.0 } else { 0.0 };
+        egui::ScrollArea::vertical()
+            .id_source("rules_editor_text")
+            .max_height(ui.available_height() - diagnostics_height)
+            .show(ui, |ui| {
+                let response = ui.add(
+                    egui::TextEdit::multiline(&mut self.text)
+                        .code_editor()
+                        .desired_width(f32::INFINITY)
+                        .desired_rows(20),
+                );
+                if response.changed() {
+                    self.dirty = true;
+                    self.recompile();
+                }
+            });
+
+        if self.compiled.has_errors() {
+            ui.separator();
+            egui::ScrollArea::vertical().id_source("rules_editor_diagnostics").show(ui, |ui| {
+                for diagnostic in &self.compiled.diagnostics {
+                    ui.colored_label(egui::Color32::from_rgb(243, 139, 168), diagnostic.to_string());
+                }
+            });
+        }
+        handle_again
+    }
+}
+
+fn compile(text: &str, path: &Path) -> CompiledRules {
+    user_rules::compile(text, path, &|p| std::fs::read_to_string(p))
+}
+
+fn write_rules(path: &Path, text: &str) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(path, text)
+}
@@ -0,0 +1,71 @@
+use eframe::egui;
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+use wave_core::shield::{WaveShield, safe_browsing::UPDATE_INTERVAL};
+
+const UPDATE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Fetches Safe Browsing list updates in the background and applies them to the shield, the
+/// first right away and then every [`UPDATE_INTERVAL`].
+pub struct SafeBrowsingUpdater {
+    // Update API endpoint; without one the lists stay as they are
+    url: Option<String>,
+    sender: mpsc::Sender<Result<String, String>>,
+    receiver: mpsc::Receiver<Result<String, String>>,
+    in_flight: bool,
+    next_update: Instant,
+}
+
+impl SafeBrowsingUpdater {
+    pub fn new(url: Option<String>) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        Self {
+            url,
+            sender,
+            receiver,
+            in_flight: false,
+            next_update: Instant::now(),
+        }
+    }
+
+    /// Applies a finished update, and starts the next one once it is due.
+    pub fn poll(&mut self, ctx: &egui::Context, shield: &WaveShield) {
+        let Some(url) = &self.url else {
+            return;
+        };
+        if let Ok(response) = self.receiver.try_recv() {
+            self.in_flight = false;
+            match response.and_then(|body| shield.apply_safe_browsing_update(&body).map_err(|e| e.to_string())) {
+                Ok(()) => log::info!("Safe Browsing lists updated"),
+                Err(e) => log::warn!("Safe Browsing update failed: {}", e),
+            }
+        }
+        if self.in_flight {
+            return;
+        }
+        let now = Instant::now();
+        if now < self.next_update {
+            ctx.request_repaint_after(self.next_update - now);
+            return;
+        }
+
+        self.in_flight = true;
+        self.next_update = now + UPDATE_INTERVAL;
+        let url = url.clone();
+        let body = shield.safe_browsing_update_request();
+        let sender = self.sender.clone();
+        let ctx = ctx.clone();
+        std::thread::spawn(move || {
+            // The URL carries the API key, so it isn't logged
+            let response = ureq::post(&url)
+                .timeout(UPDATE_TIMEOUT)
+                .set("Content-Type", "application/json")
+                .send_string(&body)
+                .map_err(|e| e.to_string())
+                .and_then(|response| response.into_string().map_err(|e| e.to_string()));
+            let _ = sender.send(response);
+            ctx.request_repaint();
+        });
+    }
+}